@@ -6,12 +6,14 @@
 // for Pandora Core AG
 
 #![allow(clippy::result_large_err)]
+use std::collections::BTreeMap;
 use std::fs;
 use std::fs::File;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::process::exit;
 
-use aluasm::linker::LibManager;
+use aluasm::issues::{Diagnostic, Linking, MessageFormat, Severity, Stage};
+use aluasm::linker::{enumerate_libs, LibManager};
 use aluasm::module::Module;
 use aluasm::product::Product;
 use aluasm::{BuildError, MainError};
@@ -34,6 +36,11 @@ pub struct Args {
     #[clap(short, long, global = true, parse(from_occurrences))]
     pub verbose: u8,
 
+    /// Format of the diagnostic messages: `human` for colored text or `json` for one JSON object
+    /// per diagnostic printed to the standard output
+    #[clap(long, global = true, default_value = "human")]
+    pub message_format: MessageFormat,
+
     /// Output executable binary
     #[clap(long, global = true, conflicts_with = "lib")]
     pub bin: bool,
@@ -42,6 +49,10 @@ pub struct Args {
     #[clap(long, global = true)]
     pub lib: bool,
 
+    /// Removes debug information, taken from the object files, from the product
+    #[clap(long, global = true)]
+    pub strip: bool,
+
     /// Build directory with object files. Defaults to `{build-dir}/objects` (see --build-dir
     /// argument)
     #[clap(short = 'O', long, global = true, default_value = "${ALU_BUILD_DIR}/objects")]
@@ -72,10 +83,10 @@ pub struct Args {
     #[clap(short, long = "org")]
     pub org_name: String,
 
-    /// Object file name to link. The object file is looked up at --obj-dir path and extension is
-    /// automatically added if necessary.
-    #[clap(required = true)]
-    pub file: String,
+    /// Object file names to link. The object files are looked up at --obj-dir path and extension
+    /// is automatically added if necessary. If no files are given, all object files from
+    /// --obj-dir are linked together.
+    pub files: Vec<String>,
 }
 
 impl Args {
@@ -99,9 +110,12 @@ impl Args {
 }
 
 fn main() {
-    let args: Args = Clap::parse();
+    let args = Args::parse().processed();
 
-    link(&args.processed()).unwrap_or_else(|err| {
+    link(&args).unwrap_or_else(|err| {
+        if let Some(diagnostic) = fatal_diagnostic(&err) {
+            emit(diagnostic, args.message_format);
+        }
         eprintln!("{}\n", err);
         exit(1)
     });
@@ -114,14 +128,26 @@ fn link(args: &Args) -> Result<(), MainError> {
         details: Box::new(err),
     })?;
 
-    let product_name = args.product_name.as_ref().cloned().unwrap_or(args.file.to_owned());
+    let product_name = match (&args.product_name, args.files.as_slice()) {
+        (Some(name), _) => name.clone(),
+        (None, [file]) => file.clone(),
+        (None, _) => return Err(BuildError::ProductNameRequired.into()),
+    };
     let org_name = args.org_name.clone();
     eprintln!("\x1B[1;32m  Linking\x1B[0m {}\x1B[1;34m@{}\x1B[0m", product_name, org_name);
 
-    let mut path = args.obj_dir.clone();
-    path.push(&args.file);
-    path.set_extension("ao");
-    let module = read_object(&path, args)?;
+    let modules = if args.files.is_empty() {
+        read_all_objects(args)?
+    } else {
+        let mut modules = BTreeMap::new();
+        for file in &args.files {
+            let mut path = args.obj_dir.clone();
+            path.push(file);
+            path.set_extension("ao");
+            modules.insert(file.clone(), read_object(&path, args)?);
+        }
+        modules
+    };
 
     let mut libs = enumerate_libs(&args.target_dir)?;
     for path in &args.lib_dirs {
@@ -130,21 +156,26 @@ fn link(args: &Args) -> Result<(), MainError> {
     libs.extend(args.libs.iter().cloned());
     let mut manager = LibManager::with(libs)?;
 
-    let (product, issues) = if args.bin {
-        module.link_bin(product_name.clone(), org_name.clone(), &mut manager)?
+    let (mut product, issues) = if args.bin {
+        Module::link_bin(&modules, product_name.clone(), org_name.clone(), &mut manager)?
     } else {
-        module.link_lib(product_name.clone(), org_name.clone(), &mut manager)?
+        Module::link_lib(&modules, product_name.clone(), org_name.clone(), &mut manager)?
     };
 
+    let report = print_json(issues.render(args.message_format), args.message_format);
     if issues.has_errors() {
         return Err(MainError::Linking(
             product_name,
             issues.count_errors(),
             issues.count_warnings(),
-            issues.to_string(),
+            report,
         ));
     }
-    eprint!("{}", issues);
+    eprint!("{}", report);
+
+    if args.strip {
+        product.strip_debug();
+    }
 
     if args.verbose >= 2 {
         eprintln!("\x1B[0;35m Printing\x1B[0m product dump:");
@@ -172,40 +203,51 @@ fn link(args: &Args) -> Result<(), MainError> {
     Ok(())
 }
 
-fn enumerate_libs(lib_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, BuildError> {
-    let lib_dir = lib_dir.as_ref();
-    let lib_dir_name = lib_dir.to_string_lossy().to_string();
-    if lib_dir.is_file() {
-        return Err(BuildError::LibDirIsFile(lib_dir_name));
+/// Prints diagnostics rendered in JSON format to the standard output, returning human-readable
+/// report which is left to be printed to the standard error
+fn print_json(report: String, format: MessageFormat) -> String {
+    match format {
+        MessageFormat::Human => report,
+        MessageFormat::Json => {
+            print!("{}", report);
+            String::new()
+        }
     }
+}
 
-    let mut vec = vec![];
-    for entry in fs::read_dir(lib_dir)
-        .map_err(|err| BuildError::ObjDirFail(lib_dir_name.clone(), err.into()))?
-    {
-        let path =
-            entry.map_err(|err| BuildError::LibDirFail(lib_dir_name.clone(), err.into()))?.path();
-        if path.is_dir() {
-            continue;
-        }
-        if path.extension().unwrap_or_default().to_string_lossy() != Product::LIB_EXTENSION {
-            continue;
-        }
-        vec.push(path);
+/// Prints diagnostic of an error which has stopped the build, if JSON format is requested
+fn emit(diagnostic: Diagnostic, format: MessageFormat) {
+    if format == MessageFormat::Json {
+        println!("{}", diagnostic.to_json());
     }
-    Ok(vec)
 }
 
-// TODO: Reserved for the future where linked will be able to use multiple modules for single
-//       product
-#[allow(dead_code)]
-fn read_all_objects(args: &Args) -> Result<Vec<Module>, MainError> {
+/// Describes errors which have stopped linking before any linker issue was reported
+fn fatal_diagnostic(err: &MainError) -> Option<Diagnostic> {
+    let (errno, message) = match err {
+        MainError::Build(err) => (0, err.to_string()),
+        MainError::Module(err, file) => {
+            (0, format!("broken binary data in module `{}`: {}", file, err))
+        }
+        MainError::Linker(err, errno) => (*errno, format!("internal linker error: {}", err)),
+        _ => return None,
+    };
+    Some(Diagnostic {
+        errno,
+        severity: Severity::Error,
+        stage: Linking::NAME,
+        message,
+        location: None,
+    })
+}
+
+fn read_all_objects(args: &Args) -> Result<BTreeMap<String, Module>, MainError> {
     let obj_dir = args.obj_dir.to_string_lossy().to_string();
     if args.obj_dir.is_file() {
         Err(BuildError::ObjDirIsFile(obj_dir.clone()))?;
     }
 
-    let mut vec = vec![];
+    let mut map = BTreeMap::new();
     for entry in fs::read_dir(&args.obj_dir)
         .map_err(|err| BuildError::ObjDirFail(obj_dir.clone(), err.into()))?
     {
@@ -213,10 +255,18 @@ fn read_all_objects(args: &Args) -> Result<Vec<Module>, MainError> {
         if path.is_dir() {
             continue;
         }
-        vec.push(read_object(&path, args)?);
+        if path.extension().unwrap_or_default().to_string_lossy() != "ao" {
+            continue;
+        }
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        map.insert(name, read_object(&path, args)?);
+    }
+
+    if map.is_empty() {
+        Err(BuildError::NoObjects(obj_dir))?;
     }
 
-    Ok(vec)
+    Ok(map)
 }
 
 fn read_object(path: &PathBuf, _args: &Args) -> Result<Module, MainError> {
@@ -0,0 +1,359 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Language server for AluVM assembly files, talking to the editor over stdio.
+//!
+//! Positions are converted between LSP line/character pairs, where characters are counted in
+//! UTF-16 code units, and byte offsets in the document text.
+
+#![allow(clippy::result_large_err)]
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use aluasm::ast::{Operand, Operator, Program, Statement};
+use aluasm::isa::ExtOp;
+use aluasm::issues::{self, Diagnostic, Issues, Location, Severity};
+use aluasm::module::CallTable;
+use aluasm::plugins::{CompileCtx, IsaTable};
+use aluasm::source::SourceMap;
+use aluasm::SourceError;
+use pest::error::InputLocation;
+use pest::Span;
+use serde_json::{json, Value};
+
+const DOCUMENT_SYNC_FULL: u8 = 1;
+const COMPLETION_KEYWORD: u8 = 14;
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+fn main() {
+    let table = IsaTable::<ExtOp>::default();
+    let mut documents = BTreeMap::<String, String>::new();
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    while let Some(message) = read_message(&mut input) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_owned();
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": DOCUMENT_SYNC_FULL,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "alu-lsp", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => Value::Null,
+            "exit" => return,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                documents.insert(uri.clone(), text.to_owned());
+                publish_diagnostics(&uri, text, &table);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or_default();
+                documents.insert(uri.clone(), text.to_owned());
+                publish_diagnostics(&uri, text, &table);
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }));
+                continue;
+            }
+            "textDocument/definition" => documents
+                .get(&uri)
+                .and_then(|text| definition(&uri, text, &params["position"]))
+                .unwrap_or_default(),
+            "textDocument/hover" => documents
+                .get(&uri)
+                .and_then(|text| hover(&uri, text, &params["position"], &table))
+                .unwrap_or_default(),
+            "textDocument/completion" => completion(&table),
+            _ => {
+                if let Some(id) = id.filter(|_| !method.is_empty()) {
+                    send(json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": METHOD_NOT_FOUND, "message": "method not found" },
+                    }));
+                }
+                continue;
+            }
+        };
+
+        if let Some(id) = id {
+            send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+        }
+    }
+}
+
+/// Reads JSON-RPC message framed with `Content-Length` header. Returns `None` when the input is
+/// closed; messages which are not valid JSON are returned as `null` value.
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            len = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0u8; len?];
+    input.read_exact(&mut body).ok()?;
+    Some(serde_json::from_slice(&body).unwrap_or_default())
+}
+
+fn send(message: Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout();
+    let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = stdout.flush();
+}
+
+fn notify(method: &str, params: Value) {
+    send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+}
+
+/// Analyzes document text and runs `f` over the resulting program
+fn with_program<T, F>(uri: &str, text: &str, f: F) -> Option<T>
+where
+    F: for<'i> FnOnce(&'i Program<'i>) -> Option<T>,
+{
+    let sources = SourceMap::with(uri_to_path(uri).display(), text).ok()?;
+    let (program, _) = Program::analyze(&sources).ok()?;
+    f(&program)
+}
+
+fn publish_diagnostics(uri: &str, text: &str, table: &IsaTable<ExtOp>) {
+    let name = uri_to_path(uri).display().to_string();
+    let diagnostics = match SourceMap::with(&name, text) {
+        Err(SourceError::Parser(file, err)) if file == name => {
+            let pos = match err.location {
+                InputLocation::Pos(pos) | InputLocation::Span((pos, _)) => pos,
+            };
+            vec![json!({
+                "range": range(text, pos..pos),
+                "severity": SEVERITY_ERROR,
+                "source": "aluasm",
+                "message": err.variant.message(),
+            })]
+        }
+        Err(err) => vec![fatal(text, err)],
+        Ok(sources) => match Program::analyze_with(&sources, table) {
+            Err(err) => vec![fatal(text, err)],
+            Ok((program, issues)) => {
+                let mut diagnostics = issues.diagnostics();
+                if !issues.has_errors() {
+                    match program.compile_with(table, &mut None) {
+                        Ok((_, issues)) => diagnostics.extend(issues.diagnostics()),
+                        Err(err) => return publish(uri, vec![fatal(text, err)]),
+                    }
+                }
+                diagnostics
+                    .iter()
+                    .map(|diagnostic| lsp_diagnostic(diagnostic, &name, text))
+                    .collect()
+            }
+        },
+    };
+    publish(uri, diagnostics);
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) {
+    notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }));
+}
+
+/// Reports internal error, which has no source location, at the beginning of the document
+fn fatal(text: &str, err: impl ToString) -> Value {
+    let message = err.to_string();
+    json!({
+        "range": range(text, 0..0),
+        "severity": SEVERITY_ERROR,
+        "source": "aluasm",
+        "message": message.lines().next().unwrap_or_default(),
+    })
+}
+
+/// Converts diagnostic into LSP representation. Issues located in the included files are shown at
+/// the site of the macro invocation within the document, if any, or at its beginning otherwise.
+fn lsp_diagnostic(diagnostic: &Diagnostic, name: &str, text: &str) -> Value {
+    let mut location = diagnostic.location.as_ref();
+    while let Some(loc) = location.filter(|loc| loc.file.as_deref() != Some(name)) {
+        location = loc.expansion.as_deref();
+    }
+    json!({
+        "range": range(text, location.map(|loc: &Location| loc.range.clone()).unwrap_or(0..0)),
+        "severity": match diagnostic.severity {
+            Severity::Error => SEVERITY_ERROR,
+            Severity::Warning => SEVERITY_WARNING,
+        },
+        "code": format!("E{:04}", diagnostic.errno),
+        "source": "aluasm",
+        "message": diagnostic.message,
+    })
+}
+
+fn definition(uri: &str, text: &str, position: &Value) -> Option<Value> {
+    with_program(uri, text, |program| {
+        let (symbol, _) = program.symbol_at(SourceMap::ROOT, offset(text, position)?)?;
+        let span = program.definition(&symbol)?;
+        let file = program.sources.locate(&span)?;
+        let uri = if ptr::eq(file, program.sources.file(SourceMap::ROOT)) {
+            uri.to_owned()
+        } else {
+            path_to_uri(&file.path)
+        };
+        Some(json!({ "uri": uri, "range": range(&file.text, span_range(&span)) }))
+    })
+}
+
+fn hover(uri: &str, text: &str, position: &Value, table: &IsaTable<ExtOp>) -> Option<Value> {
+    with_program(uri, text, |program| {
+        let (_, statement) = program.statement_at(SourceMap::ROOT, offset(text, position)?)?;
+        let value =
+            format!("{}\n\n{}", signature(statement, table), compiled(program, statement, table));
+        Some(json!({
+            "contents": { "kind": "markdown", "value": value },
+            "range": range(text, span_range(&statement.span)),
+        }))
+    })
+}
+
+/// Describes operator of the statement with the operands it takes
+fn signature(statement: &Statement, table: &IsaTable<ExtOp>) -> String {
+    let operator = statement.operator.0;
+    let operands =
+        statement.operands.iter().map(Operand::description).collect::<Vec<_>>().join(", ");
+    let mut s =
+        format!("`{}` ({})", operator, if operands.is_empty() { "no operands" } else { &operands });
+    if let (Some(isa), Some(mnemonic)) = (table.isa_id(operator), table.mnemonic(operator)) {
+        let kinds = mnemonic.operands.iter().map(|kind| kind.description()).collect::<Vec<_>>();
+        match kinds.as_slice() {
+            [] => s.push_str(&format!("\n\n{} instruction without operands", isa)),
+            kinds => s.push_str(&format!("\n\n{} instruction taking {}", isa, kinds.join(", "))),
+        }
+        if !mnemonic.flags.is_empty() {
+            s.push_str(&format!("; flags: `{}`", mnemonic.flags));
+        }
+    }
+    s
+}
+
+/// Compiles single statement, showing the resulting instruction or the first compilation error
+fn compiled<'i>(
+    program: &'i Program<'i>,
+    statement: &'i Statement<'i>,
+    table: &IsaTable<ExtOp>,
+) -> String {
+    let mut call_table = CallTable::default();
+    let mut issues = Issues::<issues::Compile>::default();
+    let mut ctx = CompileCtx { program, call_table: &mut call_table, issues: &mut issues };
+    match table.compile(statement, &mut ctx) {
+        Ok(_) if issues.has_errors() => format!(
+            "Does not compile: {}",
+            issues
+                .diagnostics()
+                .first()
+                .map(|diagnostic| diagnostic.message.as_str())
+                .unwrap_or("")
+        ),
+        Ok(instr) => format!("Compiles to `{}`", instr),
+        Err(err) => format!("Does not compile: {}", err),
+    }
+}
+
+fn completion(table: &IsaTable<ExtOp>) -> Value {
+    Operator::all()
+        .iter()
+        .map(|operator| {
+            json!({
+                "label": operator.to_string(),
+                "kind": COMPLETION_KEYWORD,
+                "detail": table.isa_id(*operator),
+            })
+        })
+        .collect()
+}
+
+/// Converts LSP position into byte offset in the document text
+fn offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let start = match line {
+        0 => 0,
+        line => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let line = text[start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    let column = line
+        .char_indices()
+        .find(|(_, c)| {
+            units += c.len_utf16();
+            units > character
+        })
+        .map(|(pos, _)| pos)
+        .unwrap_or(line.len());
+    Some(start + column)
+}
+
+/// Converts byte range in the document text into LSP range
+fn range(text: &str, range: Range<usize>) -> Value {
+    let position = |offset: usize| {
+        let before = &text[..offset.min(text.len())];
+        let line = before.matches('\n').count();
+        let character = before.rsplit('\n').next().unwrap_or_default().encode_utf16().count();
+        json!({ "line": line, "character": character })
+    };
+    json!({ "start": position(range.start), "end": position(range.end) })
+}
+
+/// Converts `file://` URI into a file system path, decoding percent-encoded characters
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut pos = 0;
+    while pos < path.len() {
+        let byte = path.as_bytes()[pos];
+        if byte == b'%' {
+            if let Some(decoded) =
+                path.get(pos + 1..pos + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                bytes.push(decoded);
+                pos += 3;
+                continue;
+            }
+        }
+        bytes.push(byte);
+        pos += 1;
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn path_to_uri(path: &Path) -> String { format!("file://{}", path.display()) }
+
+fn span_range(span: &Span) -> Range<usize> { span.start()..span.end() }
@@ -6,20 +6,25 @@
 // for Pandora Core AG
 
 #![allow(clippy::result_large_err)]
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
 use std::path::PathBuf;
 use std::process::exit;
 
 use aluasm::ast::Program;
-use aluasm::parser::{Parser, Rule};
-use aluasm::{BuildError, LexerError, MainError};
-use aluvm::data::encoding::Encode;
-use aluvm::isa::Instr;
-use clap::{AppSettings, Parser as Clap};
-use pest::Parser as ParserTrait;
+use aluasm::debug::SrcLoc;
+use aluasm::isa::Instr;
+use aluasm::issues::{Diagnostic, MessageFormat};
+use aluasm::linker::LibManager;
+use aluasm::module::Module;
+use aluasm::product::Product;
+use aluasm::source::SourceMap;
+use aluasm::tester::TestResult;
+use aluasm::{formatter, BuildError, MainError};
+use aluvm::data::encoding::{Decode, Encode};
+use clap::{AppSettings, Parser as Clap, Subcommand};
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Clap)]
 #[clap(
@@ -41,9 +46,20 @@ pub struct Args {
     #[clap(long, global = true)]
     pub dump: Option<PathBuf>,
 
-    /// Tests disassembly of the generated library
-    #[clap(long, global = true)]
-    pub test: bool,
+    /// Adds debug information section, mapping code offsets to the source code, to the object
+    /// files
+    #[clap(short = 'g', long, global = true)]
+    pub debug: bool,
+
+    /// Tests that the generated module can be decompiled back into the source code which
+    /// compiles into the same module
+    #[clap(long, global = true, alias = "test")]
+    pub roundtrip: bool,
+
+    /// Format of the diagnostic messages: `human` for colored text or `json` for one JSON object
+    /// per diagnostic printed to the standard output
+    #[clap(long, global = true, default_value = "human")]
+    pub message_format: MessageFormat,
 
     /// Directory to output object files into
     #[clap(short, long, global = true, default_value = "./build/objects")]
@@ -51,11 +67,56 @@ pub struct Args {
 
     /// List of source files to compile
     pub files: Vec<PathBuf>,
+
+    /// Command to run instead of compiling the source files
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum Command {
+    /// Formats source files into the canonical layout, rewriting them in place
+    Fmt {
+        /// Checks that the files are formatted, failing if they are not, without changing them
+        #[clap(long)]
+        check: bool,
+
+        /// List of source files to format
+        files: Vec<PathBuf>,
+    },
+
+    /// Decompiles object file (`.ao`) or linked library or binary into the source code, printing
+    /// it to the standard output
+    Disasm {
+        /// Libraries used to resolve names of the routines called by a linked product
+        #[clap(short = 'l', long = "lib")]
+        libs: Vec<PathBuf>,
+
+        /// Object, library or binary file to decompile
+        file: PathBuf,
+    },
+
+    /// Compiles source files together with the tests defined in their `.TEST` segments and runs
+    /// each test, reporting which of them have failed
+    Test {
+        /// Libraries providing routines called by the tested code
+        #[clap(short = 'l', long = "lib")]
+        libs: Vec<PathBuf>,
+
+        /// List of source files to test
+        files: Vec<PathBuf>,
+    },
 }
 
 fn main() {
-    let args = Clap::parse();
-    compile(&args).unwrap_or_else(|err| {
+    let args: Args = Clap::parse();
+    let result = match &args.command {
+        Some(Command::Fmt { check, files }) => format(files, *check),
+        Some(Command::Disasm { libs, file }) => disasm(file, libs),
+        Some(Command::Test { libs, files }) => test(files, libs, &args),
+        None => compile(&args),
+    };
+    result.unwrap_or_else(|err| {
         eprintln!("{}\n", err);
         exit(1)
     });
@@ -76,6 +137,181 @@ fn compile(args: &Args) -> Result<(), MainError> {
     Ok(())
 }
 
+fn format(files: &[PathBuf], check: bool) -> Result<(), MainError> {
+    let mut unformatted = 0;
+    for file in files {
+        let file_name = file.display().to_string();
+        let text = fs::read_to_string(file).map_err(|err| BuildError::FileNoAccess {
+            file: file_name.clone(),
+            details: Box::new(err),
+        })?;
+        let formatted = formatter::format(&file_name, &text)?;
+        if formatted == text {
+            continue;
+        }
+        if check {
+            eprintln!("\x1B[1;33mUnformatted\x1B[0m {}", file_name);
+            unformatted += 1;
+        } else {
+            eprintln!("\x1B[1;32m Formatting\x1B[0m {}", file_name);
+            fs::write(file, formatted).map_err(|err| BuildError::SourceFileWrite {
+                file: file_name,
+                details: Box::new(err),
+            })?;
+        }
+    }
+
+    if unformatted > 0 {
+        return Err(BuildError::Unformatted(unformatted).into());
+    }
+    Ok(())
+}
+
+fn disasm(file: &PathBuf, libs: &[PathBuf]) -> Result<(), MainError> {
+    let file_name = file.display().to_string();
+    let fd = File::open(file).map_err(|err| BuildError::FileNotFound {
+        file: file_name.clone(),
+        details: Box::new(err),
+    })?;
+
+    let source = if file.extension() == Some(OsStr::new("ao")) {
+        let module = Module::decode(fd).map_err(|err| MainError::Module(err, file_name.clone()))?;
+        module.decompile()
+    } else {
+        let product = Product::decode(fd)
+            .map_err(|err| BuildError::ProductIncorrectData(file_name.clone(), err))?;
+        let mut lib_man = LibManager::with(libs.to_vec())?;
+        product.decompile(&mut lib_man)
+    }
+    .map_err(|details| BuildError::Decompiling { file: file_name, details })?;
+
+    print!("{}", source);
+    Ok(())
+}
+
+fn test(files: &[PathBuf], libs: &[PathBuf], args: &Args) -> Result<(), MainError> {
+    let mut lib_man = LibManager::with(libs.to_vec())?;
+    for file in files {
+        let file_name = file.display().to_string();
+        let sources = SourceMap::load(file).map_err(|err| {
+            emit(err.diagnostic(), args.message_format);
+            err
+        })?;
+        let (program, issues) = Program::analyze(&sources).map_err(|err| {
+            emit(err.diagnostic(), args.message_format);
+            err
+        })?;
+        let report = print_json(issues.render(args.message_format), args.message_format);
+        if issues.has_errors() {
+            return Err(MainError::Syntax(
+                file_name,
+                issues.count_errors(),
+                issues.count_warnings(),
+                report,
+            ));
+        }
+
+        let (module, issues) = program.compile_tests()?;
+        let report = print_json(issues.render(args.message_format), args.message_format);
+        if issues.has_errors() {
+            return Err(MainError::Compile(
+                file_name,
+                issues.count_errors(),
+                issues.count_warnings(),
+                report,
+            ));
+        }
+
+        let name = file.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let mut modules = BTreeMap::new();
+        modules.insert(name.clone(), module);
+        let (product, issues) =
+            Module::link_lib(&modules, name.clone(), "test".to_owned(), &mut lib_man)?;
+        if issues.has_errors() {
+            return Err(MainError::Linking(
+                name,
+                issues.count_errors(),
+                issues.count_warnings(),
+                print_json(issues.render(args.message_format), args.message_format),
+            ));
+        }
+        let lib = match product {
+            Product::Lib(lib) => lib,
+            Product::Bin(_) => unreachable!("linking library produces binary"),
+        };
+
+        let tests = lib.tests().map(str::to_owned).collect::<Vec<_>>();
+        eprintln!("\x1B[1;32m  Running\x1B[0m {} test(s) from {}", tests.len(), file_name);
+        let mut failed = 0;
+        for test in tests {
+            let result = lib.run_test(&test, &mut lib_man)?;
+            if result.is_passed() {
+                println!("test {} ... \x1B[1;32mok\x1B[0m", test);
+                continue;
+            }
+            failed += 1;
+            println!("test {} ... \x1B[1;31mFAILED\x1B[0m", test);
+            let (statement, at) = match &result {
+                TestResult::Passed => continue,
+                TestResult::Assertion { statement } | TestResult::StepLimit { statement } => {
+                    (statement, None)
+                }
+                TestResult::Execution { statement, loc, .. } => (statement, loc.as_ref()),
+            };
+            println!("\x1B[1;31merror:\x1B[0m {}", result);
+            print_loc(&sources, statement);
+            if let Some(loc) = at {
+                println!("  failed instruction:");
+                print_loc(&sources, loc);
+            }
+        }
+
+        if failed > 0 {
+            return Err(BuildError::TestsFailed(file_name, failed).into());
+        }
+    }
+    Ok(())
+}
+
+/// Prints diagnostics rendered in JSON format to the standard output, returning human-readable
+/// report which is left to be printed to the standard error
+fn print_json(report: String, format: MessageFormat) -> String {
+    match format {
+        MessageFormat::Human => report,
+        MessageFormat::Json => {
+            print!("{}", report);
+            String::new()
+        }
+    }
+}
+
+/// Prints diagnostic of an error which has stopped the build, if JSON format is requested
+fn emit(diagnostic: Diagnostic, format: MessageFormat) {
+    if format == MessageFormat::Json {
+        println!("{}", diagnostic.to_json());
+    }
+}
+
+/// Prints source code line pointed by the location
+fn print_loc(sources: &SourceMap, loc: &SrcLoc) {
+    let text = sources
+        .files()
+        .find(|file| file.name == loc.file)
+        .and_then(|file| file.text.lines().nth(loc.line.saturating_sub(1) as usize))
+        .unwrap_or_default();
+    let width = loc.line.to_string().len();
+    println!("{:width$}\x1B[1;34m-->\x1B[0m {}", "", loc, width = width);
+    println!("{:width$} \x1B[1;34m|\x1B[0m", "", width = width);
+    println!("\x1B[1;34m{} |\x1B[0m {}", loc.line, text.trim_end());
+    println!(
+        "{:width$} \x1B[1;34m|\x1B[0m {:col$}\x1B[1;31m^\x1B[0m\n",
+        "",
+        "",
+        width = width,
+        col = loc.col.saturating_sub(1) as usize
+    );
+}
+
 fn compile_file(file: &PathBuf, args: &Args) -> Result<(), MainError> {
     let file_name =
         file.file_name().unwrap_or(OsStr::new("<noname>")).to_string_lossy().to_string();
@@ -104,41 +340,38 @@ fn compile_file(file: &PathBuf, args: &Args) -> Result<(), MainError> {
         file.canonicalize().unwrap_or_default().display()
     );
 
-    let mut s = String::new();
-    let mut fd = File::open(file).map_err(|err| BuildError::FileNotFound {
-        file: file_name.clone(),
-        details: Box::new(err),
+    let sources = SourceMap::load(file).map_err(|err| {
+        emit(err.diagnostic(), args.message_format);
+        err
     })?;
-    fd.read_to_string(&mut s).map_err(|err| BuildError::FileNoAccess {
-        file: file_name.clone(),
-        details: Box::new(err),
+    let (program, issues) = Program::analyze(&sources).map_err(|err| {
+        emit(err.diagnostic(), args.message_format);
+        err
     })?;
 
-    let pairs = Parser::parse(Rule::program, &s)
-        .map_err(|err| MainError::Parser(file_name.clone(), err))?;
-    let (program, issues) =
-        Program::analyze(pairs.into_iter().next().ok_or(LexerError::ProgramAbsent)?)?;
-
+    let report = print_json(issues.render(args.message_format), args.message_format);
     if issues.has_errors() {
         return Err(MainError::Syntax(
             file_name,
             issues.count_errors(),
             issues.count_warnings(),
-            issues.to_string(),
+            report,
         ));
     }
-    eprint!("{}", issues);
+    eprint!("{}", report);
 
-    let (module, issues) = program.compile(&mut dump)?;
+    let (module, issues) =
+        if args.debug { program.compile_debug(&mut dump)? } else { program.compile(&mut dump)? };
+    let report = print_json(issues.render(args.message_format), args.message_format);
     if issues.has_errors() {
         return Err(MainError::Compile(
             file_name,
             issues.count_errors(),
             issues.count_warnings(),
-            issues.to_string(),
+            report,
         ));
     }
-    eprint!("{}", issues);
+    eprint!("{}", report);
 
     let mut dest = args.output.clone();
     dest.push(file.file_name().unwrap_or_default());
@@ -158,11 +391,11 @@ fn compile_file(file: &PathBuf, args: &Args) -> Result<(), MainError> {
         println!("{}\n", module);
     }
 
-    if args.test {
+    if args.roundtrip {
         let code = module
             .as_static_lib()
             .disassemble::<Instr>()
-            .map_err(|_| BuildError::Disassembling { file: dest_name })?;
+            .map_err(|_| BuildError::Disassembling { file: dest_name.clone() })?;
 
         if args.verbose >= 2 {
             eprintln!("\x1B[0;35m Printing\x1B[0m module disassembly:");
@@ -170,6 +403,26 @@ fn compile_file(file: &PathBuf, args: &Args) -> Result<(), MainError> {
                 println!("\t\t{}", instr);
             }
         }
+
+        let source = module
+            .decompile()
+            .map_err(|details| BuildError::Decompiling { file: dest_name.clone(), details })?;
+        if args.verbose >= 2 {
+            eprintln!("\x1B[0;35m Printing\x1B[0m decompiled source:");
+            println!("{}", source);
+        }
+
+        let sources = SourceMap::with(&dest_name, source)?;
+        let (program, issues) = Program::analyze(&sources)?;
+        if issues.has_errors() {
+            return Err(BuildError::DecompileMismatch(dest_name).into());
+        }
+        let (recompiled, issues) = program.compile(&mut None)?;
+        let mut stripped = module.clone();
+        stripped.strip_debug();
+        if issues.has_errors() || recompiled != stripped {
+            return Err(BuildError::DecompileMismatch(dest_name).into());
+        }
     }
 
     Ok(())
@@ -0,0 +1,310 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+#![allow(clippy::result_large_err)]
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process::exit;
+
+use aluasm::ast::{Operand, Program};
+use aluasm::debug::DebugInfo;
+use aluasm::debugger::{Debugger, Halt};
+use aluasm::isa::Context;
+use aluasm::linker::{enumerate_libs, LibManager};
+use aluasm::module::Module;
+use aluasm::product::Product;
+use aluasm::source::SourceMap;
+use aluasm::{BuildError, MainError};
+use aluvm::data::Number;
+use aluvm::library::{LibId, LibSite};
+use aluvm::reg::RegAll;
+use clap::{AppSettings, Parser as Clap};
+
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Clap)]
+#[clap(
+    name = "aludbg",
+    bin_name = "aludbg",
+    author,
+    version,
+    about,
+    setting = AppSettings::ColoredHelp
+)]
+pub struct Args {
+    /// Directories containing library files
+    #[clap(short = 'L', long = "lib-dir")]
+    pub lib_dirs: Vec<PathBuf>,
+
+    /// Adds specific library
+    #[clap(short = 'l')]
+    pub libs: Vec<PathBuf>,
+
+    /// Value of the program input variable in form of `name=value`, where value uses the same
+    /// literal syntax as the defaults in `.INPUT` segment
+    #[clap(short, long = "input", parse(try_from_str = parse_input))]
+    pub inputs: Vec<(String, String)>,
+
+    /// Source file of the program to debug
+    pub file: PathBuf,
+}
+
+const HELP: &str = "\
+Commands:
+  break, b LOCATION    set breakpoint at LINE, FILE:LINE, ROUTINE, ROUTINE:LABEL or LABEL
+  delete, d LOCATION   remove breakpoint
+  step, s [COUNT]      execute COUNT instructions (one by default)
+  continue, c          execute until a breakpoint is reached or the program completes
+  print, p REG         print value of register, like `a8[1]` or `s16[0]`
+  set REG VALUE        put literal value into register
+  regs                 print all registers
+  list, l              print source code around the current line
+  help, h              print this message
+  quit, q              exit the debugger";
+
+fn parse_input(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s.split_once('=').ok_or("input must have `name=value` form")?;
+    Ok((name.trim().trim_start_matches('$').to_owned(), value.trim().to_owned()))
+}
+
+fn main() {
+    let args: Args = Clap::parse();
+
+    if let Err(err) = debug(&args) {
+        eprintln!("{}\n", err);
+        exit(1)
+    }
+}
+
+/// Source-level information about the debugged program
+struct Session {
+    sources: SourceMap,
+    module: Module,
+    debug: DebugInfo,
+    lib_id: LibId,
+}
+
+fn debug(args: &Args) -> Result<(), MainError> {
+    let file_name = args.file.display().to_string();
+    let sources = SourceMap::load(&args.file)?;
+    let (program, issues) = Program::analyze(&sources)?;
+    if issues.has_errors() {
+        return Err(MainError::Syntax(
+            file_name,
+            issues.count_errors(),
+            issues.count_warnings(),
+            issues.to_string(),
+        ));
+    }
+    let (module, issues) = program.compile_debug(&mut None)?;
+    if issues.has_errors() {
+        return Err(MainError::Compile(
+            file_name,
+            issues.count_errors(),
+            issues.count_warnings(),
+            issues.to_string(),
+        ));
+    }
+
+    let mut libs = vec![];
+    for path in &args.lib_dirs {
+        libs.extend(enumerate_libs(path)?);
+    }
+    libs.extend(args.libs.iter().cloned());
+    let mut lib_man = LibManager::with(libs)?;
+
+    let name = args.file.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let mut modules = BTreeMap::new();
+    modules.insert(name.clone(), module.clone());
+    let (product, issues) =
+        Module::link_bin(&modules, name.clone(), "debug".to_owned(), &mut lib_man)?;
+    if issues.has_errors() {
+        return Err(MainError::Linking(
+            name,
+            issues.count_errors(),
+            issues.count_warnings(),
+            issues.to_string(),
+        ));
+    }
+    let bin = match product {
+        Product::Bin(bin) => bin,
+        Product::Lib(_) => return Err(BuildError::NotBinary(name).into()),
+    };
+
+    let values = args.inputs.iter().cloned().collect();
+    let inputs = bin.inputs(&values)?;
+    let prog = bin.program(&mut lib_man)?;
+    let mut debugger = Debugger::with(&prog, Context::from(inputs));
+
+    let debug = bin.debug().cloned().unwrap_or_default();
+    let session = Session { sources, module, debug, lib_id: bin.lib_id() };
+    eprintln!("\x1B[1;32mDebugging\x1B[0m {}; type `help` for the list of commands", file_name);
+    session.show(&mut debugger);
+
+    let stdin = io::stdin();
+    loop {
+        eprint!("\x1B[1;34m(aludbg)\x1B[0m ");
+        io::stderr().flush().ok();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        match session.command(&mut debugger, line.trim()) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => eprintln!("\x1B[1;31mError:\x1B[0m {}", err),
+        }
+    }
+    Ok(())
+}
+
+impl Session {
+    /// Executes debugger command, returning `false` if the debugger must exit
+    fn command(&self, debugger: &mut Debugger, line: &str) -> Result<bool, String> {
+        let (cmd, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let arg = arg.trim();
+        match cmd {
+            "" => {}
+            "break" | "b" => {
+                let site = self.locate(arg)?;
+                if debugger.set_breakpoint(site) {
+                    println!("breakpoint set at {}", self.describe(site));
+                }
+            }
+            "delete" | "d" => {
+                let site = self.locate(arg)?;
+                if debugger.remove_breakpoint(site) {
+                    println!("breakpoint removed from {}", self.describe(site));
+                }
+            }
+            "step" | "s" => {
+                let count = if arg.is_empty() {
+                    1
+                } else {
+                    arg.parse::<usize>().map_err(|_| format!("invalid step count `{}`", arg))?
+                };
+                for _ in 0..count {
+                    match debugger.step().map_err(|err| err.to_string())? {
+                        Halt::Step => {}
+                        halt => {
+                            println!("{}", halt);
+                            return Ok(true);
+                        }
+                    }
+                }
+                self.show(debugger);
+            }
+            "continue" | "c" => {
+                let halt = debugger.resume().map_err(|err| err.to_string())?;
+                println!("{}", halt);
+                self.show(debugger);
+            }
+            "print" | "p" => println!("{} = {}", arg, read_reg(debugger, arg)?),
+            "set" => {
+                let (reg, value) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or("`set` requires register and value arguments")?;
+                debugger
+                    .exec(&format!("put {}, {}", reg, value.trim()))
+                    .map_err(|err| err.to_string())?;
+                println!("{} = {}", reg, read_reg(debugger, reg)?);
+            }
+            "regs" => println!("{:?}", debugger.registers()),
+            "list" | "l" => self.list(debugger),
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" => return Ok(false),
+            _ => return Err(format!("unknown command `{}`; type `help` for the list", cmd)),
+        }
+        Ok(true)
+    }
+
+    /// Resolves breakpoint location into the code positions
+    fn locate(&self, location: &str) -> Result<LibSite, String> {
+        let pos = match location.rsplit_once(':') {
+            _ if location.is_empty() => return Err("location is required".to_owned()),
+            Some((file, line)) => match line.parse::<u32>() {
+                Ok(line) => self.debug.offsets(Some(file), line).next(),
+                Err(_) => self.debug.label(file, line),
+            },
+            None => match location.parse::<u32>() {
+                Ok(line) => self.debug.offsets(None, line).next(),
+                Err(_) => self.module.exports.get(location).copied().or_else(|| {
+                    self.debug.labels.values().find_map(|labels| labels.get(location).copied())
+                }),
+            },
+        };
+        pos.map(|pos| LibSite::with(pos, self.lib_id))
+            .ok_or_else(|| format!("unknown location `{}`", location))
+    }
+
+    /// Describes code position with its source location
+    fn describe(&self, site: LibSite) -> String {
+        match self.debug.loc(site.pos) {
+            Some(loc) => format!("{} ({})", loc, site),
+            None => site.to_string(),
+        }
+    }
+
+    /// Prints current source line and the next instruction
+    fn show(&self, debugger: &mut Debugger) {
+        let site = match debugger.site() {
+            Some(site) => site,
+            None => return,
+        };
+        if site.lib != self.lib_id {
+            println!("{}", site);
+        } else if let Some(loc) = self.debug.loc(site.pos) {
+            let text = self.line(&loc.file, loc.line).unwrap_or_default();
+            println!("{}\n{:>5} | {}", self.describe(site), loc.line, text.trim_end());
+        }
+        if let Ok(Some(instr)) = debugger.instr() {
+            println!("      > {}", instr);
+        }
+    }
+
+    /// Prints source code lines around the current one
+    fn list(&self, debugger: &Debugger) {
+        let loc = match debugger.site().and_then(|site| self.debug.loc(site.pos)) {
+            Some(loc) => loc,
+            None => return,
+        };
+        let from = loc.line.saturating_sub(5).max(1);
+        for line in from..loc.line + 5 {
+            let text = match self.line(&loc.file, line) {
+                Some(text) => text,
+                None => break,
+            };
+            let mark = if line == loc.line { '>' } else { ' ' };
+            println!("{} {:>5} | {}", mark, line, text.trim_end());
+        }
+    }
+
+    fn line(&self, file: &str, line: u32) -> Option<&str> {
+        let file = self.sources.files().find(|source| source.name == file)?;
+        file.text.lines().nth(line.checked_sub(1)? as usize)
+    }
+}
+
+/// Reads register value, formatting it in the literal syntax
+fn read_reg(debugger: &Debugger, reg: &str) -> Result<String, String> {
+    let (set, index) =
+        Operand::parse_reg(reg).ok_or_else(|| format!("`{}` is not a valid register", reg))?;
+    let regs = debugger.registers();
+    let value = match set {
+        RegAll::A(a) => Option::<Number>::from(regs.get_n(a, index)).map(|n| n.to_string()),
+        RegAll::F(f) => Option::<Number>::from(regs.get_n(f, index)).map(|n| n.to_string()),
+        RegAll::R(r) => Option::<Number>::from(regs.get_n(r, index)).map(|n| n.to_string()),
+        RegAll::S => regs.get_s(index as u8).map(|val| {
+            let bytes = AsRef::<[u8]>::as_ref(val);
+            match std::str::from_utf8(bytes) {
+                Ok(text) => format!("{:?}", text),
+                Err(_) => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            }
+        }),
+    };
+    Ok(value.unwrap_or_else(|| "~".to_owned()))
+}
@@ -0,0 +1,176 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+#![allow(clippy::result_large_err)]
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use aluasm::isa::{Context, Instr};
+use aluasm::linker::{enumerate_libs, LibManager};
+use aluasm::module::DataType;
+use aluasm::product::{DyBin, Product};
+use aluasm::{BuildError, MainError, RunError};
+use aluvm::data::encoding::Decode;
+use aluvm::Vm;
+use clap::{AppSettings, Parser as Clap};
+
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Clap)]
+#[clap(
+    name = "alurun",
+    bin_name = "alurun",
+    author,
+    version,
+    about,
+    setting = AppSettings::ColoredHelp
+)]
+pub struct Args {
+    /// Directories containing library files
+    #[clap(short = 'L', long = "lib-dir")]
+    pub lib_dirs: Vec<PathBuf>,
+
+    /// Adds specific library
+    #[clap(short = 'l')]
+    pub libs: Vec<PathBuf>,
+
+    /// Value of the program input variable in form of `name=value`, where value uses the same
+    /// literal syntax as the defaults in `.INPUT` segment. Overrides values from --inputs file
+    #[clap(short, long = "input", parse(try_from_str = parse_input))]
+    pub inputs: Vec<(String, String)>,
+
+    /// TOML or JSON file (detected by `.json` extension) with a table of input variable values.
+    /// Values are either numbers or strings using the literal syntax of `.INPUT` segment; integer
+    /// numbers are accepted for float variables
+    #[clap(short = 'f', long = "inputs")]
+    pub inputs_file: Option<PathBuf>,
+
+    /// Print registers after the program completes
+    #[clap(short, long)]
+    pub regs: bool,
+
+    /// Executable binary (`.rex`) file to run
+    pub file: PathBuf,
+}
+
+fn parse_input(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s.split_once('=').ok_or("input must have `name=value` form")?;
+    Ok((name.trim().trim_start_matches('$').to_owned(), value.trim().to_owned()))
+}
+
+fn main() {
+    let args: Args = Clap::parse();
+
+    let success = run(&args).unwrap_or_else(|err| {
+        eprintln!("{}\n", err);
+        exit(2)
+    });
+    if success {
+        eprintln!("\x1B[1;32m Finished\x1B[0m with success status");
+    } else {
+        eprintln!("\x1B[1;31m Finished\x1B[0m with failure status");
+    }
+    exit(if success { 0 } else { 1 })
+}
+
+/// Runs the program, returning the value of `st0` register after its completion
+fn run(args: &Args) -> Result<bool, MainError> {
+    let file_name = args.file.display().to_string();
+    let fd = File::open(&args.file).map_err(|err| BuildError::FileNotFound {
+        file: file_name.clone(),
+        details: Box::new(err),
+    })?;
+    let bin = match Product::decode(fd)
+        .map_err(|err| BuildError::ProductIncorrectData(file_name.clone(), err))?
+    {
+        Product::Bin(bin) => bin,
+        Product::Lib(_) => return Err(BuildError::NotBinary(file_name).into()),
+    };
+
+    let mut values = match &args.inputs_file {
+        Some(path) => read_inputs(path, &bin)?,
+        None => BTreeMap::new(),
+    };
+    values.extend(args.inputs.iter().cloned());
+    let inputs = bin.inputs(&values)?;
+
+    let mut libs = vec![];
+    for path in &args.lib_dirs {
+        libs.extend(enumerate_libs(path)?);
+    }
+    libs.extend(args.libs.iter().cloned());
+    let mut lib_man = LibManager::with(libs)?;
+    let program = bin.program(&mut lib_man)?;
+
+    eprintln!("\x1B[1;32m  Running\x1B[0m {}\x1B[1;34m@{}\x1B[0m", bin.name(), bin.org());
+    let mut vm = Vm::<Instr>::new();
+    let success = vm.run(&program, &Context::from(inputs));
+
+    if args.regs {
+        println!("{:?}", vm.registers);
+    }
+
+    Ok(success)
+}
+
+/// Reads input values from TOML or JSON file, converting numbers into literals. Integer numbers
+/// given for float variables of the binary are converted into float literals.
+fn read_inputs(path: &Path, bin: &DyBin) -> Result<BTreeMap<String, String>, MainError> {
+    let is_float = |name: &str| {
+        bin.var_index(name)
+            .and_then(|index| bin.vars().get(index as usize))
+            .map(|var| matches!(var.data, DataType::Float(..)))
+            .unwrap_or_default()
+    };
+
+    let file_name = path.display().to_string();
+    let text = fs::read_to_string(path).map_err(|err| BuildError::FileNoAccess {
+        file: file_name.clone(),
+        details: Box::new(err),
+    })?;
+
+    let mut values = BTreeMap::new();
+    if path.extension().unwrap_or_default() == "json" {
+        let table = serde_json::from_str::<BTreeMap<String, serde_json::Value>>(&text)
+            .map_err(|err| RunError::InputFile(file_name, err.to_string()))?;
+        for (name, value) in table {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Number(n) if n.is_f64() || is_float(&name) => {
+                    float_lit(n.to_string())
+                }
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => return Err(RunError::InputFileValue(name).into()),
+            };
+            values.insert(name, value);
+        }
+    } else {
+        let table = toml::from_str::<BTreeMap<String, toml::Value>>(&text)
+            .map_err(|err| RunError::InputFile(file_name, err.to_string()))?;
+        for (name, value) in table {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Float(f) if f.is_finite() => float_lit(f.to_string()),
+                toml::Value::Integer(i) if is_float(&name) => float_lit(i.to_string()),
+                toml::Value::Integer(i) => i.to_string(),
+                _ => return Err(RunError::InputFileValue(name).into()),
+            };
+            values.insert(name, value);
+        }
+    }
+    Ok(values)
+}
+
+/// Ensures that the number has a fractional part, which is required by float literals
+fn float_lit(mut s: String) -> String {
+    if !s.contains('.') {
+        let pos = s.find(|c| c == 'e' || c == 'E').unwrap_or(s.len());
+        s.insert_str(pos, ".0");
+    }
+    s
+}
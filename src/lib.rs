@@ -18,17 +18,20 @@ mod pipelines;
 use std::error::Error;
 use std::num::ParseIntError;
 
-use aluvm::data::encoding::DecodeError;
-use aluvm::isa::Instr;
-use aluvm::library::{CodeEofError, IsaSegError};
+use aluvm::library::{CodeEofError, IsaSegError, LibId, LibSite};
 use amplify::{hex, IoError};
-pub use model::{ast, issues, module, product};
+use pest::error::{InputLocation, LineColLocation};
+pub use model::{ast, debug, isa, issues, module, product, source};
 #[doc(hidden)]
 pub use paste::paste;
-pub use pipelines::{analyzer, compiler, linker, parser};
-
-use crate::issues::Src;
-use crate::module::CallTableError;
+pub use pipelines::assembler::Assembler;
+pub use pipelines::{
+    analyzer, assembler, compiler, debugger, decompiler, formatter, linker, navigator, parser,
+    plugins, runner, tester, verifier,
+};
+
+use crate::issues::{Analyze, Diagnostic, Location, Severity, Src, Stage};
+use crate::module::{CallTableError, ModuleError};
 use crate::parser::Rule;
 use crate::product::DyError;
 
@@ -47,6 +50,14 @@ pub enum MainError {
     #[from]
     Build(BuildError),
 
+    #[display("\x1B[1;31mError:\x1B[0m {0}")]
+    #[from]
+    Run(RunError),
+
+    #[display("\x1B[1;31mError:\x1B[0m {0}")]
+    #[from]
+    Debug(DebugError),
+
     #[display(
         "{1}\n\x1B[1;31mError:\x1B[0m could not compile `{0}` due to a previous parsing error"
     )]
@@ -68,7 +79,7 @@ pub enum MainError {
         "\x1B[1;31mError:\x1B[0m {0}\n\n\x1B[1;31mError:\x1B[0m failing due to broken binary data \
          in module `{1}` "
     )]
-    Module(DecodeError, String),
+    Module(ModuleError, String),
 
     #[display(
         "{3}\n\x1B[1;31mError:\x1B[0m could not link `{0}` due to {1} previous error(s); {2} \
@@ -84,163 +95,145 @@ pub enum LexerError<'i> {
     UnknownSegment(Rule),
 
     /// library statement `{0:#}` has no detectable name
-    /// {0}
     LibNoName(Src<'i>),
 
     /// library statement `{0:#}` has no detectable lib id
-    /// {0}
     LibNoId(Src<'i>),
 
     /// routine `{0:#}` has no detectable name
-    /// {0}
     RoutineNoName(Src<'i>),
 
     /// routine `{0:#}` does not start with neither .MAIN or .ROUTINE
-    /// {0}
     RoutineUnrecognized(Src<'i>),
 
     /// label in `{0:#}` is not followed by an instruction
-    /// {0}
     StatementNoInstruction(Src<'i>),
 
     /// mnemonic is followed by non-flag rules
-    /// {0}
     StatementNoFlag(Src<'i>),
 
     /// operator `{0:#}` is not composed of mnemonic and flags
-    /// {0}
     OperatorMiscomposition(Src<'i>),
 
     /// flag without flag value
-    /// {0}
     FlagWithoutValue(Src<'i>),
 
     /// register operand contains no register type
-    /// {0}
     RegisterNoType(Src<'i>),
 
     /// register operand does not specify register name
-    /// {0}
     RegisterNoName(Src<'i>),
 
     /// register name must be encoded as a decimal number
-    /// {0}
     /// details: {1}
     RegisterNameNonDecimal(Src<'i>, ParseIntError),
 
     /// register operand does not specify register index
-    /// {0}
     RegisterNoIndex(Src<'i>),
 
     /// register index {2} is not a decimal number
-    /// {0}
     /// details: {1}
     RegisterIndexNonDecimal(Src<'i>, ParseIntError, &'i str),
 
     /// unknown register type
-    /// {0}
     RegisterUnknown(Src<'i>),
 
     /// call statement without library name
-    /// {0}
     CallWithoutLibName(Src<'i>),
 
     /// call statement without routine name
-    /// {0}
     CallWithoutRoutineName(Src<'i>),
 
     /// unknown operand format `{1}` inside the statement `{0:#}`
-    /// {0}
     OperandUnknown(Src<'i>, &'i str),
 
     /// literal contains no data
-    /// {0}
     LiteralNoData(Src<'i>),
 
     /// incorrect decimal literal `{1}`
-    /// {0}
     /// details: {2}
     LiteralWrongDec(Src<'i>, &'i str, ParseIntError),
 
     /// incorrect hex literal `{1}`
-    /// {0}
     /// details: {2}
     LiteralWrongHex(Src<'i>, &'i str, hex::Error),
 
     /// incorrect oct literal `{1}`
-    /// {0}
     /// details: {2}
     LiteralWrongOct(Src<'i>, &'i str, ParseIntError),
 
     /// incorrect bin literal `{1}`
-    /// {0}
     /// details: {2}
     LiteralWrongBin(Src<'i>, &'i str, ParseIntError),
 
     /// float literal contains no whole mantissa part
-    /// {0}
     FloatNoWhole(Src<'i>),
 
     /// float literal contains no fractional mantissa part
-    /// {0}
     FloatNoFraction(Src<'i>),
 
     /// float literal mantissa whole part is not an integer
-    /// {0}
     /// details: {1}
     FloatWholeNotNumber(Src<'i>, ParseIntError),
 
-    /// float literal mantissa fraction part is not an integer
-    /// {0}
-    /// details: {1}
-    FloatFractionNotNumber(Src<'i>, ParseIntError),
-
     /// float literal exponential part is not an integer
-    /// {0}
     /// details: {1}
     FloatExponentialNotNumber(Src<'i>, ParseIntError),
 
     /// unknown type of literal `{1:?}`
-    /// {0}
     LiteralUnknown(Src<'i>, Rule),
 
     /// constant statement has no name
-    /// {0}
     ConstNoName(Src<'i>),
 
     /// constant statement has no value
-    /// {0}
     ConstNoValue(Src<'i>),
 
     /// input variable has no name
-    /// {0}
     VarNoName(Src<'i>),
 
     /// input variable has no type
-    /// {0}
     VarNoType(Src<'i>),
 
     /// input variable has no description
-    /// {0}
     VarNoDescription(Src<'i>),
 
     /// input variable description is not a string literal
-    /// {0}
     VarWrongDescription(Src<'i>),
 
     /// unknown variable type `{0}`
-    /// {1}
     VarTypeUnknown(String, Src<'i>),
 
     /// unable to detect program code
     ProgramAbsent,
+
+    /// macro `{0:#}` has no detectable name
+    MacroNoName(Src<'i>),
+
+    /// macro `{0:#}` has no parameter list
+    MacroNoParams(Src<'i>),
+
+    /// include directive `{0:#}` has no file path
+    IncludeNoPath(Src<'i>),
+
+    /// included file `{0}` is absent from the source map
+    IncludeNotLoaded(String),
+
+    /// expression `{0:#}` misses an operand
+    ExprIncomplete(Src<'i>),
+
+    /// unknown expression component `{0:#}`
+    ExprUnknown(Src<'i>),
 }
 
 impl<'i> From<LexerError<'i>> for MainError {
     #[inline]
     fn from(err: LexerError<'i>) -> Self {
-        let no = err.errno();
-        MainError::Lexer(err.to_string(), no)
+        let msg = match err.src() {
+            Some(src) => format!("{}\n{}", err, src),
+            None => err.to_string(),
+        };
+        MainError::Lexer(msg, err.errno())
     }
 }
 
@@ -273,7 +266,6 @@ impl<'i> LexerError<'i> {
             LexerError::FloatNoWhole(_) => 24,
             LexerError::FloatNoFraction(_) => 25,
             LexerError::FloatWholeNotNumber(_, _) => 26,
-            LexerError::FloatFractionNotNumber(_, _) => 27,
             LexerError::FloatExponentialNotNumber(_, _) => 28,
             LexerError::LiteralUnknown(_, _) => 29,
             LexerError::ConstNoName(_) => 30,
@@ -284,6 +276,71 @@ impl<'i> LexerError<'i> {
             LexerError::ProgramAbsent => 35,
             LexerError::VarNoType(_) => 36,
             LexerError::VarTypeUnknown(_, _) => 37,
+            LexerError::MacroNoName(_) => 38,
+            LexerError::MacroNoParams(_) => 39,
+            LexerError::IncludeNoPath(_) => 40,
+            LexerError::IncludeNotLoaded(_) => 41,
+            LexerError::ExprIncomplete(_) => 42,
+            LexerError::ExprUnknown(_) => 43,
+        }
+    }
+
+    /// Describes the error as a diagnostic reported at the analysis stage
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            errno: self.errno(),
+            severity: Severity::Error,
+            stage: Analyze::NAME,
+            message: self.to_string(),
+            location: self.src().map(Location::from),
+        }
+    }
+
+    /// Location of the source code which has caused the error
+    pub fn src(&self) -> Option<&Src<'i>> {
+        match self {
+            LexerError::LibNoName(src)
+            | LexerError::LibNoId(src)
+            | LexerError::RoutineNoName(src)
+            | LexerError::RoutineUnrecognized(src)
+            | LexerError::StatementNoInstruction(src)
+            | LexerError::StatementNoFlag(src)
+            | LexerError::OperatorMiscomposition(src)
+            | LexerError::FlagWithoutValue(src)
+            | LexerError::RegisterNoType(src)
+            | LexerError::RegisterNoName(src)
+            | LexerError::RegisterNameNonDecimal(src, _)
+            | LexerError::RegisterNoIndex(src)
+            | LexerError::RegisterIndexNonDecimal(src, _, _)
+            | LexerError::RegisterUnknown(src)
+            | LexerError::CallWithoutLibName(src)
+            | LexerError::CallWithoutRoutineName(src)
+            | LexerError::OperandUnknown(src, _)
+            | LexerError::LiteralNoData(src)
+            | LexerError::LiteralWrongDec(src, _, _)
+            | LexerError::LiteralWrongHex(src, _, _)
+            | LexerError::LiteralWrongOct(src, _, _)
+            | LexerError::LiteralWrongBin(src, _, _)
+            | LexerError::FloatNoWhole(src)
+            | LexerError::FloatNoFraction(src)
+            | LexerError::FloatWholeNotNumber(src, _)
+            | LexerError::FloatExponentialNotNumber(src, _)
+            | LexerError::LiteralUnknown(src, _)
+            | LexerError::ConstNoName(src)
+            | LexerError::ConstNoValue(src)
+            | LexerError::VarNoName(src)
+            | LexerError::VarNoDescription(src)
+            | LexerError::VarWrongDescription(src)
+            | LexerError::VarNoType(src)
+            | LexerError::VarTypeUnknown(_, src)
+            | LexerError::MacroNoName(src)
+            | LexerError::MacroNoParams(src)
+            | LexerError::IncludeNoPath(src)
+            | LexerError::ExprIncomplete(src)
+            | LexerError::ExprUnknown(src) => Some(src),
+            LexerError::UnknownSegment(_)
+            | LexerError::IncludeNotLoaded(_)
+            | LexerError::ProgramAbsent => None,
         }
     }
 }
@@ -298,7 +355,7 @@ pub enum InstrError {
     Read,
 
     /// instruction has changed from `{0}` to `{1}`
-    Changed(&'static str, Instr),
+    Changed(&'static str, String),
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
@@ -319,17 +376,17 @@ pub enum CompilerError {
     InstrRead(u16),
 
     /// instruction at position {0} has changed from `{1}` into `{2}`
-    InstrChanged(u16, &'static str, Instr),
+    InstrChanged(u16, &'static str, String),
 
     /// Call table error
     #[from]
     #[display(inner)]
     CallTable(CallTableError),
 
-    /// unable to construct float representation of literal `{0}.{1}e{2}`
+    /// unable to construct float representation of literal `{0}`
     ///
-    /// details: {3:?}
-    FloatConstruction(u128, u128, u16, amplify::num::apfloat::ParseError),
+    /// details: {1:?}
+    FloatConstruction(String, amplify::num::apfloat::ParseError),
 }
 
 impl CompilerError {
@@ -345,7 +402,7 @@ impl CompilerError {
             CompilerError::RoutineEmpty(_) => 6,
             CompilerError::InstrRead(_) => 7,
             CompilerError::InstrChanged(_, _, _) => 8,
-            CompilerError::FloatConstruction(_, _, _, _) => 9,
+            CompilerError::FloatConstruction(_, _) => 9,
             CompilerError::CallTable(_) => 10,
         }
     }
@@ -373,7 +430,13 @@ pub enum LinkerError {
     InstrRead(u16),
 
     /// instruction at position {0} has changed from `{1}` into `{2}`
-    InstrChanged(u16, &'static str, Instr),
+    InstrChanged(u16, &'static str, String),
+
+    /// code of module `{0}` can't be disassembled since its last instruction is incomplete
+    ModuleCode(String),
+
+    /// module `{0}` calls library {1} which is absent from the joined library segment
+    ModuleLibAbsent(String, LibId),
 }
 
 impl LinkerError {
@@ -381,6 +444,8 @@ impl LinkerError {
         match self {
             LinkerError::InstrRead(_) => 3,
             LinkerError::InstrChanged(_, _, _) => 4,
+            LinkerError::ModuleCode(_) => 5,
+            LinkerError::ModuleLibAbsent(_, _) => 6,
         }
     }
 
@@ -400,6 +465,101 @@ impl From<LinkerError> for MainError {
     }
 }
 
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum DecompileError {
+    /// code can't be disassembled since its last instruction is incomplete
+    #[from(CodeEofError)]
+    Code,
+
+    /// routine `{0}` starts at position {1:#06X} which is not a beginning of an instruction
+    RoutineMisaligned(String, u16),
+
+    /// instruction at position {0:#06X} references position {1:#06X} which is not a beginning of
+    /// an instruction
+    TargetMisaligned(u16, u16),
+
+    /// instruction at position {0:#06X} jumps to position {1:#06X} belonging to a different
+    /// routine
+    JumpOutside(u16, u16),
+
+    /// instruction at position {0:#06X} calls routine at position {2:#06X} of library {1}, which
+    /// name is unknown; please provide the library with the -l argument
+    CallUnresolved(u16, LibId, u16),
+
+    /// instruction at position {0:#06X} reads input variable {1} which is not defined
+    VarAbsent(u16, u16),
+
+    /// instruction `{1}` at position {0:#06X} has no representation in the assembly language
+    Unsupported(u16, String),
+
+    /// instruction at position {0:#06X} puts undefined value into a register
+    ValueUndefined(u16),
+
+    /// integer value of {0} bytes exceeds the maximum length of 128 bytes supported by literals
+    IntTooLarge(usize),
+
+    /// float value {0} can't be represented by a literal which parses back into the same value
+    FloatInexact(String),
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum RunError {
+    /// library {0} required by the program is not found; please provide it with -L or -l argument
+    LibNotFound(LibId),
+
+    /// program requires too many libraries to fit into a single VM
+    TooManyLibs,
+
+    /// input variable `{0}` is not defined by the program
+    InputUnknown(String),
+
+    /// value `{1}` is not valid for input variable `{0}`
+    InputInvalid(String, String),
+
+    /// unable to parse input values file `{0}`
+    ///
+    /// details: {1}
+    InputFile(String, String),
+
+    /// input value of `{0}` must be either a number or a string with a literal
+    InputFileValue(String),
+
+    /// routine `{0}` is not exported by the library
+    RoutineUnknown(String),
+
+    /// test `{0}` is not defined by the library
+    TestUnknown(String),
+
+    /// library has no debug information required to run the tests
+    DebugInfoAbsent,
+
+    #[from]
+    #[display(inner)]
+    Debug(DebugError),
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum DebugError {
+    /// library code can't be disassembled since it is truncated
+    #[from(CodeEofError)]
+    Code,
+
+    /// library {0} is not a part of the program
+    LibAbsent(LibId),
+
+    /// execution reached position {0} which is not at an instruction boundary
+    InstrMisaligned(LibSite),
+
+    /// program has already completed
+    Completed,
+
+    /// `{0}` is not a valid statement which can be executed over the registers
+    Statement(String),
+}
+
 #[derive(Debug, Display, Error)]
 #[display(doc_comments)]
 pub enum BuildError {
@@ -449,6 +609,21 @@ pub enum BuildError {
     /// error disassembling file `{file}` since last instruction is incomplete
     Disassembling { file: String },
 
+    /// unable to decompile file `{file}`
+    ///
+    /// details: {details}
+    Decompiling { file: String, details: DecompileError },
+
+    /// decompiled source of `{0}` does not reassemble into the same module; please report this as
+    /// a bug
+    DecompileMismatch(String),
+
+    /// product name must be provided with -n argument when linking multiple object files
+    ProductNameRequired,
+
+    /// no object files found to link in `{0}`
+    NoObjects(String),
+
     /// path `{0}` specified for objects directory (-O | --obj-dir) is not a directory. Try use
     /// lowercase -o argument if you'd like to specify a single object file
     ObjDirIsFile(String),
@@ -476,8 +651,108 @@ pub enum BuildError {
     /// details: {1}
     LibIncorrectData(String, DyError),
 
+    /// product `{0}` is a library and can't be executed
+    NotBinary(String),
+
+    /// {1} test(s) of `{0}` failed
+    TestsFailed(String, usize),
+
+    /// product file at `{0}` has incorrect binary data
+    ///
+    /// details: {1}
+    ProductIncorrectData(String, DyError),
+
     /// library file at `{0}` is not accessible
     ///
     /// details: {1}
     LibNotAccessible(String, Box<dyn Error>),
+
+    /// formatting of `{0}` changes the program it defines; please report this as a bug
+    FormatMismatch(String),
+
+    /// unable to write formatted code into source file `{file}`
+    ///
+    /// details: {details}
+    SourceFileWrite { file: String, details: Box<dyn Error> },
+
+    /// {0} file(s) are not formatted; run `aluasm fmt` to format them
+    Unformatted(usize),
+}
+
+/// Errors happening when loading source files into the source map
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SourceError {
+    /// {0}
+    #[from]
+    Build(BuildError),
+
+    /// unable to parse `{0}`
+    ///
+    /// {1}
+    Parser(String, pest::error::Error<Rule>),
+}
+
+impl SourceError {
+    /// Describes the error as a diagnostic reported at the analysis stage. Errors reading source
+    /// files have no location and are reported with zero errno.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let (message, location) = match self {
+            SourceError::Build(err) => (err.to_string(), None),
+            SourceError::Parser(file, err) => {
+                let range = match err.location {
+                    InputLocation::Pos(pos) => pos..pos,
+                    InputLocation::Span((start, end)) => start..end,
+                };
+                let (line, column) = match err.line_col {
+                    LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+                };
+                let location =
+                    Location { file: Some(file.clone()), range, line, column, expansion: None };
+                (err.variant.message().into_owned(), Some(location))
+            }
+        };
+        Diagnostic { errno: 0, severity: Severity::Error, stage: Analyze::NAME, message, location }
+    }
+}
+
+impl From<SourceError> for MainError {
+    fn from(err: SourceError) -> Self {
+        match err {
+            SourceError::Build(err) => MainError::Build(err),
+            SourceError::Parser(file, err) => MainError::Parser(file, err),
+        }
+    }
+}
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum AssemblerError {
+    /// {0}
+    #[from]
+    Build(BuildError),
+
+    /// unable to parse `{0}`
+    ///
+    /// {1}
+    Parser(String, pest::error::Error<Rule>),
+
+    /// internal lexer error X{1:04}: {0}
+    Lexer(String, u16, Option<Location>),
+
+    /// internal compiler error: {0}
+    #[from]
+    Compiler(CompilerError),
+
+    /// assembling failed due to {errors} error(s); {warnings} warning(s) emitted
+    Failed { errors: usize, warnings: usize, diagnostics: Vec<Diagnostic> },
+}
+
+impl From<SourceError> for AssemblerError {
+    fn from(err: SourceError) -> Self {
+        match err {
+            SourceError::Build(err) => AssemblerError::Build(err),
+            SourceError::Parser(file, err) => AssemblerError::Parser(file, err),
+        }
+    }
 }
@@ -8,30 +8,44 @@
 //! Abstract syntax tree data types
 
 use std::collections::{BTreeMap, BTreeSet};
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::Hash;
 use std::str::FromStr;
 
 use aluvm::data::{FloatLayout, IntLayout};
 use aluvm::library::LibId;
 use aluvm::reg::{Reg32, RegAll};
-use aluvm::Isa;
 use amplify::num::u1024;
 use pest::Span;
 
+use crate::source::SourceMap;
+
 #[derive(Clone, Hash, Debug)]
 pub struct Program<'i> {
-    pub isae: BTreeSet<Isa>,
+    pub sources: &'i SourceMap,
+    /// Identifiers of the instruction sets declared in `.ISAE` segment and spans of their
+    /// declarations
+    pub isae: BTreeMap<String, Span<'i>>,
     pub libs: Libs<'i>,
     pub main: Option<Routine<'i>>,
     pub routines: BTreeMap<String, Routine<'i>>,
+    /// Tests defined with `.TEST` segments, which are compiled only when the tests are run
+    pub tests: BTreeMap<String, Routine<'i>>,
+    pub macros: BTreeMap<String, Macro<'i>>,
     pub consts: BTreeMap<String, Const<'i>>,
     pub input: BTreeMap<String, Var<'i>>,
+    /// Mnemonics of the instructions provided by the ISA plugins the program is analyzed with
+    pub operators: BTreeMap<String, Operator>,
+    /// Identifiers of the instruction sets provided by the ISA plugins the program is analyzed
+    /// with
+    pub isa_ids: BTreeSet<&'static str>,
 }
 
 #[derive(Clone, Hash, Debug)]
 pub struct Libs<'i> {
     pub map: BTreeMap<String, LibId>,
+    /// Spans of the individual library declarations
+    pub spans: BTreeMap<String, Span<'i>>,
     pub span: Span<'i>,
 }
 
@@ -39,6 +53,8 @@ pub struct Libs<'i> {
 pub struct Const<'i> {
     pub name: String,
     pub value: Literal,
+    /// Names of the constants used in the expression defining the constant value
+    pub refs: BTreeSet<String>,
     pub span: Span<'i>,
 }
 
@@ -67,6 +83,17 @@ pub struct Routine<'i> {
     pub span: Span<'i>,
 }
 
+/// Named sequence of statements which gets expanded at each place of its invocation
+#[derive(Clone, Hash, Debug)]
+pub struct Macro<'i> {
+    pub name: String,
+    /// Parameter names, including `$` prefix
+    pub params: Vec<(String, Span<'i>)>,
+    pub labels: BTreeMap<String, u16>,
+    pub statements: Vec<Statement<'i>>,
+    pub span: Span<'i>,
+}
+
 #[derive(Clone, Hash, Debug)]
 pub struct Statement<'i> {
     pub label: Option<(String, Span<'i>)>,
@@ -74,6 +101,8 @@ pub struct Statement<'i> {
     pub flags: FlagSet<'i, char>,
     pub operands: Vec<Operand<'i>>,
     pub span: Span<'i>,
+    /// Span of the macro invocation, if the statement comes from a macro expansion
+    pub expansion: Option<Span<'i>>,
 }
 
 #[derive(Clone, Hash, Debug)]
@@ -100,6 +129,8 @@ pub enum Operand<'i> {
     Call { lib: String, routine: String, span: Span<'i> },
     Lit(Literal, Span<'i>),
     Const(String, Span<'i>),
+    /// Constant expression which is not folded yet
+    Expr(Expr<'i>, Span<'i>),
 }
 
 impl<'i> Operand<'i> {
@@ -109,7 +140,8 @@ impl<'i> Operand<'i> {
             | Operand::Goto(_, span)
             | Operand::Call { span, .. }
             | Operand::Lit(_, span)
-            | Operand::Const(_, span) => span,
+            | Operand::Const(_, span)
+            | Operand::Expr(_, span) => span,
         }
     }
 
@@ -120,10 +152,51 @@ impl<'i> Operand<'i> {
             Operand::Call { .. } => "call statement",
             Operand::Lit(lit, _) => lit.description(),
             Operand::Const(_, _) => "constant value",
+            Operand::Expr(_, _) => "constant expression",
+        }
+    }
+}
+
+/// Arithmetic or bitwise expression over integer literals and constants
+#[derive(Clone, Hash, Debug)]
+pub enum Expr<'i> {
+    Int(u1024, Span<'i>),
+    Const(String, Span<'i>),
+    Binary(ExprOp, Box<Expr<'i>>, Box<Expr<'i>>, Span<'i>),
+}
+
+impl<'i> Expr<'i> {
+    pub fn as_span(&self) -> &Span<'i> {
+        match self {
+            Expr::Int(_, span) | Expr::Const(_, span) | Expr::Binary(_, _, _, span) => span,
         }
     }
 }
 
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum ExprOp {
+    #[display("+")]
+    Add,
+    #[display("-")]
+    Sub,
+    #[display("*")]
+    Mul,
+    #[display("/")]
+    Div,
+    #[display("%")]
+    Rem,
+    #[display("<<")]
+    Shl,
+    #[display(">>")]
+    Shr,
+    #[display("&")]
+    And,
+    #[display("|")]
+    Or,
+    #[display("^")]
+    Xor,
+}
+
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub enum Call {
     Routine(String),
@@ -132,8 +205,12 @@ pub enum Call {
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub enum Literal {
-    Int(u1024, IntBase),
-    Float(u128, u128, u16),
+    /// Integer literal: its absolute value, sign, base and optional type suffix (like `5u16`)
+    Int { val: u1024, neg: bool, base: IntBase, ty: Option<IntLayout> },
+    /// Float literal: sign, whole part of the significand, digits of its fractional part (kept as
+    /// written, since leading zeros are significant), exponent and optional type suffix (like
+    /// `1.05f32`)
+    Float { neg: bool, int: u128, frac: String, exp: i16, ty: Option<FloatLayout> },
     String(String),
     Bytes(Vec<u8>),
     Char(u8),
@@ -142,13 +219,32 @@ pub enum Literal {
 impl Literal {
     pub fn description(&self) -> &'static str {
         match self {
-            Literal::Int(_, _) => "integer literal",
-            Literal::Float(_, _, _) => "float literal",
+            Literal::Int { neg: true, .. } => "negative integer literal",
+            Literal::Int { .. } => "integer literal",
+            Literal::Float { .. } => "float literal",
             Literal::String(_) => "string literal",
             Literal::Char(_) => "char literal",
             Literal::Bytes(_) => "bytes literal",
         }
     }
+
+    /// Constructs unsigned integer literal without type suffix
+    pub fn uint(val: u1024, base: IntBase) -> Self {
+        Literal::Int { val, neg: false, base, ty: None }
+    }
+}
+
+/// Checks whether integer with the given absolute value and sign fits into the integer layout
+pub(crate) fn int_fits(val: u1024, neg: bool, layout: IntLayout) -> bool {
+    let bits = layout.bytes as usize * 8;
+    match (layout.signed, neg) {
+        (_, true) if val == u1024::MIN => true,
+        (false, true) => false,
+        (false, false) => bits >= 1024 || val >> bits == u1024::MIN,
+        (true, _) if bits > 1024 => true,
+        (true, false) => val < u1024::from(1u64) << (bits - 1),
+        (true, true) => val <= u1024::from(1u64) << (bits - 1),
+    }
 }
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -159,8 +255,7 @@ pub enum IntBase {
     Bin,
 }
 
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
-#[display(Debug)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 #[allow(non_camel_case_types)]
 pub enum Operator {
     abs,
@@ -198,6 +293,7 @@ pub enum Operator {
     neg,
     not,
     or,
+    pld,
     put,
     putif,
     read,
@@ -207,6 +303,7 @@ pub enum Operator {
     ripemd,
     routine,
     scl,
+    scn,
     scr,
     secpadd,
     secpgen,
@@ -224,16 +321,28 @@ pub enum Operator {
     swp,
     xor,
     nop,
+    /// Mnemonic of the instruction provided by a third-party ISA plugin
+    Ext(&'static str),
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operator::Ext(mnemonic) => f.write_str(mnemonic),
+            other => Debug::fmt(other, f),
+        }
+    }
 }
 
 impl Operator {
-    pub const fn all() -> [Operator; 61] {
+    pub const fn all() -> [Operator; 63] {
         use Operator::*;
         [
             abs, add, and, call, clr, cnt, cnv, con, cpy, dec, del, div, dup, eq, exec, extr, fail,
             fill, find, gt, ifn, ifz, inc, inj, ins, jif, jmp, join, len, lt, mov, mul, neg, not,
-            or, put, putif, read, rem, ret, rev, ripemd, routine, scl, scr, secpadd, secpgen,
-            secpmul, secpneg, sha2, shl, shr, splt, spy, st, stinv, sub, succ, swp, xor, nop,
+            or, pld, put, putif, read, rem, ret, rev, ripemd, routine, scl, scn, scr, secpadd,
+            secpgen, secpmul, secpneg, sha2, shl, shr, splt, spy, st, stinv, sub, succ, swp, xor,
+            nop,
         ]
     }
 }
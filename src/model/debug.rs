@@ -0,0 +1,158 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Debug information linking code offsets back to the source code
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter, Write as WriteTrait};
+use std::io::{Read, Write};
+
+use aluvm::data::encoding::{Decode, DecodeError, Encode, EncodeError, MaxLenWord};
+use amplify::hex::ToHex;
+use sha2::{Digest, Sha256};
+
+/// Location of a statement in the source code
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{file}:{line}:{col}")]
+pub struct SrcLoc {
+    /// Name of the source file, as it is displayed in diagnostic messages
+    pub file: String,
+    pub line: u32,
+    pub col: u32,
+}
+
+/// Debug information of a compiled module
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
+pub struct DebugInfo {
+    /// Source locations of statements by the code offsets of their instructions. Statements
+    /// produced by macro expansion are located at the macro invocation.
+    pub lines: BTreeMap<u16, SrcLoc>,
+    /// Code offsets of labels, by routine name and label name
+    pub labels: BTreeMap<String, BTreeMap<String, u16>>,
+    /// SHA-256 hashes of the source files by their names, allowing to detect that the source
+    /// code has changed since the compilation
+    pub sources: BTreeMap<String, [u8; 32]>,
+}
+
+impl DebugInfo {
+    /// Finds location of the statement which instruction starts at or covers code offset `pos`
+    pub fn loc(&self, pos: u16) -> Option<&SrcLoc> {
+        self.lines.range(..=pos).next_back().map(|(_, loc)| loc)
+    }
+
+    /// Returns code offsets of all statements located at the source `line`. If `file` is not
+    /// given, lines of all source files are matched.
+    pub fn offsets<'a>(
+        &'a self,
+        file: Option<&'a str>,
+        line: u32,
+    ) -> impl Iterator<Item = u16> + 'a {
+        self.lines
+            .iter()
+            .filter(move |(_, loc)| loc.line == line && file.map(|f| f == loc.file).unwrap_or(true))
+            .map(|(pos, _)| *pos)
+    }
+
+    /// Returns code offset of the `label` defined in the `routine`
+    pub fn label(&self, routine: &str, label: &str) -> Option<u16> {
+        self.labels.get(routine)?.get(label).copied()
+    }
+
+    /// Computes hash of the source file text, as it is kept in [`DebugInfo::sources`]
+    pub fn source_hash(text: &str) -> [u8; 32] { Sha256::digest(text.as_bytes()).into() }
+
+    /// Adds debug information of another module which code is placed at `base` offset
+    pub(crate) fn join(&mut self, other: &DebugInfo, base: u16) {
+        self.lines
+            .extend(other.lines.iter().map(|(pos, loc)| (pos.wrapping_add(base), loc.clone())));
+        for (routine, labels) in &other.labels {
+            self.labels.insert(
+                routine.clone(),
+                labels.iter().map(|(label, pos)| (label.clone(), pos.wrapping_add(base))).collect(),
+            );
+        }
+        self.sources.extend(other.sources.iter().map(|(name, hash)| (name.clone(), *hash)));
+    }
+}
+
+impl Display for DebugInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (line, (name, hash)) in self.sources.iter().enumerate() {
+            if line > 0 {
+                write!(f, "{:1$}", "", f.width().unwrap_or_default())?;
+            }
+            writeln!(f, "{}\t{}", hash.to_hex(), name)?;
+        }
+        if self.sources.is_empty() {
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+impl Encode for DebugInfo {
+    type Error = EncodeError;
+
+    fn encode(&self, mut writer: impl Write) -> Result<usize, Self::Error> {
+        let mut count = len_u16(self.sources.len())?.encode(&mut writer)?;
+        for (name, hash) in &self.sources {
+            count += name.encode(&mut writer)?;
+            writer.write_all(hash)?;
+            count += hash.len();
+        }
+        count += len_u16(self.lines.len())?.encode(&mut writer)?;
+        for (pos, loc) in &self.lines {
+            count += pos.encode(&mut writer)?;
+            count += loc.file.encode(&mut writer)?;
+            count += loc.line.encode(&mut writer)?;
+            count += loc.col.encode(&mut writer)?;
+        }
+        count += len_u16(self.labels.len())?.encode(&mut writer)?;
+        for (routine, labels) in &self.labels {
+            count += routine.encode(&mut writer)?;
+            count += MaxLenWord::new(labels).encode(&mut writer)?;
+        }
+        Ok(count)
+    }
+}
+
+/// Converts length of a collection into the `u16` prefix preceding it in the encoded data
+fn len_u16(len: usize) -> Result<u16, EncodeError> {
+    u16::try_from(len).map_err(|_| EncodeError::ExceedingSize(len))
+}
+
+impl Decode for DebugInfo {
+    type Error = DecodeError;
+
+    fn decode(mut reader: impl Read) -> Result<Self, Self::Error>
+    where
+        Self: Sized,
+    {
+        let mut debug = DebugInfo::default();
+        for _ in 0..u16::decode(&mut reader)? {
+            let name = String::decode(&mut reader)?;
+            let mut hash = [0u8; 32];
+            reader.read_exact(&mut hash)?;
+            debug.sources.insert(name, hash);
+        }
+        for _ in 0..u16::decode(&mut reader)? {
+            let pos = u16::decode(&mut reader)?;
+            let loc = SrcLoc {
+                file: Decode::decode(&mut reader)?,
+                line: Decode::decode(&mut reader)?,
+                col: Decode::decode(&mut reader)?,
+            };
+            debug.lines.insert(pos, loc);
+        }
+        for _ in 0..u16::decode(&mut reader)? {
+            let routine = String::decode(&mut reader)?;
+            debug.labels.insert(routine, MaxLenWord::decode(&mut reader)?.release());
+        }
+        Ok(debug)
+    }
+}
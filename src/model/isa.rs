@@ -0,0 +1,522 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Instruction set produced by the assembler: AluVM core instructions extended with operations of
+//! AluRE ISA extension, which provide access to the runtime environment of the program, and RGB
+//! ISA extension, which provide access to the contract state validated by the program.
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::ops::RangeInclusive;
+
+use aluvm::data::{ByteStr, MaybeNumber, Number};
+use aluvm::isa::{Bytecode, BytecodeError, ExecStep, InstructionSet};
+use aluvm::library::{CodeEofError, LibSite, Read, Write};
+use aluvm::reg::{CoreRegs, Reg, Reg32, RegA, RegF, RegR, RegS};
+use amplify::num::{u1, u3, u4};
+
+use crate::module::DataType;
+
+/// Instructions supported by the assembler
+pub type Instr = aluvm::isa::Instr<ExtOp>;
+
+/// Values of the program input variables, in the same order as [`crate::module::Module::vars`].
+/// Provided to the VM as an execution context.
+pub type Inputs = Vec<DataType>;
+
+const INSTR_READA: u8 = 0b10_100_000;
+const INSTR_READF: u8 = 0b10_100_001;
+const INSTR_READR: u8 = 0b10_100_010;
+const INSTR_READS: u8 = 0b10_100_011;
+
+// RGB extension is defined by the assembler and is not the ISA of the RGB node implementations:
+// programs using it run only with the [`Context`] provided by the assembler tools. Its opcodes
+// directly follow AluRE ones in the range reserved by AluVM for ISA extensions, so the range of
+// [`ExtOp`] contains only assigned opcodes. The lowest bit of the opcode is used for the `.i`/`.o`
+// state flag and the next one for the operation. Operands are encoded as:
+// - `scn.i|o  TYPE,a16[IDX]` - 4 bytes: opcode, `TYPE` as `u16` and `IDX` as `u5` followed by
+//   three zero bits;
+// - `pld.i|o  TYPE,a16[SRC],r512[DST1],r512[DST2]` - 5 bytes: opcode, `TYPE` as `u16`, `SRC`,
+//   `DST1` and `DST2` as `u5` followed by a zero bit.
+const INSTR_SCNI: u8 = 0b10_100_100;
+const INSTR_SCNO: u8 = 0b10_100_101;
+const INSTR_PLDI: u8 = 0b10_100_110;
+const INSTR_PLDO: u8 = 0b10_100_111;
+
+/// Execution context of the programs produced by the assembler
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Context {
+    /// Values of the program input variables, read by AluRE instructions
+    pub inputs: Inputs,
+    /// Contract state, accessed by RGB instructions
+    pub state: ContractState,
+}
+
+impl From<Inputs> for Context {
+    fn from(inputs: Inputs) -> Self { Context { inputs, state: ContractState::default() } }
+}
+
+/// Operations of ISA extensions supported by the assembler
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, From)]
+pub enum ExtOp {
+    #[from]
+    AluRe(AluReOp),
+
+    #[from]
+    Rgb(RgbOp),
+}
+
+impl ExtOp {
+    /// Index of the input variable read by the instruction, if any
+    pub fn var_mut(&mut self) -> Option<&mut u16> {
+        match self {
+            ExtOp::AluRe(op) => Some(op.var_mut()),
+            ExtOp::Rgb(_) => None,
+        }
+    }
+}
+
+impl Display for ExtOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtOp::AluRe(op) => Display::fmt(op, f),
+            ExtOp::Rgb(op) => Display::fmt(op, f),
+        }
+    }
+}
+
+impl Bytecode for ExtOp {
+    fn byte_count(&self) -> u16 {
+        match self {
+            ExtOp::AluRe(op) => op.byte_count(),
+            ExtOp::Rgb(op) => op.byte_count(),
+        }
+    }
+
+    #[inline]
+    fn instr_range() -> RangeInclusive<u8> {
+        *AluReOp::instr_range().start()..=*RgbOp::instr_range().end()
+    }
+
+    fn instr_byte(&self) -> u8 {
+        match self {
+            ExtOp::AluRe(op) => op.instr_byte(),
+            ExtOp::Rgb(op) => op.instr_byte(),
+        }
+    }
+
+    fn encode_args<W>(&self, writer: &mut W) -> Result<(), BytecodeError>
+    where
+        W: Write,
+    {
+        match self {
+            ExtOp::AluRe(op) => op.encode_args(writer),
+            ExtOp::Rgb(op) => op.encode_args(writer),
+        }
+    }
+
+    fn decode<R>(reader: &mut R) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: Read,
+    {
+        let instr = reader.peek_u8()?;
+        Ok(if AluReOp::instr_range().contains(&instr) {
+            ExtOp::AluRe(AluReOp::decode(reader)?)
+        } else {
+            ExtOp::Rgb(RgbOp::decode(reader)?)
+        })
+    }
+}
+
+impl InstructionSet for ExtOp {
+    type Context<'ctx> = Context;
+
+    fn isa_ids() -> BTreeSet<&'static str> {
+        let mut ids = AluReOp::isa_ids();
+        ids.extend(RgbOp::isa_ids());
+        ids
+    }
+
+    fn src_regs(&self) -> BTreeSet<Reg> {
+        match self {
+            ExtOp::AluRe(op) => op.src_regs(),
+            ExtOp::Rgb(op) => op.src_regs(),
+        }
+    }
+
+    fn dst_regs(&self) -> BTreeSet<Reg> {
+        match self {
+            ExtOp::AluRe(op) => op.dst_regs(),
+            ExtOp::Rgb(op) => op.dst_regs(),
+        }
+    }
+
+    fn complexity(&self) -> u64 {
+        match self {
+            ExtOp::AluRe(op) => op.complexity(),
+            ExtOp::Rgb(op) => op.complexity(),
+        }
+    }
+
+    fn exec(&self, regs: &mut CoreRegs, site: LibSite, context: &Context) -> ExecStep {
+        match self {
+            ExtOp::AluRe(op) => op.exec(regs, site, &context.inputs),
+            ExtOp::Rgb(op) => op.exec(regs, site, &context.state),
+        }
+    }
+}
+
+/// Operations of AluRE ISA extension
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum AluReOp {
+    /// Reads value of the input variable with the given index into arithmetic register
+    ReadA(RegA, Reg32, u16),
+
+    /// Reads value of the input variable with the given index into float register
+    ReadF(RegF, Reg32, u16),
+
+    /// Reads value of the input variable with the given index into general register
+    ReadR(RegR, Reg32, u16),
+
+    /// Reads value of the input variable with the given index into byte string register
+    ReadS(RegS, u16),
+}
+
+impl AluReOp {
+    /// Index of the input variable read by the instruction
+    pub fn var_mut(&mut self) -> &mut u16 {
+        match self {
+            AluReOp::ReadA(_, _, var)
+            | AluReOp::ReadF(_, _, var)
+            | AluReOp::ReadR(_, _, var)
+            | AluReOp::ReadS(_, var) => var,
+        }
+    }
+}
+
+impl Display for AluReOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AluReOp::ReadA(reg, idx, var) => write!(f, "read    {}{},${}", reg, idx, var),
+            AluReOp::ReadF(reg, idx, var) => write!(f, "read    {}{},${}", reg, idx, var),
+            AluReOp::ReadR(reg, idx, var) => write!(f, "read    {}{},${}", reg, idx, var),
+            AluReOp::ReadS(idx, var) => write!(f, "read    s16{},${}", idx, var),
+        }
+    }
+}
+
+impl Bytecode for AluReOp {
+    #[inline]
+    fn byte_count(&self) -> u16 { 4 }
+
+    #[inline]
+    fn instr_range() -> RangeInclusive<u8> { INSTR_READA..=INSTR_READS }
+
+    fn instr_byte(&self) -> u8 {
+        match self {
+            AluReOp::ReadA(_, _, _) => INSTR_READA,
+            AluReOp::ReadF(_, _, _) => INSTR_READF,
+            AluReOp::ReadR(_, _, _) => INSTR_READR,
+            AluReOp::ReadS(_, _) => INSTR_READS,
+        }
+    }
+
+    fn encode_args<W>(&self, writer: &mut W) -> Result<(), BytecodeError>
+    where
+        W: Write,
+    {
+        match *self {
+            AluReOp::ReadA(reg, idx, var) => {
+                writer.write_u3(reg)?;
+                writer.write_u5(idx)?;
+                writer.write_u16(var)?;
+            }
+            AluReOp::ReadF(reg, idx, var) => {
+                writer.write_u3(reg)?;
+                writer.write_u5(idx)?;
+                writer.write_u16(var)?;
+            }
+            AluReOp::ReadR(reg, idx, var) => {
+                writer.write_u3(reg)?;
+                writer.write_u5(idx)?;
+                writer.write_u16(var)?;
+            }
+            AluReOp::ReadS(idx, var) => {
+                writer.write_u4(idx)?;
+                writer.write_u4(u4::with(0))?;
+                writer.write_u16(var)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode<R>(reader: &mut R) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: Read,
+    {
+        // Opcodes are contiguous, so any byte is decoded by its lowest bits
+        Ok(match INSTR_READA | (reader.read_u8()? & 0b11) {
+            INSTR_READA => AluReOp::ReadA(
+                reader.read_u3()?.into(),
+                reader.read_u5()?.into(),
+                reader.read_u16()?,
+            ),
+            INSTR_READF => AluReOp::ReadF(
+                reader.read_u3()?.into(),
+                reader.read_u5()?.into(),
+                reader.read_u16()?,
+            ),
+            INSTR_READR => AluReOp::ReadR(
+                reader.read_u3()?.into(),
+                reader.read_u5()?.into(),
+                reader.read_u16()?,
+            ),
+            _ => {
+                let idx = reader.read_u4()?.into();
+                reader.read_u4()?;
+                AluReOp::ReadS(idx, reader.read_u16()?)
+            }
+        })
+    }
+}
+
+impl InstructionSet for AluReOp {
+    type Context<'ctx> = Inputs;
+
+    #[inline]
+    fn isa_ids() -> BTreeSet<&'static str> { bset!["ALURE"] }
+
+    #[inline]
+    fn src_regs(&self) -> BTreeSet<Reg> { bset![] }
+
+    fn dst_regs(&self) -> BTreeSet<Reg> {
+        bset![match *self {
+            AluReOp::ReadA(reg, idx, _) => Reg::A(reg, idx),
+            AluReOp::ReadF(reg, idx, _) => Reg::F(reg, idx),
+            AluReOp::ReadR(reg, idx, _) => Reg::R(reg, idx),
+            AluReOp::ReadS(idx, _) => Reg::S(idx),
+        }]
+    }
+
+    #[inline]
+    fn complexity(&self) -> u64 { 1 }
+
+    fn exec(&self, regs: &mut CoreRegs, _site: LibSite, inputs: &Inputs) -> ExecStep {
+        let value = |var: u16| match inputs.get(var as usize) {
+            Some(DataType::Int(_, val) | DataType::Float(_, val)) => *val,
+            _ => MaybeNumber::none(),
+        };
+        match *self {
+            AluReOp::ReadA(reg, idx, var) => {
+                regs.set_n(reg, idx, value(var));
+            }
+            AluReOp::ReadF(reg, idx, var) => {
+                regs.set_n(reg, idx, value(var));
+            }
+            AluReOp::ReadR(reg, idx, var) => {
+                regs.set_n(reg, idx, value(var));
+            }
+            AluReOp::ReadS(idx, var) => {
+                let value = match inputs.get(var as usize) {
+                    Some(DataType::ByteStr(Some(bytes))) => Some(ByteStr::with(bytes)),
+                    _ => None,
+                };
+                regs.set_s(idx, value);
+            }
+        }
+        ExecStep::Next
+    }
+}
+
+/// Contract state accessible to RGB validation scripts. The state is a list of typed items, each
+/// carrying a pair of 512-bit values, separately for the state spent and assigned by the
+/// validated operation; `scn` instructions count items of a type and `pld` load them by their
+/// index among the items of that type.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct ContractState {
+    /// State assigned by the previous operations and spent by the validated operation
+    pub inputs: Vec<StateItem>,
+    /// State assigned by the validated operation
+    pub outputs: Vec<StateItem>,
+}
+
+/// Single item of the contract state
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct StateItem {
+    /// Type of the state, matching the type operand of `scn` and `pld` instructions
+    pub ty: u16,
+    /// State data as a pair of 512-bit values, like a Pedersen commitment and its blinding
+    /// factor, loaded by `pld` instruction into two `r512` registers
+    pub data: [MaybeNumber; 2],
+}
+
+/// Direction of the contract state accessed by RGB operations
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum StateFlag {
+    /// State spent by the operation (`.i` flag)
+    #[display("i")]
+    Input,
+
+    /// State assigned by the operation (`.o` flag)
+    #[display("o")]
+    Output,
+}
+
+impl StateFlag {
+    #[inline]
+    fn select(self, state: &ContractState) -> impl Iterator<Item = &StateItem> {
+        match self {
+            StateFlag::Input => state.inputs.iter(),
+            StateFlag::Output => state.outputs.iter(),
+        }
+    }
+}
+
+/// Operations of RGB ISA extension
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum RgbOp {
+    /// Counts number of state items of the given type and puts it into `a16` register
+    Scn(StateFlag, u16, Reg32),
+
+    /// Loads data of the state item of the given type, which index is taken from `a16` register,
+    /// into a pair of `r512` registers. Fails if the index register is not set or the item is
+    /// absent.
+    Pld(StateFlag, u16, Reg32, Reg32, Reg32),
+}
+
+impl Display for RgbOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RgbOp::Scn(flag, ty, idx) => write!(f, "scn.{}   {},a16{}", flag, ty, idx),
+            RgbOp::Pld(flag, ty, src, dst1, dst2) => {
+                write!(f, "pld.{}   {},a16{},r512{},r512{}", flag, ty, src, dst1, dst2)
+            }
+        }
+    }
+}
+
+impl Bytecode for RgbOp {
+    fn byte_count(&self) -> u16 {
+        match self {
+            RgbOp::Scn(_, _, _) => 4,
+            RgbOp::Pld(_, _, _, _, _) => 5,
+        }
+    }
+
+    #[inline]
+    fn instr_range() -> RangeInclusive<u8> { INSTR_SCNI..=INSTR_PLDO }
+
+    fn instr_byte(&self) -> u8 {
+        match self {
+            RgbOp::Scn(StateFlag::Input, _, _) => INSTR_SCNI,
+            RgbOp::Scn(StateFlag::Output, _, _) => INSTR_SCNO,
+            RgbOp::Pld(StateFlag::Input, _, _, _, _) => INSTR_PLDI,
+            RgbOp::Pld(StateFlag::Output, _, _, _, _) => INSTR_PLDO,
+        }
+    }
+
+    fn encode_args<W>(&self, writer: &mut W) -> Result<(), BytecodeError>
+    where
+        W: Write,
+    {
+        match *self {
+            RgbOp::Scn(_, ty, idx) => {
+                writer.write_u16(ty)?;
+                writer.write_u5(idx)?;
+                writer.write_u3(u3::with(0))?;
+            }
+            RgbOp::Pld(_, ty, src, dst1, dst2) => {
+                writer.write_u16(ty)?;
+                writer.write_u5(src)?;
+                writer.write_u5(dst1)?;
+                writer.write_u5(dst2)?;
+                writer.write_u1(u1::with(0))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode<R>(reader: &mut R) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: Read,
+    {
+        // Opcodes are contiguous, so any byte is decoded by its lowest bits
+        let instr = INSTR_SCNI | (reader.read_u8()? & 0b11);
+        let flag = match instr {
+            INSTR_SCNI | INSTR_PLDI => StateFlag::Input,
+            _ => StateFlag::Output,
+        };
+        let ty = reader.read_u16()?;
+        Ok(match instr {
+            INSTR_SCNI | INSTR_SCNO => {
+                let idx = reader.read_u5()?.into();
+                reader.read_u3()?;
+                RgbOp::Scn(flag, ty, idx)
+            }
+            _ => {
+                let src = reader.read_u5()?.into();
+                let dst1 = reader.read_u5()?.into();
+                let dst2 = reader.read_u5()?.into();
+                reader.read_u1()?;
+                RgbOp::Pld(flag, ty, src, dst1, dst2)
+            }
+        })
+    }
+}
+
+impl InstructionSet for RgbOp {
+    type Context<'ctx> = ContractState;
+
+    #[inline]
+    fn isa_ids() -> BTreeSet<&'static str> { bset!["RGB"] }
+
+    fn src_regs(&self) -> BTreeSet<Reg> {
+        match *self {
+            RgbOp::Scn(_, _, _) => bset![],
+            RgbOp::Pld(_, _, src, _, _) => bset![Reg::A(RegA::A16, src)],
+        }
+    }
+
+    fn dst_regs(&self) -> BTreeSet<Reg> {
+        match *self {
+            RgbOp::Scn(_, _, idx) => bset![Reg::A(RegA::A16, idx)],
+            RgbOp::Pld(_, _, _, dst1, dst2) => {
+                bset![Reg::R(RegR::R512, dst1), Reg::R(RegR::R512, dst2)]
+            }
+        }
+    }
+
+    #[inline]
+    fn complexity(&self) -> u64 { 2 }
+
+    fn exec(&self, regs: &mut CoreRegs, _site: LibSite, state: &ContractState) -> ExecStep {
+        match *self {
+            RgbOp::Scn(flag, ty, idx) => {
+                let count = flag.select(state).filter(|item| item.ty == ty).count();
+                regs.set_n(RegA::A16, idx, MaybeNumber::from(Number::from(count as u16)));
+            }
+            RgbOp::Pld(flag, ty, src, dst1, dst2) => {
+                let no = Option::<Number>::from(regs.get_n(RegA::A16, src))
+                    .and_then(|no| u16::try_from(no).ok());
+                let item = no.and_then(|no| {
+                    flag.select(state).filter(|item| item.ty == ty).nth(no as usize)
+                });
+                let item = match item {
+                    Some(item) => item,
+                    None => return ExecStep::Fail,
+                };
+                regs.set_n(RegR::R512, dst1, item.data[0]);
+                regs.set_n(RegR::R512, dst2, item.data[1]);
+            }
+        }
+        ExecStep::Next
+    }
+}
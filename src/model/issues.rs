@@ -6,19 +6,24 @@
 // for Pandora Core AG
 
 use std::fmt::{self, Debug, Display, Formatter, Write};
+use std::ops::Range;
+use std::str::FromStr;
 use std::string::FromUtf8Error;
 
+use aluvm::data::FloatLayout;
 use aluvm::isa::{BytecodeError, ParseFlagError};
 use aluvm::library::{LibId, LibSegOverflow, WriteError};
 use aluvm::reg::RegBlock;
-use aluvm::Isa;
 use baid58::Baid58ParseError;
 use pest::iterators::Pair;
 use pest::Span;
+#[cfg(feature = "json")]
+use serde_json::{json, Value};
 
-use crate::ast::Operator;
+use crate::ast::{ExprOp, Operator};
 use crate::module::CallTableError;
 use crate::parser::Rule;
+use crate::source::SourceMap;
 
 pub trait Issue: Debug + Display {
     fn errno(&self) -> u16;
@@ -26,6 +31,9 @@ pub trait Issue: Debug + Display {
 }
 
 pub trait Stage {
+    /// Name of the stage as it is reported in diagnostics
+    const NAME: &'static str;
+
     type Error: std::error::Error + Issue;
     type Warning: Issue;
 }
@@ -38,16 +46,22 @@ pub struct Compile;
 pub struct Linking;
 
 impl Stage for Analyze {
+    const NAME: &'static str = "Analyze";
+
     type Error = SyntaxError;
     type Warning = SyntaxWarning;
 }
 
 impl Stage for Compile {
+    const NAME: &'static str = "Compile";
+
     type Error = SemanticError;
     type Warning = SemanticWarning;
 }
 
 impl Stage for Linking {
+    const NAME: &'static str = "Linking";
+
     type Error = ReferenceError;
     type Warning = ReferenceWarning;
 }
@@ -93,6 +107,49 @@ pub enum SyntaxError {
 
     /// re-definition of `{0}` input variable
     RepeatedVarName(String),
+
+    /// re-definition of `{0}` macro
+    RepeatedMacroName(String),
+
+    /// macro name `{0}` clashes with the instruction mnemonic
+    MacroNameReserved(String),
+
+    /// macro `{name}` requires {expected} arguments, while {found} were provided
+    MacroArgCount { name: String, expected: usize, found: usize },
+
+    /// macro `{0}` can't be invoked with flags
+    MacroFlags(String),
+
+    /// file `{0}` is included into itself, directly or via other included files
+    IncludeCycle(String),
+
+    /// undefined constant `{0}`; constants used in expressions of `.CONST` segment must be
+    /// defined before them
+    ConstUndefined(String),
+
+    /// constant `{name}` is {found}, while expressions may use integer constants only
+    ExprNotInt { name: String, found: &'static str },
+
+    /// result of `{0}` operation in constant expression does not fit 1024-bit unsigned integer
+    ExprOverflow(ExprOp),
+
+    /// division by zero in constant expression
+    ExprDivZero,
+
+    /// unknown escape sequence `{0}`
+    InvalidEscape(String),
+
+    /// string literal `{0}` is not a valid UTF-8 string; use byte string literal instead
+    StrNotUtf8(String),
+
+    /// byte string literal `{0}` must contain even number of hex digits
+    InvalidBytesLiteral(String),
+
+    /// integer literal `{0}` does not fit into its type
+    IntOutOfType(String),
+
+    /// re-definition of `{0}` test
+    TestNameReuse(String),
 }
 
 #[derive(Clone, Debug, Display, Error, From)]
@@ -146,9 +203,6 @@ pub enum SemanticError {
     #[display(inner)]
     LibError(CallTableError),
 
-    /// call to an unknown routine `{0}`
-    RoutineUnknown(String),
-
     /// number of routines in single module exceeds maximal value
     RoutinesOverflow,
 
@@ -167,6 +221,39 @@ pub enum SemanticError {
     ///
     /// details: {1}
     VarValueNotUtf8(String, FromUtf8Error),
+
+    /// default value for input variable `{0}` overflows {1:?} float layout
+    VarDefaultOverflow(String, FloatLayout),
+
+    /// input variables of tapered float type can't have default value (variable `{0}`)
+    VarDefaultTapered(String),
+
+    /// literal `{lit}` does not fit into {reg} register
+    LiteralOutOfRange { lit: String, reg: String },
+
+    /// type of literal `{lit}` does not match {reg} register
+    LiteralTypeMismatch { lit: String, reg: String },
+
+    /// default value for input variable `{0}` does not fit into the variable type
+    VarDefaultOutOfRange(String),
+
+    /// reading undeclared input variable `{0}`
+    VarUnknown(String),
+
+    /// type of input variable `{0}` does not match the register it is read into
+    VarRegMismatch(String),
+
+    /// operator `{operator}` requires `{isae}` ISA extension to be declared in `.ISAE` segment
+    IsaeNotDeclared { operator: Operator, isae: &'static str },
+
+    /// operator `{operator}` takes at most {expected} operands
+    OperandExcess { operator: Operator, expected: u8 },
+
+    /// operator `{0}` is not supported by any of the ISA plugins used by the compiler
+    MnemonicUnsupported(Operator),
+
+    /// jump to label `{label}` which is not defined in `{routine}` routine
+    LabelUnknown { label: String, routine: String },
 }
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error, From)]
@@ -186,6 +273,29 @@ pub enum ReferenceError {
     /// none of the provided object files contains `{routine}` routine which is called from
     /// `{module}` module; consider adding more -O=path or -o=obj_file arguments
     ModulesNoRoutine { routine: String, module: String },
+
+    /// routine `{routine}` is defined both in `{first}` and `{second}` modules
+    RoutineRedefined { routine: String, first: String, second: String },
+
+    /// code of `{0}` module does not fit into the maximum code segment length when joined with
+    /// other modules
+    CodeLengthOverflow(String),
+
+    /// data of `{0}` module does not fit into the maximum data segment length when joined with
+    /// other modules
+    DataLengthOverflow(String),
+
+    /// joined list of ISA extensions used by the modules exceeds maximum ISA segment length
+    IsaeLengthOverflow,
+
+    /// joined list of external libraries used by the modules exceeds maximum library segment
+    /// length
+    #[from(LibSegOverflow)]
+    LibsLengthOverflow,
+
+    #[from]
+    #[display(inner)]
+    CallTable(CallTableError),
 }
 
 impl Issue for SyntaxError {
@@ -204,6 +314,20 @@ impl Issue for SyntaxError {
             SyntaxError::WrongLibId(_, _) => 2012,
             SyntaxError::RepeatedConstName(_) => 2013,
             SyntaxError::RepeatedVarName(_) => 2014,
+            SyntaxError::RepeatedMacroName(_) => 2015,
+            SyntaxError::MacroNameReserved(_) => 2016,
+            SyntaxError::MacroArgCount { .. } => 2017,
+            SyntaxError::MacroFlags(_) => 2018,
+            SyntaxError::IncludeCycle(_) => 2019,
+            SyntaxError::ConstUndefined(_) => 2020,
+            SyntaxError::ExprNotInt { .. } => 2021,
+            SyntaxError::ExprOverflow(_) => 2022,
+            SyntaxError::ExprDivZero => 2023,
+            SyntaxError::InvalidEscape(_) => 2024,
+            SyntaxError::StrNotUtf8(_) => 2025,
+            SyntaxError::InvalidBytesLiteral(_) => 2026,
+            SyntaxError::IntOutOfType(_) => 2027,
+            SyntaxError::TestNameReuse(_) => 2032,
         }
     }
 
@@ -241,12 +365,22 @@ impl Issue for SemanticError {
                 CallTableError::RoutineNotFound(_, _) => 4022,
                 CallTableError::TooManyLibs => 4023,
             },
-            SemanticError::RoutineUnknown(_) => 4024,
             SemanticError::RoutinesOverflow => 4025,
             SemanticError::VarNameLongInfo(_, _) => 4026,
             SemanticError::VarWrongDefault(_) => 4027,
             SemanticError::VarInfoNotUtf8(_, _) => 4028,
             SemanticError::VarValueNotUtf8(_, _) => 4029,
+            SemanticError::VarDefaultOverflow(_, _) => 4031,
+            SemanticError::VarDefaultTapered(_) => 4032,
+            SemanticError::LiteralOutOfRange { .. } => 4033,
+            SemanticError::LiteralTypeMismatch { .. } => 4034,
+            SemanticError::VarDefaultOutOfRange(_) => 4035,
+            SemanticError::VarUnknown(_) => 4036,
+            SemanticError::VarRegMismatch(_) => 4037,
+            SemanticError::IsaeNotDeclared { .. } => 4038,
+            SemanticError::OperandExcess { .. } => 4039,
+            SemanticError::MnemonicUnsupported(_) => 4040,
+            SemanticError::LabelUnknown { .. } => 4042,
         }
     }
 
@@ -261,7 +395,13 @@ impl Issue for ReferenceError {
             ReferenceError::LibraryAbsent(_, _) => 8003,
             ReferenceError::LibraryNoRoutine { .. } => 8004,
             ReferenceError::ModulesNoRoutine { .. } => 8005,
-            // continue from 8007
+            ReferenceError::RoutineRedefined { .. } => 8007,
+            ReferenceError::CodeLengthOverflow(_) => 8008,
+            ReferenceError::DataLengthOverflow(_) => 8009,
+            ReferenceError::IsaeLengthOverflow => 8010,
+            ReferenceError::LibsLengthOverflow => 8011,
+            ReferenceError::CallTable(_) => 8012,
+            // continue from 8013
         }
     }
 
@@ -295,12 +435,46 @@ impl From<BytecodeError> for SemanticError {
 #[display(doc_comments)]
 pub enum SyntaxWarning {
     /// duplicated ISA extension declaration for `{0}`
-    DuplicatedIsa(Isa),
+    DuplicatedIsa(String),
+
+    /// constant `{0}` is never used
+    ConstNotUsed(String),
+
+    /// input variable `{0}` is never read
+    VarNotUsed(String),
+
+    /// library `{0}` is never called
+    LibNotUsed(String),
+
+    /// routine `{0}` is never called
+    RoutineNotUsed(String),
 }
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 #[display(doc_comments)]
-pub enum SemanticWarning {}
+pub enum SemanticWarning {
+    /// default value for input variable `{0}` can't be exactly represented in {1:?} float layout
+    /// and is rounded
+    VarDefaultInexact(String, FloatLayout),
+
+    /// ISA extension `{0}` is declared in `.ISAE` segment but none of its instructions is used
+    IsaeNotUsed(String),
+
+    /// label `{0}` is never used as a jump target
+    LabelNotUsed(String),
+
+    /// execution of routine `{0}` may reach its end without `ret`, `succ` or `fail` instruction
+    RoutineNoReturn(String),
+
+    /// unreachable code in routine `{0}`
+    CodeUnreachable(String),
+
+    /// routine `{0}` is not defined in the module and will be resolved at link time
+    RoutineExtern(String),
+
+    /// integer literal `{lit}` can't be exactly represented as {reg} and is rounded
+    LiteralInexact { lit: String, reg: String },
+}
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error, From)]
 #[display(doc_comments)]
@@ -308,15 +482,16 @@ pub enum ReferenceWarning {
     /// building library from an object file containing `.MAIN` routine; consider building binary
     /// by using --bin option
     LibraryWithMain,
-
-    /// library {0} is not used anywhere in the code
-    LibraryNotUsed(LibId),
 }
 
 impl Issue for SyntaxWarning {
     fn errno(&self) -> u16 {
         match self {
             SyntaxWarning::DuplicatedIsa(_) => 2010,
+            SyntaxWarning::ConstNotUsed(_) => 2028,
+            SyntaxWarning::VarNotUsed(_) => 2029,
+            SyntaxWarning::LibNotUsed(_) => 2030,
+            SyntaxWarning::RoutineNotUsed(_) => 2031,
         }
     }
 
@@ -325,7 +500,17 @@ impl Issue for SyntaxWarning {
 }
 
 impl Issue for SemanticWarning {
-    fn errno(&self) -> u16 { 0 }
+    fn errno(&self) -> u16 {
+        match self {
+            SemanticWarning::VarDefaultInexact(_, _) => 4030,
+            SemanticWarning::IsaeNotUsed(_) => 4041,
+            SemanticWarning::LabelNotUsed(_) => 4043,
+            SemanticWarning::RoutineNoReturn(_) => 4044,
+            SemanticWarning::CodeUnreachable(_) => 4045,
+            SemanticWarning::RoutineExtern(_) => 4046,
+            SemanticWarning::LiteralInexact { .. } => 4047,
+        }
+    }
 
     #[inline]
     fn is_error(&self) -> bool { false }
@@ -335,7 +520,6 @@ impl Issue for ReferenceWarning {
     fn errno(&self) -> u16 {
         match self {
             ReferenceWarning::LibraryWithMain => 8002,
-            ReferenceWarning::LibraryNotUsed(_) => 8006,
         }
     }
 
@@ -350,6 +534,8 @@ where
 {
     errors: Vec<(S::Error, Option<Src<'i>>)>,
     warnings: Vec<(S::Warning, Option<Src<'i>>)>,
+    sources: Option<&'i SourceMap>,
+    expansion: Option<Span<'i>>,
 }
 
 impl<'i, S> Default for Issues<'i, S>
@@ -357,7 +543,9 @@ where
     S: Stage,
 {
     #[inline]
-    fn default() -> Self { Issues { errors: vec![], warnings: vec![] } }
+    fn default() -> Self {
+        Issues { errors: vec![], warnings: vec![], sources: None, expansion: None }
+    }
 }
 
 impl<'i, S> Display for Issues<'i, S>
@@ -395,11 +583,18 @@ impl<'i, S> Issues<'i, S>
 where
     S: Stage,
 {
+    /// Constructs issue list which reports file names of the issues from the provided sources
+    pub fn with_sources(sources: &'i SourceMap) -> Self {
+        Issues { sources: Some(sources), ..Issues::default() }
+    }
+
     pub fn push_error(&mut self, error: S::Error, span: &impl ToSrc<'i>) {
-        self.errors.push((error, Some(span.to_src())));
+        let src = self.src(span);
+        self.errors.push((error, Some(src)));
     }
     pub fn push_warning(&mut self, warning: S::Warning, span: &impl ToSrc<'i>) {
-        self.warnings.push((warning, Some(span.to_src())));
+        let src = self.src(span);
+        self.warnings.push((warning, Some(src)));
     }
     pub fn push_error_nospan(&mut self, error: S::Error) { self.errors.push((error, None)); }
     pub fn push_warning_nospan(&mut self, warning: S::Warning) {
@@ -408,6 +603,46 @@ where
     pub fn has_errors(&self) -> bool { !self.errors.is_empty() }
     pub fn count_errors(&self) -> usize { self.errors.len() }
     pub fn count_warnings(&self) -> usize { self.warnings.len() }
+
+    /// Returns structured representation of all errors followed by all warnings
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let errors = self.errors.iter().map(|(error, src)| Diagnostic::with::<S>(error, src));
+        let warnings =
+            self.warnings.iter().map(|(warning, src)| Diagnostic::with::<S>(warning, src));
+        errors.chain(warnings).collect()
+    }
+
+    /// Renders issues in the requested format: as human-readable text or as JSON diagnostics, one
+    /// per line
+    pub fn render(&self, format: MessageFormat) -> String {
+        match format {
+            MessageFormat::Human => self.to_string(),
+            #[cfg(feature = "json")]
+            MessageFormat::Json => {
+                self.diagnostics().iter().map(|diagnostic| diagnostic.to_json() + "\n").collect()
+            }
+        }
+    }
+
+    /// Makes all subsequently reported issues to point also to the macro invocation at `site`
+    pub fn set_expansion(&mut self, site: Option<Span<'i>>) { self.expansion = site; }
+
+    fn src(&self, span: &impl ToSrc<'i>) -> Src<'i> {
+        let mut src = span.to_src();
+        if src.file.is_none() {
+            src.file = self.file_name(&src.span);
+        }
+        if src.expansion.is_none() {
+            src.expansion = self.expansion.map(|site| {
+                Box::new(Src { span: site, file: self.file_name(&site), expansion: None })
+            });
+        }
+        src
+    }
+
+    fn file_name(&self, span: &Span) -> Option<&'i str> {
+        self.sources.and_then(|sources| sources.locate(span)).map(|file| file.name.as_str())
+    }
 }
 
 pub trait ToSrc<'i> {
@@ -416,12 +651,12 @@ pub trait ToSrc<'i> {
 
 impl<'i> ToSrc<'i> for Span<'i> {
     #[inline]
-    fn to_src(&self) -> Src<'i> { Src(*self) }
+    fn to_src(&self) -> Src<'i> { Src::with(*self) }
 }
 
 impl<'i> ToSrc<'i> for &Span<'i> {
     #[inline]
-    fn to_src(&self) -> Src<'i> { Src(*(*self)) }
+    fn to_src(&self) -> Src<'i> { Src::with(*(*self)) }
 }
 
 impl<'i> ToSrc<'i> for Pair<'i, Rule> {
@@ -432,32 +667,54 @@ impl<'i> ToSrc<'i> for Pair<'i, Rule> {
     }
 }
 
+/// Source code location of an issue, optionally accompanied by the location of the macro
+/// invocation which has produced the code
 #[derive(Clone)]
-pub struct Src<'i>(Span<'i>);
+pub struct Src<'i> {
+    span: Span<'i>,
+    file: Option<&'i str>,
+    expansion: Option<Box<Src<'i>>>,
+}
 
 impl<'i> Src<'i> {
     #[inline]
-    pub fn as_span(&self) -> &Span<'i> { &self.0 }
+    pub fn with(span: Span<'i>) -> Self { Src { span, file: None, expansion: None } }
+
+    #[inline]
+    pub fn as_span(&self) -> &Span<'i> { &self.span }
+
+    #[inline]
+    pub fn file(&self) -> Option<&'i str> { self.file }
+
+    #[inline]
+    pub fn expansion(&self) -> Option<&Src<'i>> { self.expansion.as_deref() }
 }
 
 impl<'i> Display for Src<'i> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if f.alternate() {
-            f.write_str(self.0.as_str())?;
+            f.write_str(self.span.as_str())?;
             return Ok(());
         }
 
-        let (line, col) = self.0.start_pos().line_col();
-        writeln!(f, "\n\x1B[1;34m   --> line {}, column {}", line, col)?;
+        let (line, col) = self.span.start_pos().line_col();
+        match self.file {
+            Some(file) => writeln!(f, "\n\x1B[1;34m   --> {}:{}:{}", file, line, col)?,
+            None => writeln!(f, "\n\x1B[1;34m   --> line {}, column {}", line, col)?,
+        }
         writeln!(f, "\x1B[1;34m     |\x1B[0m")?;
-        for (index, s) in self.0.lines().enumerate() {
+        for (index, s) in self.span.lines().enumerate() {
             write!(f, "\x1B[1;34m{:>4} |\x1B[0m {}", line + index, s)?;
             write!(f, "\x1B[1;34m     |\x1B[1;31m{:1$}", "", col)?;
-            for _ in 0..(self.0.end() - self.0.start()) {
+            for _ in 0..(self.span.end() - self.span.start()) {
                 f.write_char('^')?;
             }
             f.write_str("\x1B[0m\n")?;
         }
+        if let Some(site) = &self.expansion {
+            writeln!(f, "\x1B[1;34m     = \x1B[0mnote: in expansion of the macro invoked here")?;
+            Display::fmt(site, f)?;
+        }
         Ok(())
     }
 }
@@ -466,3 +723,131 @@ impl<'i> Debug for Src<'i> {
     #[inline]
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { Display::fmt(self, f) }
 }
+
+/// Severity of a diagnostic message
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum Severity {
+    #[display("error")]
+    Error,
+    #[display("warning")]
+    Warning,
+}
+
+/// Source code location of a diagnostic, independent from the lifetime of the source code
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Location {
+    /// Name of the source file, if known
+    pub file: Option<String>,
+    /// Byte range of the code within the source file
+    pub range: Range<usize>,
+    /// Line of the range start, counting from 1
+    pub line: usize,
+    /// Column of the range start, counting from 1
+    pub column: usize,
+    /// Location of the macro invocation which has produced the code
+    pub expansion: Option<Box<Location>>,
+}
+
+impl<'i> From<&Src<'i>> for Location {
+    fn from(src: &Src<'i>) -> Self {
+        let (line, column) = src.span.start_pos().line_col();
+        Location {
+            file: src.file.map(str::to_owned),
+            range: src.span.start()..src.span.end(),
+            line,
+            column,
+            expansion: src.expansion.as_deref().map(Location::from).map(Box::new),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Location {
+    fn to_json(&self) -> Value {
+        json!({
+            "file": self.file,
+            "range": [self.range.start, self.range.end],
+            "line": self.line,
+            "column": self.column,
+            "expansion": self.expansion.as_deref().map(Location::to_json),
+        })
+    }
+}
+
+/// Structured representation of an issue, which may be consumed by tools processing compiler
+/// output
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Diagnostic {
+    pub errno: u16,
+    pub severity: Severity,
+    /// Name of the stage which has reported the issue (see [`Stage::NAME`])
+    pub stage: &'static str,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Diagnostic {
+    /// Constructs diagnostic from an issue reported at the stage `S`
+    pub fn with<S: Stage>(issue: &impl Issue, src: &Option<Src>) -> Self {
+        Diagnostic {
+            errno: issue.errno(),
+            severity: if issue.is_error() { Severity::Error } else { Severity::Warning },
+            stage: S::NAME,
+            message: issue.to_string(),
+            location: src.as_ref().map(Location::from),
+        }
+    }
+
+    /// Serializes diagnostic as a single-line JSON object. Location fields (`file`, `range`,
+    /// `line`, `column` and `expansion`) are `null` for the issues not related to the source code.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        let mut object = json!({
+            "errno": self.errno,
+            "severity": self.severity.to_string(),
+            "stage": self.stage,
+            "message": self.message,
+            "file": null,
+            "range": null,
+            "line": null,
+            "column": null,
+            "expansion": null,
+        });
+        if let (Some(location), Value::Object(fields)) = (&self.location, &mut object) {
+            if let Value::Object(location) = location.to_json() {
+                fields.extend(location);
+            }
+        }
+        object.to_string()
+    }
+}
+
+/// Format in which command-line tools report diagnostics
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum MessageFormat {
+    /// Colored human-readable text printed to the standard error
+    #[display("human")]
+    Human,
+    /// One JSON object per diagnostic printed to the standard output
+    #[cfg(feature = "json")]
+    #[display("json")]
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = MessageFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            #[cfg(feature = "json")]
+            "json" => Ok(MessageFormat::Json),
+            other => Err(MessageFormatError(other.to_owned())),
+        }
+    }
+}
+
+/// unknown message format `{0}`; use `human` or `json`
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub struct MessageFormatError(String);
@@ -6,6 +6,9 @@
 // for Pandora Core AG
 
 pub mod ast;
+pub mod debug;
+pub mod isa;
 pub mod issues;
 pub mod module;
 pub mod product;
+pub mod source;
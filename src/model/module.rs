@@ -5,7 +5,8 @@
 //     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
 // for Pandora Core AG
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt::{self, Display, Formatter, Write as WriteTrait};
 use std::io::{self, Read, Write};
 use std::string::FromUtf8Error;
@@ -18,6 +19,15 @@ use aluvm::library::{Lib, LibId, LibSegOverflow, LibSite};
 use amplify::hex::format_hex;
 use amplify::IoError;
 
+use crate::debug::DebugInfo;
+
+/// Magic bytes starting object files
+pub const MAGIC_MODULE: [u8; 10] = *b"ALU objct\0";
+
+/// Version of the object file format, written after the magic bytes. Must be increased with each
+/// change of the object file layout.
+pub const MODULE_VERSION: u16 = 1;
+
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error, From)]
 #[display(doc_comments)]
 pub enum CallTableError {
@@ -38,23 +48,18 @@ pub enum CallTableError {
 }
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
-pub struct CallRef {
-    pub routine: String,
-    pub sites: BTreeSet<u16>,
-}
-
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
-pub struct CallTable(BTreeMap<LibId, Vec<CallRef>>);
+pub struct CallTable(BTreeMap<LibId, Vec<String>>);
 
 impl CallTable {
     #[inline]
     pub fn count(&self) -> u16 { self.0.len() as u16 }
 
-    pub fn get_mut(&mut self, site: LibSite) -> Result<&mut CallRef, CallTableError> {
+    pub fn get(&self, site: LibSite) -> Result<&str, CallTableError> {
         self.0
-            .get_mut(&site.lib)
+            .get(&site.lib)
             .ok_or(CallTableError::LibTableNotFound(site.lib))?
-            .get_mut(site.pos as usize)
+            .get(site.pos as usize)
+            .map(String::as_str)
             .ok_or(CallTableError::RoutineNotFound(site.lib, site.pos))
     }
 
@@ -66,35 +71,51 @@ impl CallTable {
             return Err(CallTableError::TooManyLibs);
         }
         let vec = self.0.entry(id).or_default();
-        let pos =
-            vec.iter_mut().position(|callref| callref.routine == routine).unwrap_or_else(|| {
-                let callref = CallRef { routine: routine.to_owned(), sites: bset![] };
-                vec.push(callref);
-                vec.len() - 1
-            });
+        let pos = vec.iter().position(|name| name == routine).unwrap_or_else(|| {
+            vec.push(routine.to_owned());
+            vec.len() - 1
+        });
         Ok(pos as u16)
     }
 
     pub fn routines(&self) -> IntoIter<(LibId, &str)> {
         self.0
             .iter()
-            .flat_map(|(id, routines)| {
-                routines.iter().map(move |call_ref| (*id, call_ref.routine.as_str()))
-            })
+            .flat_map(|(id, routines)| routines.iter().map(move |name| (*id, name.as_str())))
             .collect::<Vec<_>>()
             .into_iter()
     }
+}
 
-    pub fn call_refs(&self) -> IntoIter<(LibId, &str, &BTreeSet<u16>)> {
-        self.0
-            .iter()
-            .flat_map(|(id, routines)| {
-                routines
-                    .iter()
-                    .map(move |call_ref| (*id, call_ref.routine.as_str(), &call_ref.sites))
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
+/// Reference to a symbol which offset must be written into the instruction by the linker
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum Reloc {
+    /// `call` or `exec` of an external library routine, referenced by its call table entry
+    Import(LibSite),
+
+    /// `routine` call to a routine which is not defined in the module and must be provided by
+    /// some other module
+    Extern(String),
+}
+
+/// Relocation table, mapping code offsets of the instructions which must be patched by the linker
+/// to the symbols they are referencing
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
+pub struct RelocTable(BTreeMap<u16, Reloc>);
+
+impl RelocTable {
+    #[inline]
+    pub fn len(&self) -> usize { self.0.len() }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    #[inline]
+    pub fn insert(&mut self, offset: u16, reloc: Reloc) { self.0.insert(offset, reloc); }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Reloc)> {
+        self.0.iter().map(|(offset, reloc)| (*offset, reloc))
     }
 }
 
@@ -107,6 +128,9 @@ pub enum DataType {
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct Variable {
+    /// Name of the variable (without `$` prefix); absent in files produced by assembler versions
+    /// which did not preserve variable names
+    pub name: Option<String>,
     pub info: String,
     pub data: DataType,
 }
@@ -118,21 +142,29 @@ pub struct Module {
     pub imports: CallTable,
     /// Map of local routine names to code offsets
     pub exports: BTreeMap<String, u16>,
+    /// Instructions which must be patched with routine offsets during linking
+    pub relocs: RelocTable,
+    /// Optional debug information mapping code offsets to the source code
+    pub debug: Option<DebugInfo>,
 }
 
 impl Module {
     #[inline]
     pub fn as_static_lib(&self) -> &Lib { &self.inner }
+
+    /// Index of the input variable with the given name (without `$` prefix), which is used by
+    /// `read` instructions to reference the variable
+    #[inline]
+    pub fn var_index(&self, name: &str) -> Option<u16> { var_index(&self.vars, name) }
+
+    /// Removes debug information from the module
+    #[inline]
+    pub fn strip_debug(&mut self) { self.debug = None; }
 }
 
-impl Display for CallRef {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "`{}` called from", self.routine)?;
-        for offset in &self.sites {
-            write!(f, " 0x{:04X}", offset)?;
-        }
-        Ok(())
-    }
+pub(crate) fn var_index(vars: &[Variable], name: &str) -> Option<u16> {
+    let name = name.trim_start_matches('$');
+    vars.iter().position(|var| var.name.as_deref() == Some(name)).map(|pos| pos as u16)
 }
 
 impl Display for CallTable {
@@ -142,9 +174,33 @@ impl Display for CallTable {
                 write!(f, "{:1$}", "", f.width().unwrap_or_default())?;
             }
             writeln!(f, "{}:", lib)?;
-            for call_ref in map {
-                writeln!(f, "{:2$}- {}", "", call_ref, f.width().unwrap_or_default())?;
+            for (index, routine) in map.iter().enumerate() {
+                writeln!(f, "{:3$}- #{} {}", "", index, routine, f.width().unwrap_or_default())?;
+            }
+        }
+        if self.0.is_empty() {
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Reloc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Reloc::Import(site) => write!(f, "call #{} from {}", site.pos, site.lib),
+            Reloc::Extern(routine) => write!(f, "routine {}", routine),
+        }
+    }
+}
+
+impl Display for RelocTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (line, (offset, reloc)) in self.0.iter().enumerate() {
+            if line > 0 {
+                write!(f, "{:1$}", "", f.width().unwrap_or_default())?;
             }
+            writeln!(f, "0x{:04X}\t{}", offset, reloc)?;
         }
         if self.0.is_empty() {
             f.write_char('\n')?;
@@ -179,6 +235,9 @@ impl Display for DataType {
 
 impl Display for Variable {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(name) = &self.name {
+            write!(f, "${}: ", name)?;
+        }
         write!(f, "{}; {}", self.data, self.info)
     }
 }
@@ -192,7 +251,7 @@ impl Display for Module {
             if line > 0 {
                 write!(f, "{:8}", "")?;
             }
-            writeln!(f, "{}", v)?;
+            writeln!(f, "#{}\t{}", line, v)?;
         }
         if self.vars.is_empty() {
             f.write_char('\n')?;
@@ -211,14 +270,31 @@ impl Display for Module {
             f.write_char('\n')?;
         }
 
+        write!(f, "RELOCS: {:8}", self.relocs)?;
+
+        if let Some(debug) = &self.debug {
+            write!(f, "DEBUG:  {:8}", debug)?;
+        }
+
         Ok(())
     }
 }
 
-/// TODO: use in decoding (currently unused, had left after refactoring)
 #[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
 #[display(doc_comments)]
 pub enum ModuleError {
+    #[from]
+    #[display(inner)]
+    Decode(DecodeError),
+
+    /// wrong magic bytes in object file header (expected `{expected}`, got `{found}`); the file
+    /// is not an object file or was produced by an older assembler version and must be recompiled
+    WrongMagic { expected: String, found: String },
+
+    /// object file has format version {0}, while only version {1} is supported; please recompile
+    /// the source code
+    UnsupportedVersion(u16, u16),
+
     /// end of data is reached before the complete module read
     ///
     /// details: {0}
@@ -357,37 +433,82 @@ impl Decode for Variable {
     where
         Self: Sized,
     {
-        Ok(Variable { info: Decode::decode(&mut reader)?, data: Decode::decode(&mut reader)? })
+        Ok(Variable {
+            name: None,
+            info: Decode::decode(&mut reader)?,
+            data: Decode::decode(&mut reader)?,
+        })
     }
 }
 
-impl Encode for CallRef {
+impl Encode for Reloc {
     type Error = EncodeError;
 
     fn encode(&self, mut writer: impl Write) -> Result<usize, Self::Error> {
-        Ok(self.routine.encode(&mut writer)? + MaxLenWord::new(&self.sites).encode(&mut writer)?)
+        match self {
+            Reloc::Import(site) => Ok(0u8.encode(&mut writer)?
+                + site.lib.encode(&mut writer)?
+                + site.pos.encode(&mut writer)?),
+            Reloc::Extern(routine) => Ok(1u8.encode(&mut writer)? + routine.encode(&mut writer)?),
+        }
     }
 }
 
-impl Decode for CallRef {
+impl Decode for Reloc {
     type Error = DecodeError;
 
     fn decode(mut reader: impl Read) -> Result<Self, Self::Error>
     where
         Self: Sized,
     {
-        Ok(CallRef {
-            routine: Decode::decode(&mut reader)?,
-            sites: MaxLenWord::decode(&mut reader)?.release(),
+        Ok(match u8::decode(&mut reader)? {
+            0 => {
+                let lib = LibId::decode(&mut reader)?;
+                Reloc::Import(LibSite::with(u16::decode(&mut reader)?, lib))
+            }
+            1 => Reloc::Extern(Decode::decode(&mut reader)?),
+            unknown => return Err(DecodeError::InvalidBool(unknown)),
         })
     }
 }
 
+impl Encode for RelocTable {
+    type Error = EncodeError;
+
+    fn encode(&self, mut writer: impl Write) -> Result<usize, Self::Error> {
+        let len =
+            u16::try_from(self.0.len()).map_err(|_| EncodeError::ExceedingSize(self.0.len()))?;
+        let mut count = len.encode(&mut writer)?;
+        for (offset, reloc) in &self.0 {
+            count += offset.encode(&mut writer)?;
+            count += reloc.encode(&mut writer)?;
+        }
+        Ok(count)
+    }
+}
+
+impl Decode for RelocTable {
+    type Error = DecodeError;
+
+    fn decode(mut reader: impl Read) -> Result<Self, Self::Error>
+    where
+        Self: Sized,
+    {
+        let len = u16::decode(&mut reader)?;
+        let mut table = bmap! {};
+        for _ in 0..len {
+            table.insert(u16::decode(&mut reader)?, Reloc::decode(&mut reader)?);
+        }
+        Ok(RelocTable(table))
+    }
+}
+
 impl Encode for CallTable {
     type Error = EncodeError;
 
     fn encode(&self, mut writer: impl Write) -> Result<usize, Self::Error> {
-        let len = self.0.len() as u8;
+        let len =
+            u8::try_from(self.0.len()).map_err(|_| EncodeError::ExceedingSize(self.0.len()))?;
         let mut count = len.encode(&mut writer)?;
         for (lib, map) in &self.0 {
             count += lib.encode(&mut writer)?;
@@ -417,25 +538,113 @@ impl Encode for Module {
     type Error = EncodeError;
 
     fn encode(&self, mut writer: impl Write) -> Result<usize, Self::Error> {
-        Ok(self.inner.encode(&mut writer)?
+        writer.write_all(&MAGIC_MODULE)?;
+        Ok(10
+            + MODULE_VERSION.encode(&mut writer)?
+            + self.inner.encode(&mut writer)?
             + self.imports.encode(&mut writer)?
             + MaxLenWord::new(&self.exports).encode(&mut writer)?
-            + MaxLenWord::new(&self.vars).encode(&mut writer)?)
+            + MaxLenWord::new(&self.vars).encode(&mut writer)?
+            + self.relocs.encode(&mut writer)?
+            + encode_sections(&self.vars, &self.debug, &mut writer)?)
     }
 }
 
 impl Decode for Module {
-    type Error = DecodeError;
+    type Error = ModuleError;
 
     fn decode(mut reader: impl Read) -> Result<Self, Self::Error>
     where
         Self: Sized,
     {
-        Ok(Module {
+        let mut magic = [0u8; 10];
+        reader.read_exact(&mut magic).map_err(DecodeError::from)?;
+        if magic != MAGIC_MODULE {
+            return Err(ModuleError::WrongMagic {
+                expected: String::from_utf8_lossy(&MAGIC_MODULE[..9]).to_string(),
+                found: String::from_utf8_lossy(&magic[..9]).to_string(),
+            });
+        }
+        let version = u16::decode(&mut reader)?;
+        if version != MODULE_VERSION {
+            return Err(ModuleError::UnsupportedVersion(version, MODULE_VERSION));
+        }
+        let mut module = Module {
             inner: Decode::decode(&mut reader)?,
             imports: Decode::decode(&mut reader)?,
             exports: MaxLenWord::decode(&mut reader)?.release(),
             vars: MaxLenWord::decode(&mut reader)?.release(),
-        })
+            relocs: Decode::decode(&mut reader)?,
+            debug: None,
+        };
+        module.debug = decode_sections(&mut module.vars, &mut reader)?;
+        Ok(module)
+    }
+}
+
+/// Tag of the optional section with names of the input variables
+const SECTION_VAR_NAMES: u8 = 1;
+/// Tag of the optional section with the debug information
+const SECTION_DEBUG: u8 = 2;
+
+/// Encodes optional sections: names of the input variables followed by the debug information,
+/// if present. Each section is prefixed with its tag and length and kept at the end of the file,
+/// so readers skip sections they do not know about.
+pub(crate) fn encode_sections(
+    vars: &[Variable],
+    debug: &Option<DebugInfo>,
+    mut writer: impl Write,
+) -> Result<usize, EncodeError> {
+    let names = vars.iter().map(|var| var.name.clone().unwrap_or_default()).collect::<Vec<_>>();
+    let mut count = encode_section(SECTION_VAR_NAMES, &MaxLenWord::new(&names), &mut writer)?;
+    if let Some(debug) = debug {
+        count += encode_section(SECTION_DEBUG, debug, &mut writer)?;
+    }
+    Ok(count)
+}
+
+fn encode_section(
+    tag: u8,
+    section: &impl Encode<Error = EncodeError>,
+    mut writer: impl Write,
+) -> Result<usize, EncodeError> {
+    let mut data = vec![];
+    section.encode(&mut data)?;
+    let len = u32::try_from(data.len()).map_err(|_| EncodeError::ExceedingSize(data.len()))?;
+    let count = tag.encode(&mut writer)? + len.encode(&mut writer)?;
+    writer.write_all(&data)?;
+    Ok(count + data.len())
+}
+
+/// Decodes optional sections at the end of the file, leaving variables unnamed if the section
+/// with their names is absent, and returning debug information if the file has it. Sections with
+/// unknown tags are skipped.
+pub(crate) fn decode_sections(
+    vars: &mut [Variable],
+    mut reader: impl Read,
+) -> Result<Option<DebugInfo>, DecodeError> {
+    let mut debug = None;
+    loop {
+        let mut tag = [0u8; 1];
+        if reader.read(&mut tag)? == 0 {
+            return Ok(debug);
+        }
+        let len = u32::decode(&mut reader)?;
+        let mut data = vec![];
+        reader.by_ref().take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let mut data = &data[..];
+        match tag[0] {
+            SECTION_VAR_NAMES => {
+                let names: Vec<String> = MaxLenWord::decode(&mut data)?.release();
+                for (var, name) in vars.iter_mut().zip(names) {
+                    var.name = if name.is_empty() { None } else { Some(name) };
+                }
+            }
+            SECTION_DEBUG => debug = Some(DebugInfo::decode(&mut data)?),
+            _ => {}
+        }
     }
 }
@@ -12,7 +12,8 @@ use std::io::{self, Read, Write};
 use aluvm::data::encoding::{Decode, DecodeError, Encode, EncodeError, MaxLenWord};
 use aluvm::library::{Lib, LibId};
 
-use crate::module::Variable;
+use crate::debug::DebugInfo;
+use crate::module::{self, decode_sections, encode_sections, Variable};
 
 pub const MAGIC_DYLIB: [u8; 10] = *b"ALU dyLib\0";
 pub const MAGIC_DYBIN: [u8; 10] = *b"ALU dyBin\0";
@@ -43,6 +44,8 @@ pub struct DyInner {
     pub org: String,
     pub(crate) inner: Lib,
     pub vars: Vec<Variable>,
+    /// Optional debug information mapping code offsets to the source code
+    pub debug: Option<DebugInfo>,
 }
 
 impl DyInner {
@@ -51,6 +54,15 @@ impl DyInner {
 
     #[inline]
     pub fn as_static_lib(&self) -> &Lib { &self.inner }
+
+    /// Index of the input variable with the given name (without `$` prefix), allowing runtime
+    /// hosts to supply program inputs by name
+    #[inline]
+    pub fn var_index(&self, name: &str) -> Option<u16> { module::var_index(&self.vars, name) }
+
+    /// Removes debug information from the product
+    #[inline]
+    pub fn strip_debug(&mut self) { self.debug = None; }
 }
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -71,6 +83,15 @@ impl DyLib {
 
     #[inline]
     pub fn as_static_lib(&self) -> &Lib { &self.inner.inner }
+
+    #[inline]
+    pub fn vars(&self) -> &[Variable] { &self.inner.vars }
+
+    #[inline]
+    pub fn var_index(&self, name: &str) -> Option<u16> { self.inner.var_index(name) }
+
+    #[inline]
+    pub fn debug(&self) -> Option<&DebugInfo> { self.inner.debug.as_ref() }
 }
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -91,6 +112,15 @@ impl DyBin {
 
     #[inline]
     pub fn as_static_lib(&self) -> &Lib { &self.inner.inner }
+
+    #[inline]
+    pub fn vars(&self) -> &[Variable] { &self.inner.vars }
+
+    #[inline]
+    pub fn var_index(&self, name: &str) -> Option<u16> { self.inner.var_index(name) }
+
+    #[inline]
+    pub fn debug(&self) -> Option<&DebugInfo> { self.inner.debug.as_ref() }
 }
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -126,6 +156,23 @@ impl Product {
             Product::Bin(bin) => bin.org(),
         }
     }
+
+    #[inline]
+    pub fn debug(&self) -> Option<&DebugInfo> {
+        match self {
+            Product::Lib(lib) => lib.debug(),
+            Product::Bin(bin) => bin.debug(),
+        }
+    }
+
+    /// Removes debug information from the product
+    #[inline]
+    pub fn strip_debug(&mut self) {
+        match self {
+            Product::Lib(lib) => lib.inner.strip_debug(),
+            Product::Bin(bin) => bin.inner.strip_debug(),
+        }
+    }
 }
 
 impl Display for DyInner {
@@ -138,11 +185,15 @@ impl Display for DyInner {
             if line > 0 {
                 write!(f, "{:8}", "")?;
             }
-            writeln!(f, "{}", v)?;
+            writeln!(f, "#{}\t{}", line, v)?;
         }
         if self.vars.is_empty() {
             f.write_char('\n')?;
         }
+
+        if let Some(debug) = &self.debug {
+            write!(f, "DEBUG:  {:8}", debug)?;
+        }
         Ok(())
     }
 }
@@ -222,6 +273,7 @@ impl Decode for DyInner {
             org: Decode::decode(&mut reader)?,
             inner: Decode::decode(&mut reader)?,
             vars: MaxLenWord::decode(&mut reader)?.release(),
+            debug: None,
         })
     }
 }
@@ -234,7 +286,8 @@ impl Encode for DyLib {
         Ok(10
             + self.lib_id().encode(&mut writer)?
             + self.inner.encode(&mut writer)?
-            + MaxLenWord::new(&self.exports).encode(&mut writer)?)
+            + MaxLenWord::new(&self.exports).encode(&mut writer)?
+            + encode_sections(&self.inner.vars, &self.inner.debug, &mut writer)?)
     }
 }
 
@@ -251,7 +304,9 @@ impl Decode for DyLib {
         if inner_id != id {
             return Err(DyError::WrongLibId { library: id, found: inner_id });
         }
-        Ok(DyLib { inner, exports: MaxLenWord::decode(&mut reader)?.release() })
+        let mut lib = DyLib { inner, exports: MaxLenWord::decode(&mut reader)?.release() };
+        lib.inner.debug = decode_sections(&mut lib.inner.vars, &mut reader)?;
+        Ok(lib)
     }
 }
 
@@ -274,7 +329,10 @@ impl Encode for DyBin {
 
     fn encode(&self, mut writer: impl Write) -> Result<usize, Self::Error> {
         writer.write_all(&MAGIC_DYBIN)?;
-        Ok(10 + self.inner.encode(&mut writer)? + self.entry_point.encode(&mut writer)?)
+        Ok(10
+            + self.inner.encode(&mut writer)?
+            + self.entry_point.encode(&mut writer)?
+            + encode_sections(&self.inner.vars, &self.inner.debug, &mut writer)?)
     }
 }
 
@@ -293,7 +351,12 @@ impl Decode for DyBin {
                 found: String::from_utf8(magic[..9].to_owned()).unwrap_or_default(),
             });
         }
-        Ok(DyBin { inner: Decode::decode(&mut reader)?, entry_point: Decode::decode(&mut reader)? })
+        let mut bin = DyBin {
+            inner: Decode::decode(&mut reader)?,
+            entry_point: Decode::decode(&mut reader)?,
+        };
+        bin.inner.debug = decode_sections(&mut bin.inner.vars, &mut reader)?;
+        Ok(bin)
     }
 }
 
@@ -317,19 +380,27 @@ impl Decode for Product {
     {
         let mut magic = [0u8; 10];
         reader.read_exact(&mut magic)?;
-        match magic {
-            bin if bin == MAGIC_DYBIN => Ok(Product::Bin(DyBin {
+        let mut product = match magic {
+            bin if bin == MAGIC_DYBIN => Product::Bin(DyBin {
                 inner: Decode::decode(&mut reader)?,
                 entry_point: Decode::decode(&mut reader)?,
-            })),
-            lib if lib == MAGIC_DYLIB => Ok(Product::Lib(DyLib {
+            }),
+            lib if lib == MAGIC_DYLIB => Product::Lib(DyLib {
                 inner: Decode::decode(&mut reader)?,
                 exports: MaxLenWord::decode(&mut reader)?.release(),
-            })),
-            unknown => Err(DyError::WrongMagic {
-                expected: String::from_utf8(MAGIC_DYBIN.to_vec()).unwrap_or_default(),
-                found: String::from_utf8(unknown[..9].to_owned()).unwrap_or_default(),
             }),
-        }
+            unknown => {
+                return Err(DyError::WrongMagic {
+                    expected: String::from_utf8(MAGIC_DYBIN.to_vec()).unwrap_or_default(),
+                    found: String::from_utf8(unknown[..9].to_owned()).unwrap_or_default(),
+                })
+            }
+        };
+        let inner = match product {
+            Product::Lib(ref mut lib) => &mut lib.inner,
+            Product::Bin(ref mut bin) => &mut bin.inner,
+        };
+        inner.debug = decode_sections(&mut inner.vars, &mut reader)?;
+        Ok(product)
     }
 }
@@ -0,0 +1,197 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Source files database keeping the text of the compiled file and all files included into it
+
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+use pest::{Parser as ParserTrait, Span};
+
+use crate::parser::{Parser, Rule};
+use crate::{BuildError, SourceError};
+
+/// Index of a file in the [`SourceMap`]
+pub type FileId = usize;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SourceFile {
+    /// Lexically normalized path to the file, used to detect repeated includes
+    pub path: PathBuf,
+    /// Name of the file as it is displayed in diagnostic messages
+    pub name: String,
+    pub text: String,
+}
+
+/// Provider of the source file text, allowing to take sources from places other than the file
+/// system, like in-memory maps or network storage
+pub trait Resolver {
+    /// Reads text of the file at `path`. Paths of the included files are resolved relative to the
+    /// path of the file including them.
+    fn read(&self, path: &Path) -> io::Result<String>;
+}
+
+impl<F> Resolver for F
+where
+    F: Fn(&Path) -> io::Result<String>,
+{
+    #[inline]
+    fn read(&self, path: &Path) -> io::Result<String> { self(path) }
+}
+
+/// Resolver reading source files from the disk
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct FsResolver;
+
+impl Resolver for FsResolver {
+    #[inline]
+    fn read(&self, path: &Path) -> io::Result<String> { fs::read_to_string(path) }
+}
+
+/// Set of source files constituting a single program: the root file and all files which are
+/// (directly or indirectly) included into it with `.INCLUDE` directive.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Id of the file from which the program compilation starts
+    pub const ROOT: FileId = 0;
+
+    /// Loads source file from disk together with all files it includes
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SourceError> {
+        SourceMap::load_with(path, &FsResolver)
+    }
+
+    /// Loads source file together with all files it includes using the `resolver`
+    pub fn load_with(path: impl AsRef<Path>, resolver: &dyn Resolver) -> Result<Self, SourceError> {
+        let mut map = SourceMap::default();
+        let path = path.as_ref();
+        map.load_file(normalize(path), path.display().to_string(), resolver)?;
+        Ok(map)
+    }
+
+    /// Constructs source map from in-memory source code, loading all files it includes from the
+    /// disk. The `name` is used as a path for resolving included files.
+    #[inline]
+    pub fn with(name: impl ToString, text: impl ToString) -> Result<Self, SourceError> {
+        SourceMap::with_resolver(name, text, &FsResolver)
+    }
+
+    /// Constructs source map from in-memory source code, loading all files it includes using the
+    /// `resolver`
+    pub fn with_resolver(
+        name: impl ToString,
+        text: impl ToString,
+        resolver: &dyn Resolver,
+    ) -> Result<Self, SourceError> {
+        let mut map = SourceMap::default();
+        let name = name.to_string();
+        map.add(normalize(Path::new(&name)), name, text.to_string(), resolver)?;
+        Ok(map)
+    }
+
+    #[inline]
+    pub fn file(&self, id: FileId) -> &SourceFile { &self.files[id] }
+
+    #[inline]
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> { self.files.iter() }
+
+    /// Finds file by its normalized path
+    pub fn find(&self, path: &Path) -> Option<FileId> {
+        self.files.iter().position(|file| file.path == path)
+    }
+
+    /// Detects the file which text contains the given span
+    pub fn locate(&self, span: &Span) -> Option<&SourceFile> {
+        let ptr = span.as_str().as_ptr() as usize;
+        self.files.iter().find(|file| {
+            let start = file.text.as_ptr() as usize;
+            (start..=start + file.text.len()).contains(&ptr)
+        })
+    }
+
+    /// Resolves normalized path of a file included from the file `from`. Paths are normalized
+    /// lexically, without accessing the file system, since the files may be provided by a custom
+    /// [`Resolver`].
+    pub fn resolve(&self, from: FileId, include: &str) -> (PathBuf, String) {
+        let file = &self.files[from];
+        let dir = |path: &Path| path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let path = dir(&file.path).join(include);
+        let name = dir(Path::new(&file.name)).join(include).display().to_string();
+        (normalize(&path), name)
+    }
+
+    fn load_file(
+        &mut self,
+        path: PathBuf,
+        name: String,
+        resolver: &dyn Resolver,
+    ) -> Result<FileId, SourceError> {
+        let text = resolver.read(&path).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => {
+                BuildError::FileNotFound { file: name.clone(), details: err.into() }
+            }
+            _ => BuildError::FileNoAccess { file: name.clone(), details: err.into() },
+        })?;
+        self.add(path, name, text, resolver)
+    }
+
+    fn add(
+        &mut self,
+        path: PathBuf,
+        name: String,
+        text: String,
+        resolver: &dyn Resolver,
+    ) -> Result<FileId, SourceError> {
+        let includes = Parser::parse(Rule::program, &text)
+            .map_err(|err| SourceError::Parser(name.clone(), err))?
+            .flat_map(|pair| pair.into_inner())
+            .filter(|pair| pair.as_rule() == Rule::include)
+            .filter_map(|pair| pair.into_inner().next())
+            .map(|pair| include_path(pair.as_str()).to_owned())
+            .collect::<Vec<_>>();
+
+        let id = self.files.len();
+        self.files.push(SourceFile { path, name, text });
+
+        for include in includes {
+            let (path, name) = self.resolve(id, &include);
+            if self.find(&path).is_none() {
+                self.load_file(path, name, resolver)?;
+            }
+        }
+        Ok(id)
+    }
+}
+
+/// Normalizes path by removing `.` components and resolving `..` components against the preceding
+/// ones. Leading `..` components, which can't be resolved this way, are kept.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // Parent of the root directory is the root directory itself
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Extracts file path from the string literal of `.INCLUDE` directive
+pub(crate) fn include_path(lit: &str) -> &str { &lit[1..lit.len() - 1] }
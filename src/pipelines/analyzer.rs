@@ -8,50 +8,222 @@
 //! Analyzer code converting abstract parse tree, returned by parser, into an
 //! abstract syntax tree (AST)
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::str::FromStr;
 
 use aluvm::data::{FloatLayout, IntLayout};
+use aluvm::isa::InstructionSet;
 use aluvm::library::LibId;
-use aluvm::reg::{RegA, RegAll, RegBlock, RegF, RegR};
+use aluvm::reg::{Reg32, RegA, RegAll, RegBlock, RegF, RegR};
 use aluvm::Isa;
 use amplify::hex::FromHex;
 use amplify::num::{u1024, u5};
-use pest::iterators::Pair;
+use pest::iterators::{Pair, Pairs};
+use pest::{Parser as ParserTrait, Span};
 
 use crate::ast::{
-    Const, FlagSet, IntBase, Libs, Literal, Operand, Operator, Program, Routine, Statement, Var,
-    VarType,
+    int_fits, Const, Expr, ExprOp, FlagSet, IntBase, Libs, Literal, Macro, Operand, Operator,
+    Program, Routine, Statement, Var, VarType,
 };
+use crate::isa::ExtOp;
 use crate::issues::{self, Issues, SyntaxError, SyntaxWarning, ToSrc};
-use crate::parser::Rule;
+use crate::parser::{Parser, Rule};
+use crate::plugins::IsaTable;
+use crate::source::{include_path, FileId, SourceMap};
 use crate::LexerError;
 
 impl<'i> Program<'i> {
+    /// Analyzes program using mnemonics of the default instruction table
+    #[inline]
     pub fn analyze(
-        pair: Pair<'i, Rule>,
+        sources: &'i SourceMap,
     ) -> Result<(Self, Issues<'i, issues::Analyze>), LexerError<'i>> {
-        let mut issues = Default::default();
+        Program::analyze_with(sources, &IsaTable::<ExtOp>::default())
+    }
+
+    /// Analyzes program recognizing mnemonics of all instructions registered in the `table`
+    pub fn analyze_with<Ext>(
+        sources: &'i SourceMap,
+        table: &IsaTable<Ext>,
+    ) -> Result<(Self, Issues<'i, issues::Analyze>), LexerError<'i>>
+    where
+        Ext: InstructionSet,
+    {
+        let mut issues = Issues::with_sources(sources);
+        let pair = parse_file(sources, SourceMap::ROOT)?;
         let mut program = Program {
+            sources,
             isae: Default::default(),
-            libs: Libs { map: bmap! {}, span: pair.as_span() },
+            libs: Libs { map: bmap! {}, spans: bmap! {}, span: pair.as_span() },
             main: None,
             routines: Default::default(),
+            tests: Default::default(),
+            macros: Default::default(),
             consts: Default::default(),
             input: Default::default(),
+            operators: table.operators(),
+            isa_ids: table.isa_ids(),
         };
-        for pair in pair.into_inner() {
+        let mut included = bset! { SourceMap::ROOT };
+        program.analyze_file(pair, &mut vec![SourceMap::ROOT], &mut included, &mut issues)?;
+        program.verify_usage(&mut issues);
+        program.fold_exprs(&mut issues);
+        Ok((program, issues))
+    }
+
+    /// Reports constants, input variables, libraries and routines which are declared but never
+    /// referenced from the code. Routines are reported only by programs having `.MAIN` routine,
+    /// since routines of other programs are exported to be called by other modules; unused
+    /// libraries are also dropped from the product by the linker. Done before constant
+    /// expressions are folded, since folding removes references to the constants.
+    fn verify_usage(&self, issues: &mut Issues<'i, issues::Analyze>) {
+        let mut names = bset! {};
+        let mut libs = bset! {};
+        let mut routines = bset! {};
+        let bodies = self.routines.values().chain(self.tests.values());
+        for statement in bodies.flat_map(|routine| &routine.statements) {
+            let calls_routine = matches!(statement.operator.0, Operator::routine | Operator::call);
+            for operand in &statement.operands {
+                match operand {
+                    Operand::Const(name, _) => {
+                        names.insert(name.clone());
+                    }
+                    Operand::Expr(expr, _) => expr.collect_consts(&mut names),
+                    Operand::Call { lib, .. } => {
+                        libs.insert(lib.clone());
+                    }
+                    Operand::Goto(name, _) if calls_routine => {
+                        routines.insert(name.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Constants used in the definitions of other used constants are used as well
+        let mut queue = names.iter().cloned().collect::<Vec<_>>();
+        while let Some(name) = queue.pop() {
+            for r in self.consts.get(&name).into_iter().flat_map(|c| &c.refs) {
+                if names.insert(r.clone()) {
+                    queue.push(r.clone());
+                }
+            }
+        }
+
+        for (name, c) in &self.consts {
+            if !names.contains(name) {
+                issues.push_warning(SyntaxWarning::ConstNotUsed(name.clone()), &c.span);
+            }
+        }
+        for (name, var) in &self.input {
+            if !names.contains(name) {
+                issues.push_warning(SyntaxWarning::VarNotUsed(name.clone()), &var.span);
+            }
+        }
+        for name in self.libs.map.keys() {
+            if !libs.contains(name) {
+                let span = self.libs.spans.get(name).unwrap_or(&self.libs.span);
+                issues.push_warning(SyntaxWarning::LibNotUsed(name.clone()), span);
+            }
+        }
+        if self.routines.contains_key(".MAIN") {
+            for (name, routine) in &self.routines {
+                if name != ".MAIN" && !routines.contains(name) {
+                    issues.push_warning(SyntaxWarning::RoutineNotUsed(name.clone()), &routine.span);
+                }
+            }
+        }
+    }
+
+    /// Replaces constant expressions in instruction operands with their values. Done once all
+    /// the sources are analyzed, since operands may refer to constants defined after them.
+    fn fold_exprs(&mut self, issues: &mut Issues<'i, issues::Analyze>) {
+        for routine in self.routines.values_mut().chain(self.tests.values_mut()) {
+            for statement in &mut routine.statements {
+                issues.set_expansion(statement.expansion);
+                for operand in &mut statement.operands {
+                    if let Operand::Expr(expr, span) = operand {
+                        let val = expr.fold(&self.consts, issues).unwrap_or(u1024::MIN);
+                        *operand = Operand::Lit(Literal::uint(val, IntBase::Dec), *span);
+                    }
+                }
+            }
+        }
+        issues.set_expansion(None);
+    }
+}
+
+fn parse_file<'i>(sources: &'i SourceMap, file: FileId) -> Result<Pair<'i, Rule>, LexerError<'i>> {
+    Parser::parse(Rule::program, &sources.file(file).text)
+        .ok()
+        .and_then(|mut pairs| pairs.next())
+        .ok_or(LexerError::ProgramAbsent)
+}
+
+impl<'i> Program<'i> {
+    /// Analyzes segments of a single source file, recursively analyzing files included into it.
+    /// Files from the `stack` are currently being analyzed, and their inclusion would create a
+    /// cycle; files from `included` were already analyzed and are not included for the second
+    /// time.
+    fn analyze_file(
+        &mut self,
+        pair: Pair<'i, Rule>,
+        stack: &mut Vec<FileId>,
+        included: &mut BTreeSet<FileId>,
+        issues: &mut Issues<'i, issues::Analyze>,
+    ) -> Result<(), LexerError<'i>> {
+        let pairs = pair.into_inner();
+        // Included files and macros must be known before routines using them are analyzed
+        for pair in pairs.clone() {
             match pair.as_rule() {
-                Rule::isae => program.analyze_isae(pair, &mut issues)?,
-                Rule::routine => program.analyze_routine(pair, &mut issues)?,
-                Rule::libs => program.analyze_libs(pair, &mut issues)?,
-                Rule::data => program.analyze_const(pair, &mut issues)?,
-                Rule::input => program.analyze_input(pair, &mut issues)?,
+                Rule::include => self.analyze_include(pair, stack, included, issues)?,
+                Rule::macro_def => self.analyze_macro(pair, issues)?,
+                _ => {}
+            }
+        }
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::isae => self.analyze_isae(pair, issues)?,
+                Rule::routine => self.analyze_routine(pair, issues)?,
+                Rule::include | Rule::macro_def => {}
+                Rule::libs => self.analyze_libs(pair, issues)?,
+                Rule::data => self.analyze_const(pair, issues)?,
+                Rule::input => self.analyze_input(pair, issues)?,
                 Rule::EOI => {}
                 _ => return Err(LexerError::UnknownSegment(pair.as_rule())),
             };
         }
-        Ok((program, issues))
+        Ok(())
+    }
+
+    fn analyze_include(
+        &mut self,
+        pair: Pair<'i, Rule>,
+        stack: &mut Vec<FileId>,
+        included: &mut BTreeSet<FileId>,
+        issues: &mut Issues<'i, issues::Analyze>,
+    ) -> Result<(), LexerError<'i>> {
+        let span = pair.as_span();
+        let from = stack.last().copied().unwrap_or(SourceMap::ROOT);
+        let lit =
+            pair.into_inner().next().ok_or_else(|| LexerError::IncludeNoPath(span.to_src()))?;
+        let (path, name) = self.sources.resolve(from, include_path(lit.as_str()));
+        let file =
+            self.sources.find(&path).ok_or_else(|| LexerError::IncludeNotLoaded(name.clone()))?;
+
+        if stack.contains(&file) {
+            issues.push_error(SyntaxError::IncludeCycle(name), &span);
+            return Ok(());
+        }
+        if !included.insert(file) {
+            return Ok(());
+        }
+
+        stack.push(file);
+        self.analyze_file(parse_file(self.sources, file)?, stack, included, issues)?;
+        stack.pop();
+        Ok(())
     }
 }
 
@@ -61,27 +233,19 @@ impl<'i> Program<'i> {
         pair: Pair<'i, Rule>,
         issues: &mut Issues<'i, issues::Analyze>,
     ) -> Result<(), LexerError<'i>> {
-        let mut set = bset![];
         for pair in pair.into_inner() {
-            let mut found = false;
-            for isa in Isa::all() {
-                if isa.to_string() == pair.as_str() {
-                    if set.contains(&isa) {
-                        issues.push_warning(SyntaxWarning::DuplicatedIsa(isa), &pair);
-                    }
-                    set.insert(isa);
-                    found = true;
-                    break;
-                }
+            let id = pair.as_str();
+            // Plugins may provide instruction sets which are not known to AluVM
+            if !self.isa_ids.contains(id) && !Isa::all().iter().any(|isa| isa.to_string() == id) {
+                issues.push_error(SyntaxError::UnknownIsa(id.to_string()), &pair.as_span());
+                continue;
             }
-            if !found {
-                issues.push_error(
-                    SyntaxError::UnknownIsa(pair.as_str().to_string()),
-                    &pair.as_span(),
-                );
+            if self.isae.contains_key(id) {
+                issues.push_warning(SyntaxWarning::DuplicatedIsa(id.to_string()), &pair);
+            } else {
+                self.isae.insert(id.to_string(), pair.as_span());
             }
         }
-        self.isae = set;
         Ok(())
     }
 
@@ -91,8 +255,26 @@ impl<'i> Program<'i> {
         issues: &mut Issues<'i, issues::Analyze>,
     ) -> Result<(), LexerError<'i>> {
         let span = pair.as_span();
-        let routine = Routine::analyze(pair, issues)?;
-        if self.routines.contains_key(&routine.name) {
+        let mut iter = pair.into_inner();
+
+        let routine_name = iter.next().ok_or_else(|| LexerError::RoutineNoName(span.to_src()))?;
+        let name = match routine_name.as_rule() {
+            Rule::routine_main => ".MAIN",
+            Rule::routine_name | Rule::test_name => routine_name.as_str(),
+            _ => return Err(LexerError::RoutineUnrecognized(span.to_src())),
+        }
+        .to_owned();
+
+        let (statements, labels) = self.analyze_statements(&name, iter, issues)?;
+        let routine = Routine { name, labels, statements, span };
+
+        if routine_name.as_rule() == Rule::test_name {
+            if self.tests.contains_key(&routine.name) {
+                issues.push_error(SyntaxError::TestNameReuse(routine.name), &span);
+            } else {
+                self.tests.insert(routine.name.clone(), routine);
+            }
+        } else if self.routines.contains_key(&routine.name) {
             issues.push_error(SyntaxError::RoutineNameReuse(routine.name), &span);
         } else {
             self.routines.insert(routine.name.clone(), routine);
@@ -100,6 +282,80 @@ impl<'i> Program<'i> {
         Ok(())
     }
 
+    fn analyze_macro(
+        &mut self,
+        pair: Pair<'i, Rule>,
+        issues: &mut Issues<'i, issues::Analyze>,
+    ) -> Result<(), LexerError<'i>> {
+        let span = pair.as_span();
+        let mut iter = pair.into_inner();
+
+        let name_pair = iter.next().ok_or_else(|| LexerError::MacroNoName(span.to_src()))?;
+        let name = name_pair.as_str().to_owned();
+        let params = iter
+            .next()
+            .ok_or_else(|| LexerError::MacroNoParams(span.to_src()))?
+            .into_inner()
+            .map(|pair| (pair.as_str().to_owned(), pair.as_span()))
+            .collect();
+
+        let (statements, labels) = self.analyze_statements(&name, iter, issues)?;
+        let m = Macro { name, params, labels, statements, span };
+
+        if self.operators.contains_key(&m.name) {
+            issues.push_error(SyntaxError::MacroNameReserved(m.name), &name_pair);
+        } else if self.macros.contains_key(&m.name) {
+            issues.push_error(SyntaxError::RepeatedMacroName(m.name), &span);
+        } else {
+            self.macros.insert(m.name.clone(), m);
+        }
+        Ok(())
+    }
+
+    /// Analyzes instructions of a routine or a macro body, expanding invocations of the known
+    /// macros and collecting label positions
+    fn analyze_statements(
+        &self,
+        name: &str,
+        pairs: Pairs<'i, Rule>,
+        issues: &mut Issues<'i, issues::Analyze>,
+    ) -> Result<(Vec<Statement<'i>>, BTreeMap<String, u16>), LexerError<'i>> {
+        let mut statements = vec![];
+        let mut labels = bmap! {};
+        for pair in pairs {
+            let span = pair.as_span();
+            let (expanded, local) = match self.macro_invocation(&pair) {
+                Some(m) => m.expand(pair, issues)?,
+                None => {
+                    let statement = Statement::analyze(pair, &self.operators, issues)?;
+                    let local =
+                        statement.label.iter().map(|(label, _)| (label.clone(), 0)).collect();
+                    (vec![statement], local)
+                }
+            };
+            for (label, offset) in local {
+                if labels.contains_key(&label) {
+                    issues.push_error(
+                        SyntaxError::RepeatedLabel { label, routine: name.to_owned() },
+                        &span,
+                    );
+                } else {
+                    labels.insert(label, statements.len() as u16 + offset);
+                }
+            }
+            statements.extend(expanded);
+        }
+        Ok((statements, labels))
+    }
+
+    fn macro_invocation(&self, pair: &Pair<'i, Rule>) -> Option<&Macro<'i>> {
+        pair.clone()
+            .into_inner()
+            .find(|pair| pair.as_rule() == Rule::operator)
+            .and_then(|pair| pair.into_inner().next())
+            .and_then(|op| self.macros.get(op.as_str()))
+    }
+
     fn analyze_libs(
         &mut self,
         pair: Pair<'i, Rule>,
@@ -107,7 +363,9 @@ impl<'i> Program<'i> {
     ) -> Result<(), LexerError<'i>> {
         let span = pair.as_span();
         let mut map = bmap! {};
+        let mut spans = bmap! {};
         for pair in pair.into_inner() {
+            let def_span = pair.as_span();
             let mut iter = pair.into_inner();
             let name = iter.next().ok_or_else(|| LexerError::LibNoName(span.to_src()))?;
             let id = iter.next().ok_or_else(|| LexerError::LibNoId(span.to_src()))?;
@@ -117,6 +375,7 @@ impl<'i> Program<'i> {
                 match LibId::from_str(id.as_str()) {
                     Ok(libid) => {
                         map.insert(name.as_str().to_owned(), libid);
+                        spans.insert(name.as_str().to_owned(), def_span);
                     }
                     Err(err) => {
                         issues
@@ -125,7 +384,20 @@ impl<'i> Program<'i> {
                 }
             }
         }
-        self.libs = Libs { map, span };
+        for (name, id) in map {
+            match self.libs.map.get(&name) {
+                Some(prev) if *prev != id => {
+                    issues.push_error(SyntaxError::RepeatedLibName(name), &span)
+                }
+                _ => {
+                    if let Some(span) = spans.remove(&name) {
+                        self.libs.spans.entry(name.clone()).or_insert(span);
+                    }
+                    self.libs.map.insert(name, id);
+                }
+            }
+        }
+        self.libs.span = span;
         Ok(())
     }
 
@@ -134,17 +406,33 @@ impl<'i> Program<'i> {
         pair: Pair<'i, Rule>,
         issues: &mut Issues<'i, issues::Analyze>,
     ) -> Result<(), LexerError<'i>> {
-        let mut consts = bmap! {};
         for pair in pair.into_inner() {
             let span = pair.as_span();
-            let c = Const::analyze(pair, issues)?;
-            if consts.contains_key(&c.name) {
+            let mut iter = pair.into_inner();
+            let name = iter
+                .next()
+                .ok_or_else(|| LexerError::ConstNoName(span.to_src()))?
+                .as_str()
+                .to_owned();
+            let value = iter.next().ok_or_else(|| LexerError::ConstNoValue(span.to_src()))?;
+            let mut refs = bset! {};
+            let value = match value.as_rule() {
+                // Constants may refer only to the constants defined before them
+                Rule::expr => {
+                    let expr = Expr::analyze(value, issues)?;
+                    expr.collect_consts(&mut refs);
+                    let val = expr.fold(&self.consts, issues).unwrap_or(u1024::MIN);
+                    Literal::uint(val, IntBase::Dec)
+                }
+                _ => Literal::analyze(value, issues)?,
+            };
+            let c = Const { name, value, refs, span };
+            if self.consts.contains_key(&c.name) {
                 issues.push_error(SyntaxError::RepeatedConstName(c.name), &span);
             } else {
-                consts.insert(c.name.clone(), c);
+                self.consts.insert(c.name.clone(), c);
             }
         }
-        self.consts = consts;
         Ok(())
     }
 
@@ -153,17 +441,15 @@ impl<'i> Program<'i> {
         pair: Pair<'i, Rule>,
         issues: &mut Issues<'i, issues::Analyze>,
     ) -> Result<(), LexerError<'i>> {
-        let mut input = bmap! {};
         for pair in pair.into_inner() {
             let span = pair.as_span();
             let v = Var::analyze(pair, issues)?;
-            if input.contains_key(&v.name) {
+            if self.input.contains_key(&v.name) {
                 issues.push_error(SyntaxError::RepeatedVarName(v.name), &span);
             } else {
-                input.insert(v.name.clone(), v);
+                self.input.insert(v.name.clone(), v);
             }
         }
-        self.input = input;
         Ok(())
     }
 }
@@ -177,54 +463,86 @@ trait Analyze<'i> {
         Self: Sized;
 }
 
-impl<'i> Analyze<'i> for Routine<'i> {
-    fn analyze(
+impl<'i> Macro<'i> {
+    /// Produces statements for the macro invocation, substituting macro parameters with the
+    /// invocation arguments and making labels defined by the macro unique to the invocation.
+    /// Returns expanded statements together with the labels and their offsets inside them.
+    #[allow(clippy::type_complexity)]
+    fn expand(
+        &self,
         pair: Pair<'i, Rule>,
         issues: &mut Issues<'i, issues::Analyze>,
-    ) -> Result<Self, LexerError<'i>> {
-        let span = pair.as_span();
+    ) -> Result<(Vec<Statement<'i>>, Vec<(String, u16)>), LexerError<'i>> {
+        let site = pair.as_span();
         let mut iter = pair.into_inner();
 
-        let routine_name = iter.next().ok_or_else(|| LexerError::RoutineNoName(span.to_src()))?;
-        let name = match routine_name.as_rule() {
-            Rule::routine_main => ".MAIN",
-            Rule::routine_name => routine_name.as_str(),
-            _ => return Err(LexerError::RoutineUnrecognized(span.to_src())),
+        let mut labels = vec![];
+        if let Some(pair) = iter.peek() {
+            if pair.as_rule() == Rule::label {
+                let _ = iter.next();
+                labels.push((pair.as_str().to_owned(), 0));
+            }
         }
-        .to_owned();
 
-        let mut labels: std::collections::BTreeMap<String, u16> = bmap![];
-        let code =
-            iter.enumerate().try_fold(Vec::<Statement>::new(), |mut vec, (index, pair)| {
-                let span = pair.as_span();
-                let op = Statement::analyze(pair, issues)?;
-                if let Some(label) = op.label.clone() {
-                    match labels.contains_key(&label.0) {
-                        true => {
-                            issues.push_error(
-                                SyntaxError::RepeatedLabel {
-                                    label: label.0,
-                                    routine: name.clone(),
-                                },
-                                &span,
-                            );
-                        }
-                        false => {
-                            labels.insert(label.0, index as u16);
+        let operator =
+            iter.next().ok_or_else(|| LexerError::StatementNoInstruction(site.to_src()))?;
+        if operator.into_inner().nth(1).is_some() {
+            issues.push_error(SyntaxError::MacroFlags(self.name.clone()), &site);
+        }
+
+        let mut args = vec![];
+        for pair in iter {
+            args.push(Operand::analyze(pair, issues)?);
+        }
+        if args.len() != self.params.len() {
+            issues.push_error(
+                SyntaxError::MacroArgCount {
+                    name: self.name.clone(),
+                    expected: self.params.len(),
+                    found: args.len(),
+                },
+                &site,
+            );
+        }
+
+        let local = |label: &str| format!("{}@{}", label, site.start());
+        labels.extend(self.labels.iter().map(|(label, offset)| (local(label), *offset)));
+
+        let mut statements = self.statements.clone();
+        for statement in &mut statements {
+            if let Some((label, _)) = &mut statement.label {
+                *label = local(label);
+            }
+            for operand in &mut statement.operands {
+                match operand {
+                    Operand::Goto(label, _) if self.labels.contains_key(label) => {
+                        *label = local(label)
+                    }
+                    Operand::Expr(expr, _) => expr.substitute(&self.params, &args),
+                    Operand::Const(name, _) => {
+                        if let Some(arg) = self
+                            .params
+                            .iter()
+                            .position(|(param, _)| param == name)
+                            .and_then(|no| args.get(no))
+                        {
+                            *operand = arg.clone();
                         }
                     }
+                    _ => {}
                 }
-                vec.push(op);
-                Ok(vec)
-            })?;
+            }
+            statement.expansion.get_or_insert(site);
+        }
 
-        Ok(Routine { name, labels, statements: code, span })
+        Ok((statements, labels))
     }
 }
 
-impl<'i> Analyze<'i> for Statement<'i> {
+impl<'i> Statement<'i> {
     fn analyze(
         pair: Pair<'i, Rule>,
+        operators: &BTreeMap<String, Operator>,
         issues: &mut Issues<'i, issues::Analyze>,
     ) -> Result<Self, LexerError<'i>> {
         let span = pair.as_span();
@@ -243,7 +561,7 @@ impl<'i> Analyze<'i> for Statement<'i> {
         let pair = inner.next().ok_or_else(|| LexerError::OperatorMiscomposition(span.to_src()))?;
         let mnemonic = pair.as_str();
         let operator = (
-            Operator::from_str(mnemonic).unwrap_or_else(|_| {
+            operators.get(mnemonic).copied().unwrap_or_else(|| {
                 issues.push_error(SyntaxError::UnknownMnemonic(pair.as_str().to_owned()), &pair);
                 Operator::nop
             }),
@@ -274,7 +592,23 @@ impl<'i> Analyze<'i> for Statement<'i> {
             operands.push(Operand::analyze(pair, issues)?);
         }
 
-        Ok(Statement { label, operator, flags, operands, span })
+        Ok(Statement { label, operator, flags, operands, span, expansion: None })
+    }
+}
+
+impl<'i> Operand<'i> {
+    /// Parses register reference, like `a8[1]`, returning its register set and index. Returns
+    /// `None` if the text is not a valid register reference.
+    pub fn parse_reg(text: &'i str) -> Option<(RegAll, Reg32)> {
+        let pair = Parser::parse(Rule::reg, text).ok()?.next()?;
+        if pair.as_str().len() != text.len() {
+            return None;
+        }
+        let mut issues = Issues::<issues::Analyze>::default();
+        match Operand::analyze(pair, &mut issues).ok()? {
+            Operand::Reg { set, index, .. } if !issues.has_errors() => Some((set, index)),
+            _ => None,
+        }
     }
 }
 
@@ -372,6 +706,7 @@ impl<'i> Analyze<'i> for Operand<'i> {
             }
             Rule::lit => Operand::Lit(Literal::analyze(pair, issues)?, span),
             Rule::var => Operand::Const(pair.as_str().to_owned(), span),
+            Rule::expr => Operand::Expr(Expr::analyze(pair, issues)?, span),
             Rule::goto => Operand::Goto(pair.as_str().to_owned(), span),
             _ => return Err(LexerError::OperandUnknown(span.to_src(), pair.as_str())),
         })
@@ -386,10 +721,36 @@ impl<'i> Analyze<'i> for Literal {
         let span = pair.as_span();
         let pair =
             pair.into_inner().next().ok_or_else(|| LexerError::LiteralNoData(span.to_src()))?;
+        Literal::analyze_value(pair, issues)
+    }
+}
+
+impl Literal {
+    /// Parses literal written in the same syntax as used by the source code, like `0xFF`, `1.5`
+    /// or `x"DEAD"`. Returns `None` if the text is not a valid literal.
+    pub fn parse(text: &str) -> Option<Literal> {
+        let pair = Parser::parse(Rule::lit, text).ok()?.next()?;
+        if pair.as_str().len() != text.len() {
+            return None;
+        }
+        let mut issues = Issues::<issues::Analyze>::default();
+        let lit = Literal::analyze(pair, &mut issues).ok()?;
+        if issues.has_errors() {
+            return None;
+        }
+        Some(lit)
+    }
+
+    /// Analyzes specific literal rule (like `lit_dec`), which may be a part of `lit` or `expr`
+    fn analyze_value<'i>(
+        pair: Pair<'i, Rule>,
+        issues: &mut Issues<'i, issues::Analyze>,
+    ) -> Result<Self, LexerError<'i>> {
+        let span = pair.as_span();
         Ok(match pair.as_rule() {
             Rule::lit_dec => {
                 let val = pair.as_str();
-                Literal::Int(
+                Literal::uint(
                     u128::from_str(val)
                         .map_err(|err| LexerError::LiteralWrongDec(span.to_src(), val, err))?
                         .into(),
@@ -398,7 +759,7 @@ impl<'i> Analyze<'i> for Literal {
             }
             Rule::lit_bin => {
                 let val = pair.as_str();
-                Literal::Int(
+                Literal::uint(
                     u128::from_str_radix(&val[2..], 2)
                         .map_err(|err| LexerError::LiteralWrongBin(span.to_src(), val, err))?
                         .into(),
@@ -407,7 +768,7 @@ impl<'i> Analyze<'i> for Literal {
             }
             Rule::lit_oct => {
                 let val = pair.as_str();
-                Literal::Int(
+                Literal::uint(
                     u128::from_str_radix(&val[2..], 8)
                         .map_err(|err| LexerError::LiteralWrongOct(span.to_src(), val, err))?
                         .into(),
@@ -427,71 +788,320 @@ impl<'i> Analyze<'i> for Literal {
                     issues.push_error(SyntaxError::TooBigInt(val.to_owned()), &pair);
                     u1024::MIN
                 });
-                Literal::Int(i, IntBase::Hex)
+                Literal::uint(i, IntBase::Hex)
+            }
+            Rule::lit_int => {
+                let text = pair.as_str();
+                let mut iter = pair.clone().into_inner().peekable();
+                let neg = iter.next_if(|pair| pair.as_rule() == Rule::lit_neg).is_some();
+                let lit = iter.next().ok_or_else(|| LexerError::LiteralNoData(span.to_src()))?;
+                let (val, base) = match Literal::analyze_value(lit, issues)? {
+                    Literal::Int { val, base, .. } => (val, base),
+                    _ => return Err(LexerError::LiteralNoData(span.to_src())),
+                };
+                let ty = iter.next().and_then(|pair| int_layout(pair.as_str()));
+                if let Some(layout) = ty {
+                    if !int_fits(val, neg, layout) {
+                        issues.push_error(SyntaxError::IntOutOfType(text.to_owned()), &pair);
+                    }
+                }
+                Literal::Int { val, neg, base, ty }
             }
             Rule::lit_float => {
-                let mut iter = pair.into_inner();
-                let significand_int = iter
+                let mut iter = pair.into_inner().peekable();
+                let neg = iter.next_if(|pair| pair.as_rule() == Rule::lit_neg).is_some();
+                let int = iter
                     .next()
                     .ok_or_else(|| LexerError::FloatNoWhole(span.to_src()))?
                     .as_str()
                     .parse()
                     .map_err(|err| LexerError::FloatWholeNotNumber(span.to_src(), err))?;
-                let significand_rem = iter
+                let frac = iter
                     .next()
                     .ok_or_else(|| LexerError::FloatNoFraction(span.to_src()))?
                     .as_str()
-                    .parse()
-                    .map_err(|err| LexerError::FloatFractionNotNumber(span.to_src(), err))?;
-                let exponential = iter
-                    .next()
-                    .as_ref()
-                    .map(Pair::as_str)
-                    .unwrap_or("0")
-                    .parse()
-                    .map_err(|err| LexerError::FloatExponentialNotNumber(span.to_src(), err))?;
-                Literal::Float(significand_int, significand_rem, exponential)
+                    .to_owned();
+                let mut exp = 0;
+                let mut ty = None;
+                for pair in iter {
+                    match pair.as_rule() {
+                        Rule::lit_exponential => {
+                            exp = pair.as_str().parse().map_err(|err| {
+                                LexerError::FloatExponentialNotNumber(span.to_src(), err)
+                            })?
+                        }
+                        Rule::lit_float_type => ty = float_layout(pair.as_str()),
+                        _ => {}
+                    }
+                }
+                Literal::Float { neg, int, frac, exp, ty }
             }
             Rule::lit_str => {
                 let s = pair.as_str();
-                Literal::String(replace_special_chars(&s[1..s.len() - 1]))
+                let bytes = unescape(&s[1..s.len() - 1], &pair, issues);
+                let s = String::from_utf8(bytes).unwrap_or_else(|_| {
+                    issues.push_error(SyntaxError::StrNotUtf8(s.to_owned()), &pair);
+                    s.to_owned()
+                });
+                Literal::String(s)
+            }
+            Rule::lit_raw_str => {
+                let s = pair.as_str().trim_start_matches('r');
+                let hashes = s.len() - s.trim_start_matches('#').len();
+                Literal::String(s[hashes + 1..s.len() - hashes - 1].to_owned())
+            }
+            Rule::lit_bytes => {
+                let s = pair.as_str();
+                let bytes = Vec::from_hex(&s[2..s.len() - 1]).unwrap_or_else(|_| {
+                    issues.push_error(SyntaxError::InvalidBytesLiteral(s.to_owned()), &pair);
+                    vec![]
+                });
+                Literal::Bytes(bytes)
             }
             Rule::lit_chr => {
                 let s = pair.as_str();
-                let s = replace_special_chars(&s[1..s.len() - 1]);
-                if s.len() != 1 {
-                    issues.push_error(SyntaxError::InvalidCharLiteral(s.clone()), &pair)
+                let bytes = unescape(&s[1..s.len() - 1], &pair, issues);
+                if bytes.len() != 1 {
+                    issues.push_error(SyntaxError::InvalidCharLiteral(s.to_owned()), &pair)
                 }
-                Literal::Char(s.as_bytes()[1])
+                Literal::Char(bytes.first().copied().unwrap_or_default())
             }
             x => return Err(LexerError::LiteralUnknown(span.to_src(), x)),
         })
     }
 }
 
-fn replace_special_chars(s: &str) -> String {
-    s.replace("\\n", "\n")
-        .replace("\\r", "\r")
-        .replace("\\t", "\t")
-        .replace("\\0", "\0")
-        .replace("\\\"", "\"")
-        .replace("\\'", "'")
-        .replace("\\\\", "\\")
-    // TODO: Remove hex and unicode escape sequences
+/// Parses integer type name (like `u16` or `i8`), used by type suffixes of literals and input
+/// variable declarations
+fn int_layout(name: &str) -> Option<IntLayout> {
+    let name = name.to_lowercase();
+    let bytes = |max: u16| {
+        name.get(1..)
+            .and_then(|bits| bits.parse::<u16>().ok())
+            .filter(|bits| bits.is_power_of_two() && (8..=max).contains(bits))
+            .map(|bits| bits / 8)
+    };
+    match name.get(..1) {
+        Some("u") => bytes(8192).map(IntLayout::unsigned),
+        Some("i") => bytes(1024).map(IntLayout::signed),
+        _ => None,
+    }
+}
+
+/// Parses float type name (like `f32` or `apfloat`), used by type suffixes of literals and input
+/// variable declarations
+fn float_layout(name: &str) -> Option<FloatLayout> {
+    Some(match name.to_lowercase().as_str() {
+        "f16b" => FloatLayout::BFloat16,
+        "f16" => FloatLayout::IeeeHalf,
+        "f32" => FloatLayout::IeeeSingle,
+        "f64" => FloatLayout::IeeeDouble,
+        "f80" => FloatLayout::X87DoubleExt,
+        "f128" => FloatLayout::IeeeQuad,
+        "f256" => FloatLayout::IeeeOct,
+        "apfloat" => FloatLayout::FloatTapered,
+        _ => return None,
+    })
+}
+
+/// Processes escape sequences inside string and character literals, returning the resulting
+/// bytes. Unicode characters are encoded as UTF-8, while `\x` escapes produce raw bytes.
+fn unescape<'i>(
+    s: &str,
+    pair: &Pair<'i, Rule>,
+    issues: &mut Issues<'i, issues::Analyze>,
+) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let rest = chars.as_str();
+        let escaped = match chars.next() {
+            Some('n') => Some(b'\n'),
+            Some('r') => Some(b'\r'),
+            Some('t') => Some(b'\t'),
+            Some('0') => Some(b'\0'),
+            Some('\\') => Some(b'\\'),
+            Some('"') => Some(b'"'),
+            Some('\'') => Some(b'\''),
+            Some('x') => {
+                let hex = rest.get(1..3).unwrap_or_default();
+                let byte = u8::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 2);
+                if byte.is_some() {
+                    chars = rest[3..].chars();
+                }
+                byte
+            }
+            Some('u') => {
+                let code = rest
+                    .strip_prefix("u{")
+                    .and_then(|code| code.split_once('}'))
+                    .and_then(|(code, tail)| {
+                        let c = u32::from_str_radix(code, 16).ok().and_then(char::from_u32)?;
+                        Some((c, tail))
+                    });
+                if let Some((c, tail)) = code {
+                    let mut buf = [0u8; 4];
+                    bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+                    chars = tail.chars();
+                    continue;
+                }
+                None
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => bytes.push(byte),
+            None => {
+                let seq = rest.chars().take(1).collect::<String>();
+                issues.push_error(SyntaxError::InvalidEscape(format!("\\{}", seq)), pair);
+            }
+        }
+    }
+    bytes
 }
 
-impl<'i> Analyze<'i> for Const<'i> {
+impl<'i> Analyze<'i> for Expr<'i> {
     fn analyze(
         pair: Pair<'i, Rule>,
         issues: &mut Issues<'i, issues::Analyze>,
     ) -> Result<Self, LexerError<'i>> {
         let span = pair.as_span();
-        let mut iter = pair.into_inner();
-        let name =
-            iter.next().ok_or_else(|| LexerError::ConstNoName(span.to_src()))?.as_str().to_owned();
-        let value = iter.next().ok_or_else(|| LexerError::ConstNoValue(span.to_src()))?;
-        let value = Literal::analyze(value, issues)?;
-        Ok(Const { name, value, span })
+        Ok(match pair.as_rule() {
+            Rule::expr
+            | Rule::expr_xor
+            | Rule::expr_and
+            | Rule::expr_shift
+            | Rule::expr_sum
+            | Rule::expr_prod => {
+                let mut iter = pair.into_inner();
+                let first = iter.next().ok_or_else(|| LexerError::ExprIncomplete(span.to_src()))?;
+                let mut expr = Expr::analyze(first, issues)?;
+                while let Some(op) = iter.next() {
+                    let op = match op.as_rule() {
+                        Rule::op_add => ExprOp::Add,
+                        Rule::op_sub => ExprOp::Sub,
+                        Rule::op_mul => ExprOp::Mul,
+                        Rule::op_div => ExprOp::Div,
+                        Rule::op_rem => ExprOp::Rem,
+                        Rule::op_shl => ExprOp::Shl,
+                        Rule::op_shr => ExprOp::Shr,
+                        Rule::op_and => ExprOp::And,
+                        Rule::op_or => ExprOp::Or,
+                        Rule::op_xor => ExprOp::Xor,
+                        _ => return Err(LexerError::ExprUnknown(op.to_src())),
+                    };
+                    let rhs =
+                        iter.next().ok_or_else(|| LexerError::ExprIncomplete(span.to_src()))?;
+                    let rhs = Expr::analyze(rhs, issues)?;
+                    let span = expr.as_span().start_pos().span(&rhs.as_span().end_pos());
+                    expr = Expr::Binary(op, Box::new(expr), Box::new(rhs), span);
+                }
+                expr
+            }
+            Rule::lit_dec | Rule::lit_hex | Rule::lit_oct | Rule::lit_bin => {
+                match Literal::analyze_value(pair, issues)? {
+                    Literal::Int { val, .. } => Expr::Int(val, span),
+                    _ => return Err(LexerError::ExprUnknown(span.to_src())),
+                }
+            }
+            Rule::var => Expr::Const(pair.as_str().to_owned(), span),
+            _ => return Err(LexerError::ExprUnknown(span.to_src())),
+        })
+    }
+}
+
+impl<'i> Expr<'i> {
+    /// Computes value of the expression, reporting undefined constants and arithmetic errors
+    fn fold(
+        &self,
+        consts: &BTreeMap<String, Const<'i>>,
+        issues: &mut Issues<'i, issues::Analyze>,
+    ) -> Option<u1024> {
+        match self {
+            Expr::Int(val, _) => Some(*val),
+            Expr::Const(name, span) => match consts.get(name).map(|c| &c.value) {
+                Some(Literal::Int { val, neg: false, .. }) => Some(*val),
+                Some(lit) => {
+                    issues.push_error(
+                        SyntaxError::ExprNotInt { name: name.clone(), found: lit.description() },
+                        span,
+                    );
+                    None
+                }
+                None => {
+                    issues.push_error(SyntaxError::ConstUndefined(name.clone()), span);
+                    None
+                }
+            },
+            Expr::Binary(op, lhs, rhs, span) => {
+                let lhs = lhs.fold(consts, issues);
+                let rhs = rhs.fold(consts, issues);
+                let (lhs, rhs) = (lhs?, rhs?);
+                let shift = (rhs < u1024::from(1024u64)).then(|| rhs.low_u64() as usize);
+                let res = match op {
+                    ExprOp::Add => lhs.checked_add(rhs),
+                    ExprOp::Sub => lhs.checked_sub(rhs),
+                    ExprOp::Mul => lhs.checked_mul(rhs),
+                    ExprOp::Div | ExprOp::Rem if rhs == u1024::MIN => {
+                        issues.push_error(SyntaxError::ExprDivZero, span);
+                        return None;
+                    }
+                    ExprOp::Div => Some(lhs / rhs),
+                    ExprOp::Rem => Some(lhs % rhs),
+                    ExprOp::Shl => shift.filter(|n| (lhs << *n) >> *n == lhs).map(|n| lhs << n),
+                    ExprOp::Shr => shift.map(|n| lhs >> n),
+                    ExprOp::And => Some(lhs & rhs),
+                    ExprOp::Or => Some(lhs | rhs),
+                    ExprOp::Xor => Some(lhs ^ rhs),
+                };
+                if res.is_none() {
+                    issues.push_error(SyntaxError::ExprOverflow(*op), span);
+                }
+                res
+            }
+        }
+    }
+
+    /// Collects names of all constants referenced by the expression
+    fn collect_consts(&self, names: &mut BTreeSet<String>) {
+        match self {
+            Expr::Const(name, _) => {
+                names.insert(name.clone());
+            }
+            Expr::Binary(_, lhs, rhs, _) => {
+                lhs.collect_consts(names);
+                rhs.collect_consts(names);
+            }
+            Expr::Int(_, _) => {}
+        }
+    }
+
+    /// Replaces references to macro parameters with the values of macro arguments
+    fn substitute(&mut self, params: &[(String, Span<'i>)], args: &[Operand<'i>]) {
+        match self {
+            Expr::Const(name, _) => {
+                let arg =
+                    params.iter().position(|(param, _)| param == name).and_then(|no| args.get(no));
+                *self = match arg {
+                    Some(Operand::Lit(Literal::Int { val, neg: false, .. }, span)) => {
+                        Expr::Int(*val, *span)
+                    }
+                    Some(Operand::Const(name, span)) => Expr::Const(name.clone(), *span),
+                    Some(Operand::Expr(expr, _)) => expr.clone(),
+                    _ => return,
+                };
+            }
+            Expr::Binary(_, lhs, rhs, _) => {
+                lhs.substitute(params, args);
+                rhs.substitute(params, args);
+            }
+            Expr::Int(_, _) => {}
+        }
     }
 }
 
@@ -513,34 +1123,10 @@ impl<'i> Analyze<'i> for Var<'i> {
         {
             "bytes" => VarType::Bytes,
             "str" => VarType::Str,
-            "u8" => VarType::Int(IntLayout::unsigned(1)),
-            "u16" => VarType::Int(IntLayout::unsigned(2)),
-            "u32" => VarType::Int(IntLayout::unsigned(4)),
-            "u64" => VarType::Int(IntLayout::unsigned(8)),
-            "u128" => VarType::Int(IntLayout::unsigned(16)),
-            "u256" => VarType::Int(IntLayout::unsigned(32)),
-            "u512" => VarType::Int(IntLayout::unsigned(64)),
-            "u1024" => VarType::Int(IntLayout::unsigned(128)),
-            "u2048" => VarType::Int(IntLayout::unsigned(256)),
-            "u4096" => VarType::Int(IntLayout::unsigned(512)),
-            "u8192" => VarType::Int(IntLayout::unsigned(1024)),
-            "i8" => VarType::Int(IntLayout::signed(1)),
-            "i16" => VarType::Int(IntLayout::signed(2)),
-            "i32" => VarType::Int(IntLayout::signed(4)),
-            "i64" => VarType::Int(IntLayout::signed(8)),
-            "i128" => VarType::Int(IntLayout::signed(16)),
-            "i256" => VarType::Int(IntLayout::signed(32)),
-            "i512" => VarType::Int(IntLayout::signed(64)),
-            "i1024" => VarType::Int(IntLayout::signed(128)),
-            "f16b" => VarType::Float(FloatLayout::BFloat16),
-            "f16" => VarType::Float(FloatLayout::IeeeHalf),
-            "f32" => VarType::Float(FloatLayout::IeeeSingle),
-            "f64" => VarType::Float(FloatLayout::IeeeDouble),
-            "f80" => VarType::Float(FloatLayout::X87DoubleExt),
-            "f128" => VarType::Float(FloatLayout::IeeeQuad),
-            "f256" => VarType::Float(FloatLayout::IeeeOct),
-            "apfloat" => VarType::Float(FloatLayout::FloatTapered),
-            unknown => return Err(LexerError::VarTypeUnknown(unknown.to_owned(), span.to_src())),
+            ty => int_layout(ty)
+                .map(VarType::Int)
+                .or_else(|| float_layout(ty).map(VarType::Float))
+                .ok_or_else(|| LexerError::VarTypeUnknown(ty.to_owned(), span.to_src()))?,
         };
         let mut value = iter.next().ok_or_else(|| LexerError::VarNoDescription(span.to_src()))?;
         let default = match value.as_rule() {
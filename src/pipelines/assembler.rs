@@ -0,0 +1,144 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! High-level API assembling source code into a module with a single call, for embedding the
+//! assembler into services and build scripts.
+
+use std::path::{Path, PathBuf};
+
+use aluvm::isa::InstructionSet;
+
+use crate::ast::Program;
+use crate::isa::ExtOp;
+use crate::issues::{Diagnostic, Location, Severity};
+use crate::module::Module;
+use crate::plugins::IsaTable;
+use crate::source::{FsResolver, Resolver, SourceMap};
+use crate::AssemblerError;
+
+/// Source code of the assembled program
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+enum Source {
+    Text { name: String, text: String },
+    File(PathBuf),
+}
+
+/// Assembler of a program from its source code, which includes all files referenced with
+/// `.INCLUDE` directives, into a module. Issues are reported as [`Diagnostic`]s which do not
+/// contain terminal formatting.
+pub struct Assembler<Ext = ExtOp>
+where
+    Ext: InstructionSet,
+{
+    source: Source,
+    resolver: Box<dyn Resolver>,
+    table: IsaTable<Ext>,
+    warnings_as_errors: bool,
+    debug: bool,
+}
+
+impl Assembler<ExtOp> {
+    /// Constructs assembler of the in-memory source code. The `name` is used in diagnostics and
+    /// as a path for resolving included files.
+    pub fn with(name: impl ToString, text: impl ToString) -> Self {
+        Assembler::from_source(Source::Text { name: name.to_string(), text: text.to_string() })
+    }
+
+    /// Constructs assembler of the source file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        Assembler::from_source(Source::File(path.as_ref().to_path_buf()))
+    }
+
+    fn from_source(source: Source) -> Self {
+        Assembler {
+            source,
+            resolver: Box::new(FsResolver),
+            table: IsaTable::default(),
+            warnings_as_errors: false,
+            debug: false,
+        }
+    }
+}
+
+impl<Ext> Assembler<Ext>
+where
+    Ext: InstructionSet,
+{
+    /// Sets resolver reading the source files; by default files are read from the disk
+    pub fn resolver(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.resolver = Box::new(resolver);
+        self
+    }
+
+    /// Sets instruction table defining the ISA profile: mnemonics recognized in the source code
+    /// and ISA plugins compiling them. Defaults to [`IsaTable::default`].
+    pub fn isa<Other>(self, table: IsaTable<Other>) -> Assembler<Other>
+    where
+        Other: InstructionSet,
+    {
+        Assembler {
+            source: self.source,
+            resolver: self.resolver,
+            table,
+            warnings_as_errors: self.warnings_as_errors,
+            debug: self.debug,
+        }
+    }
+
+    /// Makes assembling to fail on warnings, which are reported as errors
+    pub fn warnings_as_errors(mut self, flag: bool) -> Self {
+        self.warnings_as_errors = flag;
+        self
+    }
+
+    /// Adds debug information, mapping code offsets to the source code, to the module
+    pub fn debug(mut self, flag: bool) -> Self {
+        self.debug = flag;
+        self
+    }
+
+    /// Assembles the source code into a module, returning it together with the warnings
+    /// reported while assembling. If any errors were found, fails with
+    /// [`AssemblerError::Failed`] listing all of the reported issues.
+    pub fn assemble(&self) -> Result<(Module, Vec<Diagnostic>), AssemblerError> {
+        let sources = match &self.source {
+            Source::Text { name, text } => {
+                SourceMap::with_resolver(name, text, self.resolver.as_ref())
+            }
+            Source::File(path) => SourceMap::load_with(path, self.resolver.as_ref()),
+        }?;
+
+        let (program, issues) = Program::analyze_with(&sources, &self.table).map_err(|err| {
+            AssemblerError::Lexer(err.to_string(), err.errno(), err.src().map(Location::from))
+        })?;
+        let mut diagnostics = self.check(issues.diagnostics())?;
+
+        let (module, issues) = if self.debug {
+            program.compile_debug_with(&self.table, &mut None)?
+        } else {
+            program.compile_with(&self.table, &mut None)?
+        };
+        diagnostics.extend(issues.diagnostics());
+
+        Ok((module, self.check(diagnostics)?))
+    }
+
+    /// Promotes warnings to errors if required, failing if there are any errors
+    fn check(&self, mut diagnostics: Vec<Diagnostic>) -> Result<Vec<Diagnostic>, AssemblerError> {
+        if self.warnings_as_errors {
+            for diagnostic in &mut diagnostics {
+                diagnostic.severity = Severity::Error;
+            }
+        }
+        let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+        if errors > 0 {
+            let warnings = diagnostics.len() - errors;
+            return Err(AssemblerError::Failed { errors, warnings, diagnostics });
+        }
+        Ok(diagnostics)
+    }
+}
@@ -7,53 +7,134 @@
 
 //! Compiler converting AST constructed by analyzer into instructions and library data structure
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::{TryFrom, TryInto};
 use std::fs::File;
 use std::io::Write as IoWrite;
 use std::str::FromStr;
 
-use aluvm::data::{ByteStr, MaybeNumber, Step};
-use aluvm::isa::{
-    ArithmeticOp, BitwiseOp, Bytecode, BytesOp, CmpOp, ControlFlowOp, DigestOp, Flag, Instr,
-    MoveOp, ParseFlagError, PutOp, Secp256k1Op,
-};
+use aluvm::data::{ByteStr, FloatLayout, IntLayout, Layout, MaybeNumber};
+use aluvm::isa::{Bytecode, ControlFlowOp, Flag, Instr, InstructionSet, ParseFlagError};
 use aluvm::library::{Cursor, IsaSeg, Lib, LibId, LibSeg, LibSite, Read, Write};
-use aluvm::reg::{
-    NumericRegister, Reg32, RegA, RegAF, RegAFR, RegAR, RegAll, RegR, RegS, Register,
-};
-use aluvm::Isa;
-use amplify::num::apfloat::ieee;
+use aluvm::reg::{NumericRegister, Reg32, RegAll, Register};
+use amplify::num::apfloat::{ieee, Float, Round, Status, StatusAnd};
 use amplify::num::u1024;
 use pest::Span;
 
 use crate::ast::{
-    Const, FlagSet, Literal, Operand, Operator, Program, Routine, Statement, Var, VarType,
+    int_fits, Const, FlagSet, Literal, Operand, Operator, Program, Routine, Statement, Var, VarType,
 };
-use crate::issues::{self, Issues, SemanticError};
-use crate::module::{CallTable, DataType, Module, Variable};
+use crate::debug::{DebugInfo, SrcLoc};
+use crate::isa::{ExtOp, StateFlag};
+use crate::issues::{self, Issues, SemanticError, SemanticWarning};
+use crate::module::{CallTable, DataType, Module, Reloc, RelocTable, Variable};
+use crate::plugins::{CompileCtx, IsaTable};
+use crate::tester::TEST_PREFIX;
 use crate::{CompilerError, InstrError};
 
 impl<'i> Program<'i> {
+    /// Compiles program using the default instruction table
+    #[inline]
     pub fn compile(
         &'i self,
         dump: &mut Option<File>,
     ) -> Result<(Module, Issues<'i, issues::Compile>), CompilerError> {
-        let mut issues = Issues::default();
+        self.compile_with(&IsaTable::<ExtOp>::default(), dump)
+    }
+
+    /// Compiles program encoding its statements with ISA plugins registered in the `table`
+    pub fn compile_with<Ext>(
+        &'i self,
+        table: &IsaTable<Ext>,
+        dump: &mut Option<File>,
+    ) -> Result<(Module, Issues<'i, issues::Compile>), CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        let (mut module, issues) = self.compile_debug_with(table, dump)?;
+        module.strip_debug();
+        Ok((module, issues))
+    }
+
+    /// Compiles program using the default instruction table into a module with debug
+    /// information
+    #[inline]
+    pub fn compile_debug(
+        &'i self,
+        dump: &mut Option<File>,
+    ) -> Result<(Module, Issues<'i, issues::Compile>), CompilerError> {
+        self.compile_debug_with(&IsaTable::<ExtOp>::default(), dump)
+    }
+
+    /// Compiles program encoding its statements with ISA plugins registered in the `table`,
+    /// adding debug information which maps code offsets back to the source code
+    pub fn compile_debug_with<Ext>(
+        &'i self,
+        table: &IsaTable<Ext>,
+        dump: &mut Option<File>,
+    ) -> Result<(Module, Issues<'i, issues::Compile>), CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        let routines = self.routines.iter().map(|(name, routine)| (name.clone(), routine));
+        self.compile_routines(routines.collect(), table, dump)
+    }
+
+    /// Compiles program together with its tests using the default instruction table
+    #[inline]
+    pub fn compile_tests(&'i self) -> Result<(Module, Issues<'i, issues::Compile>), CompilerError> {
+        self.compile_tests_with(&IsaTable::<ExtOp>::default())
+    }
+
+    /// Compiles program together with its tests into a module with debug information. Tests are
+    /// exported as routines which names have [`TEST_PREFIX`] prepended to the test name.
+    pub fn compile_tests_with<Ext>(
+        &'i self,
+        table: &IsaTable<Ext>,
+    ) -> Result<(Module, Issues<'i, issues::Compile>), CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        let routines = self.routines.iter().map(|(name, routine)| (name.clone(), routine));
+        let tests =
+            self.tests.iter().map(|(name, test)| (format!("{}{}", TEST_PREFIX, name), test));
+        self.compile_routines(routines.chain(tests).collect(), table, &mut None)
+    }
+
+    /// Compiles routines given by their export names into a module with debug information
+    fn compile_routines<Ext>(
+        &'i self,
+        routines: BTreeMap<String, &'i Routine<'i>>,
+        table: &IsaTable<Ext>,
+        dump: &mut Option<File>,
+    ) -> Result<(Module, Issues<'i, issues::Compile>), CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        let mut issues = Issues::with_sources(self.sources);
 
-        let isae = IsaSeg::from_iter(self.isae.iter().map(Isa::to_string))?;
+        let isae = IsaSeg::from_iter(self.isae.keys())?;
         let libs_segment = LibSeg::from_iter(self.libs.map.values().copied())
             .map_err(|err| issues.push_error(err.into(), &self.libs.span))
             .unwrap_or_default();
         let mut code_segment = ByteStr::default();
         let mut call_table = CallTable::default();
+        let mut relocs = RelocTable::default();
         let mut cursor = Cursor::new(&mut code_segment.bytes[..], &libs_segment);
 
-        let routine_map: BTreeMap<String, Vec<u16>> = self.routines.iter().try_fold(
+        let routine_map: BTreeMap<String, Vec<u16>> = routines.iter().try_fold(
             bmap! {},
             |mut map, (name, routine)| -> Result<_, CompilerError> {
-                let code =
-                    routine.compile(&mut cursor, self, &mut call_table, dump, &mut issues)?;
+                routine.verify(&mut issues);
+                let code = routine.compile(
+                    table,
+                    &mut cursor,
+                    self,
+                    &mut call_table,
+                    &mut relocs,
+                    dump,
+                    &mut issues,
+                )?;
                 if map.len() > u16::MAX as usize {
                     issues.push_error(SemanticError::RoutinesOverflow, &routine.span);
                 } else {
@@ -69,36 +150,38 @@ impl<'i> Program<'i> {
 
         let mut cursor = Cursor::with(&mut code_segment, data, &libs_segment);
 
-        for routine in self.routines.values() {
-            let map = routine_map
-                .get(&routine.name)
-                .ok_or_else(|| CompilerError::RoutineMissed(routine.name.clone()))?;
-            let start =
-                *map.first().ok_or_else(|| CompilerError::RoutineEmpty(routine.name.clone()))?;
+        for (name, routine) in &routines {
+            let map =
+                routine_map.get(name).ok_or_else(|| CompilerError::RoutineMissed(name.clone()))?;
             for (offset, statement) in routine.statements.iter().enumerate() {
-                if statement.operator.0 == Operator::routine {
+                if statement.calls_routine() {
                     let (routine_name, span) = match statement.routine(0, &mut issues) {
                         Some(name) => name,
                         None => continue,
                     };
+                    let seek = map[offset];
                     let posmap = match routine_map.get(&routine_name) {
                         Some(map) => map,
                         None => {
-                            issues.push_error(SemanticError::RoutineUnknown(routine_name), &span);
+                            // Routine may be provided by some other module; leaving it to linker
+                            issues.push_warning(
+                                SemanticWarning::RoutineExtern(routine_name.clone()),
+                                &span,
+                            );
+                            relocs.insert(seek, Reloc::Extern(routine_name));
                             continue;
                         }
                     };
                     let pos =
                         *posmap.first().ok_or_else(|| CompilerError::RoutineEmpty(routine_name))?;
 
-                    let seek = start + map[offset];
                     cursor
-                        .edit(seek, |instr| match instr {
+                        .edit(seek, |instr: &mut Instr<Ext>| match instr {
                             Instr::ControlFlow(ControlFlowOp::Routine(ref mut to)) => {
                                 *to = pos;
                                 Ok(())
                             }
-                            other => Err(InstrError::Changed("routine", other.clone())),
+                            other => Err(InstrError::Changed("routine", other.to_string())),
                         })
                         .map_err(|err| CompilerError::with(err, seek))?;
                 }
@@ -110,16 +193,61 @@ impl<'i> Program<'i> {
             vars.push(v.compile(&mut issues)?);
         }
 
+        let used_isae = routines
+            .values()
+            .flat_map(|routine| &routine.statements)
+            .filter_map(|statement| table.isa_id(statement.operator.0))
+            .collect::<BTreeSet<_>>();
+        for (isa, span) in &self.isae {
+            if !used_isae.contains(isa.as_str()) {
+                issues.push_warning(SemanticWarning::IsaeNotUsed(isa.clone()), span);
+            }
+        }
+
         let exports = routine_map
             .iter()
             .filter_map(|(name, map)| Some((name.clone(), *map.first()?)))
             .collect();
+        let debug = Some(self.debug_info(&routines, &routine_map));
 
         let data = cursor.into_data_segment();
 
         let lib = Lib { isae, code: code_segment, data, libs: libs_segment };
 
-        Ok((Module { inner: lib, vars, imports: call_table, exports }, issues))
+        Ok((Module { inner: lib, vars, imports: call_table, exports, relocs, debug }, issues))
+    }
+
+    /// Constructs debug information from the code offsets of the statements of each routine
+    fn debug_info(
+        &self,
+        routines: &BTreeMap<String, &Routine>,
+        routine_map: &BTreeMap<String, Vec<u16>>,
+    ) -> DebugInfo {
+        let mut debug = DebugInfo::default();
+        for file in self.sources.files() {
+            debug.sources.insert(file.name.clone(), DebugInfo::source_hash(&file.text));
+        }
+        for (name, routine) in routines {
+            let map = match routine_map.get(name) {
+                Some(map) => map,
+                None => continue,
+            };
+            for (statement, pos) in routine.statements.iter().zip(map) {
+                let span = statement.expansion.unwrap_or(statement.span);
+                if let Some(file) = self.sources.locate(&span) {
+                    let (line, col) = span.start_pos().line_col();
+                    let file = file.name.clone();
+                    debug.lines.insert(*pos, SrcLoc { file, line: line as u32, col: col as u32 });
+                }
+            }
+            let labels = routine
+                .labels
+                .iter()
+                .filter_map(|(label, no)| Some((label.clone(), *map.get(*no as usize)?)))
+                .collect();
+            debug.labels.insert(name.clone(), labels);
+        }
+        debug
     }
 }
 
@@ -148,37 +276,60 @@ impl<'i> Var<'i> {
             (VarType::Int(layout), None) => DataType::Int(layout, MaybeNumber::none()),
             (VarType::Float(layout), None) => DataType::Float(layout, MaybeNumber::none()),
             (VarType::Bytes | VarType::Str, None) => DataType::ByteStr(None),
-            (VarType::Int(layout), Some(Literal::Int(val, _))) => {
-                let mut default = MaybeNumber::from(val);
+            (VarType::Int(layout), Some(Literal::Int { val, neg, ty, .. })) => {
+                if ty.map(|ty| ty != layout).unwrap_or_default() {
+                    issues
+                        .push_error(SemanticError::VarWrongDefault(self.name.clone()), &self.span);
+                } else if !int_fits(*val, *neg, layout) {
+                    issues.push_error(
+                        SemanticError::VarDefaultOutOfRange(self.name.clone()),
+                        &self.span,
+                    );
+                }
+                let mut default = MaybeNumber::from(int_value(*val, *neg));
                 default.reshape(layout.into());
                 DataType::Int(layout, default)
             }
-            (VarType::Float(layout), Some(Literal::Float(m, r, e))) => {
-                let float = ieee::Quad::from_str(&format!("{}.{}e{}", m, r, e))
-                    .map_err(|err| CompilerError::FloatConstruction(*m, *r, *e, err))?;
-                let mut default = MaybeNumber::from(float);
-                default.reshape(layout.into());
+            (VarType::Float(FloatLayout::FloatTapered), Some(_)) => {
+                issues.push_error(SemanticError::VarDefaultTapered(self.name.clone()), &self.span);
+                DataType::Float(FloatLayout::FloatTapered, MaybeNumber::none())
+            }
+            (VarType::Float(layout), Some(Literal::Float { neg, int, frac, exp, ty })) => {
+                let (default, status) = float_number(layout, &float_text(*neg, *int, frac, *exp))?;
+                if ty.map(|ty| ty != layout).unwrap_or_default() {
+                    issues
+                        .push_error(SemanticError::VarWrongDefault(self.name.clone()), &self.span);
+                } else if status.contains(Status::OVERFLOW) {
+                    issues.push_error(
+                        SemanticError::VarDefaultOverflow(self.name.clone(), layout),
+                        &self.span,
+                    );
+                } else if status.intersects(Status::INEXACT | Status::UNDERFLOW) {
+                    issues.push_warning(
+                        SemanticWarning::VarDefaultInexact(self.name.clone(), layout),
+                        &self.span,
+                    );
+                }
                 DataType::Float(layout, default)
             }
             (VarType::Bytes, Some(Literal::String(s)))
             | (VarType::Str, Some(Literal::String(s))) => {
                 DataType::ByteStr(Some(s.as_bytes().to_vec()))
             }
-            (VarType::Bytes, Some(Literal::Int(val, _))) => {
+            (VarType::Bytes, Some(Literal::Bytes(b))) => DataType::ByteStr(Some(b.clone())),
+            (VarType::Bytes, Some(Literal::Char(c))) => DataType::ByteStr(Some(vec![*c])),
+            (VarType::Bytes, Some(Literal::Int { val, neg: false, .. })) => {
                 DataType::ByteStr(Some(val.to_be_bytes().to_vec()))
             }
-            (VarType::Str, Some(Literal::Int(val, _))) => DataType::ByteStr(Some(
-                String::from_utf8(val.to_be_bytes().to_vec())
-                    .map_err(|err| {
-                        issues.push_error(
-                            SemanticError::VarValueNotUtf8(self.name.clone(), err),
-                            &self.span,
-                        );
-                    })
-                    .unwrap_or_default()
-                    .as_bytes()
-                    .to_vec(),
-            )),
+            (VarType::Str, Some(Literal::Bytes(b))) => {
+                DataType::ByteStr(Some(self.utf8(b.clone(), issues)))
+            }
+            (VarType::Str, Some(Literal::Char(c))) => {
+                DataType::ByteStr(Some(self.utf8(vec![*c], issues)))
+            }
+            (VarType::Str, Some(Literal::Int { val, neg: false, .. })) => {
+                DataType::ByteStr(Some(self.utf8(val.to_be_bytes().to_vec(), issues)))
+            }
             (VarType::Int(layout), _) => {
                 issues.push_error(SemanticError::VarWrongDefault(self.name.clone()), &self.span);
                 DataType::Int(layout, MaybeNumber::none())
@@ -193,19 +344,103 @@ impl<'i> Var<'i> {
             }
         };
 
-        Ok(Variable { info, data })
+        let name = Some(self.name.trim_start_matches('$').to_owned());
+        Ok(Variable { name, info, data })
+    }
+
+    fn utf8(&'i self, bytes: Vec<u8>, issues: &mut Issues<'i, issues::Compile>) -> Vec<u8> {
+        String::from_utf8(bytes)
+            .map_err(|err| {
+                issues
+                    .push_error(SemanticError::VarValueNotUtf8(self.name.clone(), err), &self.span);
+            })
+            .unwrap_or_default()
+            .into_bytes()
+    }
+}
+
+/// Formats parts of a float literal as a decimal string
+pub(crate) fn float_text(neg: bool, int: u128, frac: &str, exp: i16) -> String {
+    format!("{}{}.{}e{}", if neg { "-" } else { "" }, int, frac, exp)
+}
+
+/// Formats integer literal as a decimal string, which can be parsed as a float
+pub(crate) fn int_text(neg: bool, val: u1024) -> String {
+    // Value is split into chunks of 19 decimal digits, each of which fits into `u64`
+    let base = u1024::from(10_000_000_000_000_000_000u64);
+    let mut chunks = vec![];
+    let mut rest = val;
+    loop {
+        chunks.push((rest % base).low_u64());
+        rest = rest / base;
+        if rest == u1024::MIN {
+            break;
+        }
+    }
+    let mut chunks = chunks.into_iter().rev();
+    let mut text = format!("{}{}", if neg { "-" } else { "" }, chunks.next().unwrap_or_default());
+    for chunk in chunks {
+        text.push_str(&format!("{:019}", chunk));
+    }
+    text
+}
+
+/// Parses float literal directly into the representation of the given float layout, returning
+/// the status of the conversion, which indicates overflows and precision loss.
+pub(crate) fn float_number(
+    layout: FloatLayout,
+    text: &str,
+) -> Result<(MaybeNumber, Status), CompilerError> {
+    macro_rules! parse {
+        ($ty:ty) => {{
+            let StatusAnd { status, value } = <$ty>::from_str_r(text, Round::NearestTiesToEven)
+                .map_err(|err| CompilerError::FloatConstruction(text.to_owned(), err))?;
+            (MaybeNumber::from(value), status)
+        }};
+    }
+    Ok(match layout {
+        FloatLayout::BFloat16 => parse!(ieee::BFloat),
+        FloatLayout::IeeeHalf => parse!(ieee::Half),
+        FloatLayout::IeeeSingle => parse!(ieee::Single),
+        FloatLayout::IeeeDouble => parse!(ieee::Double),
+        FloatLayout::X87DoubleExt => parse!(ieee::X87DoubleExtended),
+        FloatLayout::IeeeQuad => parse!(ieee::Quad),
+        FloatLayout::IeeeOct => parse!(ieee::Oct),
+        FloatLayout::FloatTapered => (MaybeNumber::none(), Status::OK),
+    })
+}
+
+/// Converts integer literal into two's complement representation
+fn int_value(val: u1024, neg: bool) -> u1024 {
+    if neg {
+        (!val).checked_add(u1024::from(1u64)).unwrap_or(u1024::MIN)
+    } else {
+        val
+    }
+}
+
+/// Describes value layout of a register for diagnostic messages
+fn layout_name(layout: Layout) -> String {
+    match layout {
+        Layout::Integer(int) => format!("{}-bit integer", int.bytes as usize * 8),
+        Layout::Float(float) => format!("{:?} float", float),
     }
 }
 
 impl<'i> Routine<'i> {
-    pub fn compile(
+    pub fn compile<Ext>(
         &'i self,
+        table: &IsaTable<Ext>,
         cursor: &mut (impl Read + Write),
         program: &'i Program,
         call_table: &mut CallTable,
+        relocs: &mut RelocTable,
         dump: &mut Option<File>,
         issues: &mut Issues<'i, issues::Compile>,
-    ) -> Result<Vec<u16>, CompilerError> {
+    ) -> Result<Vec<u16>, CompilerError>
+    where
+        Ext: InstructionSet,
+    {
         let mut instr_map = Vec::with_capacity(self.statements.len());
         let mut jump_map = bmap![];
 
@@ -213,7 +448,8 @@ impl<'i> Routine<'i> {
         for (no, statement) in self.statements.iter().enumerate() {
             let pos = cursor.pos();
             instr_map.push(pos);
-            let instr = statement.compile(program, call_table, issues)?;
+            issues.set_expansion(statement.expansion);
+            let instr = statement.compile(table, program, call_table, issues)?;
             if let Err(err) = instr.encode(cursor) {
                 issues.push_error(err.into(), &statement.span);
                 break;
@@ -227,7 +463,7 @@ impl<'i> Routine<'i> {
             }
             if let Instr::ControlFlow(ControlFlowOp::Call(site) | ControlFlowOp::Exec(site)) = instr
             {
-                call_table.get_mut(site)?.sites.insert(pos);
+                relocs.insert(pos, Reloc::Import(site));
             };
 
             if do_dump {
@@ -245,14 +481,15 @@ impl<'i> Routine<'i> {
                 }
             }
         }
+        issues.set_expansion(None);
 
         for (from, to) in jump_map {
             cursor
-                .edit(from, |instr| {
+                .edit(from, |instr: &mut Instr<Ext>| {
                     let pos = match instr {
                         Instr::ControlFlow(ControlFlowOp::Jif(ref mut pos))
                         | Instr::ControlFlow(ControlFlowOp::Jmp(ref mut pos)) => pos,
-                        other => return Err(InstrError::Changed("jump", other.clone())),
+                        other => return Err(InstrError::Changed("jump", other.to_string())),
                     };
                     *pos = instr_map[to as usize];
                     Ok(())
@@ -265,7 +502,8 @@ impl<'i> Routine<'i> {
 }
 
 impl<'i> Statement<'i> {
-    fn reg<T>(&'i self, no: u8, issues: &mut Issues<'i, issues::Compile>) -> T
+    /// Resolves register set of the operand number `no`
+    pub fn reg<T>(&'i self, no: u8, issues: &mut Issues<'i, issues::Compile>) -> T
     where
         T: TryFrom<RegAll> + Register,
     {
@@ -277,6 +515,7 @@ impl<'i> Statement<'i> {
                 Operand::Goto(_, span)
                 | Operand::Const(_, span)
                 | Operand::Lit(_, span)
+                | Operand::Expr(_, span)
                 | Operand::Call { span, .. } => {
                     issues.push_error(
                         SemanticError::OperandWrongType {
@@ -313,7 +552,8 @@ impl<'i> Statement<'i> {
         })
     }
 
-    fn idx<T>(&'i self, no: u8, issues: &mut Issues<'i, issues::Compile>) -> T
+    /// Resolves register index of the operand number `no`
+    pub fn idx<T>(&'i self, no: u8, issues: &mut Issues<'i, issues::Compile>) -> T
     where
         T: TryFrom<Reg32> + Register,
     {
@@ -325,6 +565,7 @@ impl<'i> Statement<'i> {
                 Operand::Goto(_, span)
                 | Operand::Const(_, span)
                 | Operand::Lit(_, span)
+                | Operand::Expr(_, span)
                 | Operand::Call { span, .. } => {
                     issues.push_error(
                         SemanticError::OperandWrongType {
@@ -361,7 +602,8 @@ impl<'i> Statement<'i> {
         })
     }
 
-    fn flags<F>(&'i self, issues: &mut Issues<'i, issues::Compile>) -> F
+    /// Parses operator flags
+    pub fn flags<F>(&'i self, issues: &mut Issues<'i, issues::Compile>) -> F
     where
         WrappedFlag<F>: TryFrom<FlagSet<'i, char>, Error = FlagError<'i>>,
         F: Flag,
@@ -374,7 +616,8 @@ impl<'i> Statement<'i> {
             .unwrap_or_default()
     }
 
-    fn num(
+    /// Resolves numeric literal or constant operand into a value fitting the register
+    pub fn num(
         &'i self,
         no: u8,
         reg: impl NumericRegister,
@@ -395,39 +638,26 @@ impl<'i> Statement<'i> {
             return Ok(MaybeNumber::none());
         };
 
-        let mut val = match operand {
-            Operand::Lit(Literal::Int(val, _), _) => MaybeNumber::from(val),
-            Operand::Lit(Literal::Float(i, r, e), _) => MaybeNumber::from(
-                ieee::Quad::from_str(&format!("{}.{}e{}", i, r, e))
-                    .map_err(|err| CompilerError::FloatConstruction(*i, *r, *e, err))?,
-            ),
-            Operand::Const(name, span) => {
-                let val = match consts.get(name) {
-                    Some(val) => val,
-                    None => {
-                        issues.push_error(SemanticError::ConstUnknown(name.clone()), span);
-                        return Ok(MaybeNumber::none());
-                    }
-                };
-                match &val.value {
-                    Literal::Int(val, _) => MaybeNumber::from(val),
-                    Literal::Float(i, r, e) => MaybeNumber::from(
-                        ieee::Quad::from_str(&format!("{}.{}e{}", i, r, e))
-                            .map_err(|err| CompilerError::FloatConstruction(*i, *r, *e, err))?,
-                    ),
-                    lit => {
-                        issues.push_error(
-                            SemanticError::ConstWrongType {
-                                name: name.clone(),
-                                expected: "integer or float",
-                                found: lit.description(),
-                            },
-                            span,
-                        );
-                        return Ok(MaybeNumber::none());
-                    }
+        let (lit, span) = match operand {
+            Operand::Lit(lit, span) => (lit, span),
+            Operand::Const(name, span) => match consts.get(name).map(|c| &c.value) {
+                Some(lit @ (Literal::Int { .. } | Literal::Float { .. })) => (lit, span),
+                Some(lit) => {
+                    issues.push_error(
+                        SemanticError::ConstWrongType {
+                            name: name.clone(),
+                            expected: "integer or float",
+                            found: lit.description(),
+                        },
+                        span,
+                    );
+                    return Ok(MaybeNumber::none());
                 }
-            }
+                None => {
+                    issues.push_error(SemanticError::ConstUnknown(name.clone()), span);
+                    return Ok(MaybeNumber::none());
+                }
+            },
             op => {
                 issues.push_error(
                     SemanticError::OperandWrongType {
@@ -440,11 +670,65 @@ impl<'i> Statement<'i> {
                 return Ok(MaybeNumber::none());
             }
         };
-        val.reshape(reg.layout());
+
+        let layout = reg.layout();
+        let mismatch = || SemanticError::LiteralTypeMismatch {
+            lit: span.as_str().to_owned(),
+            reg: layout_name(layout),
+        };
+        let out_of_range = || SemanticError::LiteralOutOfRange {
+            lit: span.as_str().to_owned(),
+            reg: layout_name(layout),
+        };
+        let mut val = match (lit, layout) {
+            (Literal::Int { val, neg, ty, .. }, Layout::Integer(int)) => {
+                if ty.map(|ty| ty.bytes != int.bytes).unwrap_or_default() {
+                    issues.push_error(mismatch(), span);
+                }
+                // Registers do not keep the sign, so both signed and unsigned values are accepted
+                let signed = IntLayout::signed(int.bytes);
+                if !int_fits(*val, *neg, int) && !int_fits(*val, *neg, signed) {
+                    issues.push_error(out_of_range(), span);
+                }
+                MaybeNumber::from(int_value(*val, *neg))
+            }
+            (Literal::Float { neg, int, frac, exp, ty }, Layout::Float(float)) => {
+                if ty.map(|ty| ty != float).unwrap_or_default() {
+                    issues.push_error(mismatch(), span);
+                }
+                let (val, status) = float_number(float, &float_text(*neg, *int, frac, *exp))?;
+                if status.contains(Status::OVERFLOW) {
+                    issues.push_error(out_of_range(), span);
+                }
+                val
+            }
+            // Untyped integer literals are converted into floats, if they fit the layout
+            (Literal::Int { val, neg, ty: None, .. }, Layout::Float(float)) => {
+                let (val, status) = float_number(float, &int_text(*neg, *val))?;
+                if status.contains(Status::OVERFLOW) {
+                    issues.push_error(out_of_range(), span);
+                } else if status.intersects(Status::INEXACT | Status::UNDERFLOW) {
+                    issues.push_warning(
+                        SemanticWarning::LiteralInexact {
+                            lit: span.as_str().to_owned(),
+                            reg: layout_name(layout),
+                        },
+                        span,
+                    );
+                }
+                val
+            }
+            _ => {
+                issues.push_error(mismatch(), span);
+                return Ok(MaybeNumber::none());
+            }
+        };
+        val.reshape(layout);
         Ok(val)
     }
 
-    fn str(
+    /// Resolves string literal or constant operand
+    pub fn str(
         &'i self,
         no: u8,
         consts: &'i BTreeMap<String, Const<'i>>,
@@ -466,6 +750,8 @@ impl<'i> Statement<'i> {
 
         let val = match operand {
             Operand::Lit(Literal::String(s), _) => ByteStr::with(s),
+            Operand::Lit(Literal::Bytes(b), _) => ByteStr::with(b),
+            Operand::Lit(Literal::Char(c), _) => ByteStr::with([*c]),
             Operand::Const(name, span) => {
                 let val = match consts.get(name) {
                     Some(val) => val,
@@ -476,11 +762,13 @@ impl<'i> Statement<'i> {
                 };
                 match &val.value {
                     Literal::String(s) => ByteStr::with(s),
+                    Literal::Bytes(b) => ByteStr::with(b),
+                    Literal::Char(c) => ByteStr::with([*c]),
                     lit => {
                         issues.push_error(
                             SemanticError::ConstWrongType {
                                 name: name.clone(),
-                                expected: "string literal",
+                                expected: "string or bytes literal",
                                 found: lit.description(),
                             },
                             span,
@@ -494,7 +782,7 @@ impl<'i> Statement<'i> {
                     SemanticError::OperandWrongType {
                         operator: self.operator.0,
                         pos: no + 1,
-                        expected: "constant or string literal",
+                        expected: "constant, string or bytes literal",
                     },
                     op.as_span(),
                 );
@@ -504,7 +792,171 @@ impl<'i> Statement<'i> {
         Ok(val)
     }
 
-    fn goto(&'i self, no: u8, issues: &mut Issues<'i, issues::Compile>) -> Option<String> {
+    /// Resolves input variable operand into the index of the variable in the module, checking that
+    /// the variable type is compatible with the register it is read into
+    pub fn var(
+        &'i self,
+        no: u8,
+        reg: RegAll,
+        program: &'i Program,
+        issues: &mut Issues<'i, issues::Compile>,
+    ) -> u16 {
+        let (name, span) = match self.operands.get(no as usize) {
+            Some(Operand::Const(name, span)) => (name, span),
+            Some(op) => {
+                issues.push_error(
+                    SemanticError::OperandWrongType {
+                        operator: self.operator.0,
+                        pos: no + 1,
+                        expected: "input variable",
+                    },
+                    op.as_span(),
+                );
+                return 0;
+            }
+            None => {
+                issues.push_error(
+                    SemanticError::OperandMissed {
+                        operator: self.operator.0,
+                        pos: no + 1,
+                        expected: "input variable",
+                    },
+                    &self.operator.1,
+                );
+                return 0;
+            }
+        };
+
+        // Module variables follow the order of the program input declarations
+        let (index, var) = match program.input.values().enumerate().find(|(_, v)| &v.name == name) {
+            Some(found) => found,
+            None => {
+                issues.push_error(SemanticError::VarUnknown(name.clone()), span);
+                return 0;
+            }
+        };
+        let compatible = match (var.ty, reg) {
+            (VarType::Int(layout), RegAll::A(a)) => a.bytes() == layout.bytes,
+            (VarType::Int(layout), RegAll::R(r)) => r.bytes() == layout.bytes,
+            (VarType::Float(layout), RegAll::F(f)) => {
+                matches!(f.layout(), Layout::Float(float) if float == layout)
+            }
+            (VarType::Bytes | VarType::Str, RegAll::S) => true,
+            _ => false,
+        };
+        if !compatible {
+            issues.push_error(SemanticError::VarRegMismatch(name.clone()), span);
+        }
+        index as u16
+    }
+
+    /// Resolves index of the register operand, which must belong to exactly the given register
+    pub fn reg_idx(
+        &'i self,
+        no: u8,
+        reg: RegAll,
+        expected: &'static str,
+        issues: &mut Issues<'i, issues::Compile>,
+    ) -> Reg32 {
+        if let Some(Operand::Reg { set, span, .. }) = self.operands.get(no as usize) {
+            if *set != reg {
+                issues.push_error(
+                    SemanticError::OperandWrongReg {
+                        operator: self.operator.0,
+                        pos: no + 1,
+                        expected,
+                    },
+                    span,
+                );
+            }
+        }
+        self.idx(no, issues)
+    }
+
+    /// Parses `.i` or `.o` flag of the instructions accessing contract state
+    pub fn state_flag(&'i self, issues: &mut Issues<'i, issues::Compile>) -> StateFlag {
+        match self.flags {
+            FlagSet::One('i', _) => StateFlag::Input,
+            FlagSet::One('o', _) => StateFlag::Output,
+            FlagSet::One(flag, span) | FlagSet::Double(_, flag, span) => {
+                issues.push_error(SemanticError::OperatorWrongFlag(self.operator.0, flag), &span);
+                StateFlag::Input
+            }
+            FlagSet::None => {
+                issues.push_error(SemanticError::OperatorRequiresFlag(self.operator.0), &self.span);
+                StateFlag::Input
+            }
+        }
+    }
+
+    /// Resolves 16-bit contract state type from a literal or constant operand
+    pub fn state_type(
+        &'i self,
+        no: u8,
+        consts: &'i BTreeMap<String, Const<'i>>,
+        issues: &mut Issues<'i, issues::Compile>,
+    ) -> u16 {
+        let (lit, span) = match self.operands.get(no as usize) {
+            Some(Operand::Lit(lit, span)) => (lit, span),
+            Some(Operand::Const(name, span)) => match consts.get(name) {
+                Some(c) => (&c.value, span),
+                None => {
+                    issues.push_error(SemanticError::ConstUnknown(name.clone()), span);
+                    return 0;
+                }
+            },
+            Some(op) => {
+                issues.push_error(
+                    SemanticError::OperandWrongType {
+                        operator: self.operator.0,
+                        pos: no + 1,
+                        expected: "state type",
+                    },
+                    op.as_span(),
+                );
+                return 0;
+            }
+            None => {
+                issues.push_error(
+                    SemanticError::OperandMissed {
+                        operator: self.operator.0,
+                        pos: no + 1,
+                        expected: "state type",
+                    },
+                    &self.operator.1,
+                );
+                return 0;
+            }
+        };
+        match lit {
+            Literal::Int { val, neg, .. } if int_fits(*val, *neg, IntLayout::unsigned(2)) => {
+                val.low_u32() as u16
+            }
+            Literal::Int { .. } => {
+                issues.push_error(
+                    SemanticError::LiteralOutOfRange {
+                        lit: span.as_str().to_owned(),
+                        reg: "16-bit state type".to_owned(),
+                    },
+                    span,
+                );
+                0
+            }
+            _ => {
+                issues.push_error(
+                    SemanticError::LiteralTypeMismatch {
+                        lit: span.as_str().to_owned(),
+                        reg: "16-bit state type".to_owned(),
+                    },
+                    span,
+                );
+                0
+            }
+        }
+    }
+
+    /// Resolves label operand of a jump instruction
+    pub fn goto(&'i self, no: u8, issues: &mut Issues<'i, issues::Compile>) -> Option<String> {
         self.operands
             .get(no as usize)
             .and_then(|op| match op {
@@ -512,6 +964,7 @@ impl<'i> Statement<'i> {
                 Operand::Reg { span, .. }
                 | Operand::Const(_, span)
                 | Operand::Lit(_, span)
+                | Operand::Expr(_, span)
                 | Operand::Call { span, .. } => {
                     issues.push_error(
                         SemanticError::OperandWrongType {
@@ -537,7 +990,20 @@ impl<'i> Statement<'i> {
             })
     }
 
-    fn routine(
+    /// Detects whether the statement calls a routine by its name, which is resolved to the routine
+    /// of the same program or, if it is absent, to a routine of other module joined by the linker.
+    /// This is the case for `routine` operator and `call` operator taking a name without library
+    /// prefix
+    pub fn calls_routine(&self) -> bool {
+        match self.operator.0 {
+            Operator::routine => true,
+            Operator::call => matches!(self.operands.first(), Some(Operand::Goto(..))),
+            _ => false,
+        }
+    }
+
+    /// Resolves routine name operand of a routine call instruction
+    pub fn routine(
         &'i self,
         no: u8,
         issues: &mut Issues<'i, issues::Compile>,
@@ -549,6 +1015,7 @@ impl<'i> Statement<'i> {
                 Operand::Reg { span, .. }
                 | Operand::Const(_, span)
                 | Operand::Lit(_, span)
+                | Operand::Expr(_, span)
                 | Operand::Call { span, .. } => {
                     issues.push_error(
                         SemanticError::OperandWrongType {
@@ -574,7 +1041,8 @@ impl<'i> Statement<'i> {
             })
     }
 
-    fn lib(
+    /// Resolves external call operand into library call site, adding it to the call table
+    pub fn lib(
         &'i self,
         no: u8,
         program: &'i Program,
@@ -614,7 +1082,7 @@ impl<'i> Statement<'i> {
                     SemanticError::OperandWrongType {
                         operator: self.operator.0,
                         pos: no + 1,
-                        expected: op.description(),
+                        expected: "external call statement",
                     },
                     op.as_span(),
                 );
@@ -623,653 +1091,19 @@ impl<'i> Statement<'i> {
         }
     }
 
-    pub fn compile(
+    /// Encodes statement into an instruction using ISA plugin registered in the `table` for the
+    /// statement operator
+    pub fn compile<Ext>(
         &'i self,
+        table: &IsaTable<Ext>,
         program: &'i Program,
         call_table: &mut CallTable,
         issues: &mut Issues<'i, issues::Compile>,
-    ) -> Result<Instr, CompilerError> {
-        macro_rules! reg {
-            ($no:expr) => {
-                self.reg($no, issues)
-            };
-        }
-        macro_rules! idx {
-            ($no:expr) => {
-                self.idx($no, issues)
-            };
-        }
-        macro_rules! num {
-            ($no:expr, $reg:ident) => {
-                Box::new(self.num($no, $reg, &program.consts, issues)?)
-            };
-        }
-        macro_rules! str {
-            ($no:expr) => {
-                Box::new(self.str($no, &program.consts, issues)?)
-            };
-        }
-        macro_rules! lib {
-            ($no:expr) => {
-                self.lib($no, program, call_table, issues)
-            };
-        }
-        macro_rules! flags {
-            () => {
-                self.flags(issues)
-            };
-        }
-        Ok(match self.operator.0 {
-            Operator::read => Instr::Nop,
-
-            Operator::succ => Instr::ControlFlow(ControlFlowOp::Succ),
-            Operator::fail => Instr::ControlFlow(ControlFlowOp::Fail),
-            Operator::ret => Instr::ControlFlow(ControlFlowOp::Ret),
-            Operator::jif => Instr::ControlFlow(ControlFlowOp::Jif(0)),
-            Operator::jmp => Instr::ControlFlow(ControlFlowOp::Jmp(0)),
-            Operator::routine => Instr::ControlFlow(ControlFlowOp::Routine(0)),
-            Operator::exec => Instr::ControlFlow(ControlFlowOp::Exec(lib! {0})),
-            Operator::call => Instr::ControlFlow(ControlFlowOp::Call(lib! {0})),
-
-            // *** Put operations
-            Operator::clr => match reg! {0} {
-                RegAFR::A(a) => Instr::Put(PutOp::ClrA(a, idx! {0})),
-                RegAFR::F(f) => Instr::Put(PutOp::ClrF(f, idx! {0})),
-                RegAFR::R(r) => Instr::Put(PutOp::ClrR(r, idx! {0})),
-            },
-            Operator::put => match reg! {0} {
-                RegAll::A(a) => Instr::Put(PutOp::PutA(a, idx! {0}, num! {1, a})),
-                RegAll::F(f) => Instr::Put(PutOp::PutF(f, idx! {0}, num! {1, f})),
-                RegAll::R(r) => Instr::Put(PutOp::PutR(r, idx! {0}, num! {1, r})),
-                RegAll::S => Instr::Bytes(BytesOp::Put(idx! {0}, str! {1}, false)),
-            },
-            Operator::putif => match reg! {1} {
-                RegAR::A(a) => Instr::Put(PutOp::PutIfA(a, idx! {1}, num! {0, a})),
-                RegAR::R(r) => Instr::Put(PutOp::PutIfR(r, idx! {1}, num! {0, r})),
-            },
-
-            // *** Move operations
-            Operator::dup => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAFR::A(a) => Instr::Move(MoveOp::DupA(a, idx! {0}, idx! {1})),
-                    RegAFR::F(f) => Instr::Move(MoveOp::DupF(f, idx! {0}, idx! {1})),
-                    RegAFR::R(r) => Instr::Move(MoveOp::DupR(r, idx! {0}, idx! {1})),
-                }
-            }
-            Operator::mov => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAll::A(a) => Instr::Move(MoveOp::MovA(a, idx! {0}, idx! {1})),
-                    RegAll::F(f) => Instr::Move(MoveOp::MovF(f, idx! {0}, idx! {1})),
-                    RegAll::R(r) => Instr::Move(MoveOp::MovR(r, idx! {0}, idx! {1})),
-                    RegAll::S => Instr::Bytes(BytesOp::Mov(idx! {0}, idx! {1})),
-                }
-            }
-            Operator::cnv => match (reg! {0}, reg! {1}) {
-                (RegAF::A(a1), RegAF::A(a2)) => {
-                    Instr::Move(MoveOp::CnvA(a1, idx! {0}, a2, idx! {1}))
-                }
-                (RegAF::F(f1), RegAF::F(f2)) => {
-                    Instr::Move(MoveOp::CnvF(f1, idx! {0}, f2, idx! {1}))
-                }
-                (RegAF::A(a), RegAF::F(f)) => Instr::Move(MoveOp::CnvAF(a, idx! {0}, f, idx! {1})),
-                (RegAF::F(f), RegAF::A(a)) => Instr::Move(MoveOp::CnvFA(f, idx! {0}, a, idx! {1})),
-            },
-            Operator::cpy => match reg! {0} {
-                RegAR::A(a) => Instr::Move(MoveOp::CpyA(a, idx! {0}, reg! {1}, idx! {1})),
-                RegAR::R(r) => Instr::Move(MoveOp::CpyR(r, idx! {0}, reg! {1}, idx! {1})),
-            },
-            Operator::spy => Instr::Move(MoveOp::SpyAR(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
-            Operator::swp => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAll::A(a) => Instr::Move(MoveOp::SwpA(a, idx! {0}, idx! {1})),
-                    RegAll::F(f) => Instr::Move(MoveOp::SwpF(f, idx! {0}, idx! {1})),
-                    RegAll::S => Instr::Bytes(BytesOp::Swp(idx! {0}, idx! {1})),
-                    _ => {
-                        issues.push_error(
-                            SemanticError::OperandWrongReg {
-                                operator: Operator::mov,
-                                pos: 0,
-                                expected: "register S",
-                            },
-                            self.operands[1].as_span(),
-                        );
-                        Instr::Nop
-                    }
-                }
-            }
-
-            // *** Comparison operations
-            Operator::eq => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAll::A(a) => Instr::Cmp(CmpOp::EqA(flags!(), a, idx! {0}, idx! {1})),
-                    RegAll::F(f) => Instr::Cmp(CmpOp::EqF(flags!(), f, idx! {0}, idx! {1})),
-                    RegAll::R(r) => Instr::Cmp(CmpOp::EqR(flags!(), r, idx! {0}, idx! {1})),
-                    RegAll::S => {
-                        let _: RegS = reg! {1};
-                        Instr::Bytes(BytesOp::Eq(idx! {0}, idx! {1}))
-                    }
-                }
-            }
-            Operator::gt => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAFR::A(a) => Instr::Cmp(CmpOp::GtA(flags!(), a, idx! {0}, idx! {1})),
-                    RegAFR::F(f) => Instr::Cmp(CmpOp::GtF(flags!(), f, idx! {0}, idx! {1})),
-                    RegAFR::R(r) => Instr::Cmp(CmpOp::GtR(r, idx! {0}, idx! {1})),
-                }
-            }
-            Operator::lt => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAFR::A(a) => Instr::Cmp(CmpOp::LtA(flags!(), a, idx! {0}, idx! {1})),
-                    RegAFR::F(f) => Instr::Cmp(CmpOp::LtF(flags!(), f, idx! {0}, idx! {1})),
-                    RegAFR::R(r) => Instr::Cmp(CmpOp::LtR(r, idx! {0}, idx! {1})),
-                }
-            }
-            Operator::ifn => match reg! {0} {
-                RegAR::A(a) => Instr::Cmp(CmpOp::IfNA(a, idx! {0})),
-                RegAR::R(r) => Instr::Cmp(CmpOp::IfNR(r, idx! {0})),
-            },
-            Operator::ifz => match reg! {0} {
-                RegAR::A(a) => Instr::Cmp(CmpOp::IfZA(a, idx! {0})),
-                RegAR::R(r) => Instr::Cmp(CmpOp::IfZR(r, idx! {0})),
-            },
-            Operator::stinv => Instr::Cmp(CmpOp::StInv),
-            Operator::st => Instr::Cmp(CmpOp::St(flags!(), reg! {0}, idx! {0})),
-
-            // *** Arithmetic
-            Operator::neg => Instr::Arithmetic(ArithmeticOp::Neg(reg! {0}, idx! {0})),
-            Operator::inc => {
-                Instr::Arithmetic(ArithmeticOp::Stp(reg! {0}, idx! {0}, Step::with(1)))
-            }
-            Operator::dec => {
-                Instr::Arithmetic(ArithmeticOp::Stp(reg! {0}, idx! {0}, Step::with(-1)))
-            }
-            Operator::add => {
-                if let Some(Operand::Lit(Literal::Int(mut step, _), span)) = self.operands.get(1) {
-                    if step > u1024::from(i8::MAX as u8) {
-                        step = u1024::from(1u64);
-                        issues.push_error(SemanticError::StepTooLarge(self.operator.0), span);
-                    }
-                    Instr::Arithmetic(ArithmeticOp::Stp(
-                        reg! {0},
-                        idx! {0},
-                        Step::with(step.low_u32() as i8),
-                    ))
-                } else {
-                    let reg = reg! {0};
-                    if reg != reg! {1} {
-                        issues.push_error(
-                            SemanticError::OperandRegMutBeEqual(self.operator.0),
-                            self.operands[1].as_span(),
-                        );
-                    }
-                    match reg {
-                        RegAF::A(a) => {
-                            Instr::Arithmetic(ArithmeticOp::AddA(flags!(), a, idx! {0}, idx! {1}))
-                        }
-                        RegAF::F(f) => {
-                            Instr::Arithmetic(ArithmeticOp::AddF(flags!(), f, idx! {0}, idx! {1}))
-                        }
-                    }
-                }
-            }
-            Operator::sub => {
-                if let Some(Operand::Lit(Literal::Int(mut step, _), span)) = self.operands.get(1) {
-                    if step > u1024::from(i8::MAX as u8) {
-                        step = u1024::from(1u64);
-                        issues.push_error(SemanticError::StepTooLarge(self.operator.0), span);
-                    }
-                    Instr::Arithmetic(ArithmeticOp::Stp(
-                        reg! {0},
-                        idx! {0},
-                        Step::with(-(step.low_u32() as i8)),
-                    ))
-                } else {
-                    let reg = reg! {0};
-                    if reg != reg! {1} {
-                        issues.push_error(
-                            SemanticError::OperandRegMutBeEqual(self.operator.0),
-                            self.operands[1].as_span(),
-                        );
-                    }
-                    match reg {
-                        RegAF::A(a) => {
-                            Instr::Arithmetic(ArithmeticOp::SubA(flags!(), a, idx! {0}, idx! {1}))
-                        }
-                        RegAF::F(f) => {
-                            Instr::Arithmetic(ArithmeticOp::SubF(flags!(), f, idx! {0}, idx! {1}))
-                        }
-                    }
-                }
-            }
-            Operator::mul => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAF::A(a) => {
-                        Instr::Arithmetic(ArithmeticOp::MulA(flags!(), a, idx! {0}, idx! {1}))
-                    }
-                    RegAF::F(f) => {
-                        Instr::Arithmetic(ArithmeticOp::MulF(flags!(), f, idx! {0}, idx! {1}))
-                    }
-                }
-            }
-            Operator::div => {
-                let reg = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                match reg {
-                    RegAF::A(a) => {
-                        Instr::Arithmetic(ArithmeticOp::DivA(flags!(), a, idx! {0}, idx! {1}))
-                    }
-                    RegAF::F(f) => {
-                        Instr::Arithmetic(ArithmeticOp::DivF(flags!(), f, idx! {0}, idx! {1}))
-                    }
-                }
-            }
-            Operator::rem => {
-                Instr::Arithmetic(ArithmeticOp::Rem(reg! {0}, idx! {0}, reg! {1}, idx! {1}))
-            }
-            Operator::abs => Instr::Arithmetic(ArithmeticOp::Abs(reg! {0}, idx! {0})),
-
-            // *** Bitwise
-            Operator::not => Instr::Bitwise(BitwiseOp::Not(reg! {0}, idx! {0})),
-            Operator::and => {
-                let reg: RegAR = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                if reg != reg! {2} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[2].as_span(),
-                    );
-                }
-                Instr::Bitwise(BitwiseOp::And(reg, idx! {0}, idx! {1}, idx! {2}))
-            }
-            Operator::or => {
-                let reg: RegAR = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                if reg != reg! {2} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[2].as_span(),
-                    );
-                }
-                Instr::Bitwise(BitwiseOp::Or(reg, idx! {0}, idx! {1}, idx! {2}))
-            }
-            Operator::xor => {
-                let reg: RegAR = reg! {0};
-                if reg != reg! {1} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[1].as_span(),
-                    );
-                }
-                if reg != reg! {2} {
-                    issues.push_error(
-                        SemanticError::OperandRegMutBeEqual(self.operator.0),
-                        self.operands[2].as_span(),
-                    );
-                }
-                Instr::Bitwise(BitwiseOp::Xor(reg, idx! {0}, idx! {1}, idx! {2}))
-            }
-            Operator::shl => Instr::Bitwise(BitwiseOp::Shl(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
-            Operator::shr => match reg! {1} {
-                RegAR::A(a) => {
-                    Instr::Bitwise(BitwiseOp::ShrA(flags!(), reg! {0}, idx! {0}, a, idx! {1}))
-                }
-                RegAR::R(r) => Instr::Bitwise(BitwiseOp::ShrR(reg! {0}, idx! {0}, r, idx! {1})),
-            },
-            Operator::scl => Instr::Bitwise(BitwiseOp::Scl(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
-            Operator::scr => Instr::Bitwise(BitwiseOp::Scr(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
-            Operator::rev => match reg! {0} {
-                RegAll::A(a) => Instr::Bitwise(BitwiseOp::RevA(a, idx! {0})),
-                RegAll::R(r) => Instr::Bitwise(BitwiseOp::RevR(r, idx! {0})),
-                RegAll::S => {
-                    let _: RegS = reg! {1};
-                    Instr::Bytes(BytesOp::Rev(idx! {0}, idx! {1}))
-                }
-                _ => {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 0,
-                            expected: "register S",
-                        },
-                        self.operands[0].as_span(),
-                    );
-                    Instr::Nop
-                }
-            },
-
-            // *** String operations
-            Operator::fill => {
-                let _: RegS = reg! {0};
-                let reg1: RegA = reg! {1};
-                let reg2: RegA = reg! {2};
-                let reg3: RegA = reg! {3};
-                if reg1 != RegA::A16 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 1,
-                            expected: "a16 register",
-                        },
-                        self.operands[1].as_span(),
-                    );
-                }
-                if reg2 != RegA::A16 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 2,
-                            expected: "a16 register",
-                        },
-                        self.operands[2].as_span(),
-                    );
-                }
-                if reg3 != RegA::A8 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 3,
-                            expected: "a8 register",
-                        },
-                        self.operands[3].as_span(),
-                    );
-                }
-                Instr::Bytes(BytesOp::Fill(idx! {0}, idx! {1}, idx! {2}, idx! {3}, flags!()))
-            }
-            Operator::len => {
-                let _: RegS = reg! {0};
-                Instr::Bytes(BytesOp::Len(idx! {0}, reg! {1}, idx! {1}))
-            }
-            Operator::cnt => {
-                let _: RegS = reg! {0};
-                let byte_reg: RegA = reg! {1};
-                let dst_reg: RegA = reg! {2};
-                if byte_reg != RegA::A8 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 2,
-                            expected: "a8 register",
-                        },
-                        self.operands[2].as_span(),
-                    );
-                }
-                if dst_reg != RegA::A16 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 0,
-                            expected: "a16 register",
-                        },
-                        self.operands[0].as_span(),
-                    );
-                }
-                Instr::Bytes(BytesOp::Cnt(idx! {0}, idx! {1}, idx! {2}))
-            }
-            Operator::con => {
-                let _: RegS = reg! {0};
-                let _: RegS = reg! {1};
-                for n in 2..5 {
-                    let reg: RegA = reg! {n};
-                    if reg != RegA::A16 {
-                        issues.push_error(
-                            SemanticError::OperandWrongReg {
-                                operator: self.operator.0,
-                                pos: n,
-                                expected: "a16 register",
-                            },
-                            self.operands[n as usize].as_span(),
-                        );
-                    }
-                }
-                Instr::Bytes(BytesOp::Con(idx! {0}, idx! {1}, idx! {2}, idx! {3}, idx! {4}))
-            }
-            Operator::find => {
-                let _: RegS = reg! {0};
-                let _: RegS = reg! {1};
-                let reg: RegA = reg! {2};
-                let idx: Reg32 = idx! {2};
-                if reg != RegA::A16 || idx != Reg32::Reg0 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 2,
-                            expected: "a16[0] register",
-                        },
-                        self.operands[2].as_span(),
-                    );
-                }
-                Instr::Bytes(BytesOp::Find(idx! {0}, idx! {1}))
-            }
-            Operator::extr => {
-                let _: RegS = reg! {0};
-                Instr::Bytes(BytesOp::Extr(idx! {0}, reg! {1}, idx! {1}, idx! {2}))
-            }
-            Operator::inj => {
-                let _: RegS = reg! {0};
-                let reg: RegA = reg! {2};
-                if reg != RegA::A16 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 2,
-                            expected: "a16 register",
-                        },
-                        self.operands[2].as_span(),
-                    );
-                }
-                Instr::Bytes(BytesOp::Inj(idx! {0}, reg! {1}, idx! {1}, idx! {2}))
-            }
-            Operator::join => {
-                let _: RegS = reg! {0};
-                let _: RegS = reg! {1};
-                let _: RegS = reg! {2};
-                Instr::Bytes(BytesOp::Join(idx! {0}, idx! {1}, idx! {2}))
-            }
-            Operator::splt => {
-                let _: RegS = reg! {0};
-                let _: RegS = reg! {2};
-                let _: RegS = reg! {3};
-                let reg: RegA = reg! {1};
-                if reg != RegA::A16 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 1,
-                            expected: "a16[0] register",
-                        },
-                        self.operands[1].as_span(),
-                    );
-                }
-                Instr::Bytes(BytesOp::Splt(flags!(), idx! {1}, idx! {0}, idx! {2}, idx! {3}))
-            }
-            Operator::ins => {
-                let _: RegS = reg! {0};
-                let _: RegS = reg! {1};
-                let reg: RegA = reg! {2};
-                if reg != RegA::A16 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 2,
-                            expected: "a16[0] register",
-                        },
-                        self.operands[2].as_span(),
-                    );
-                }
-                Instr::Bytes(BytesOp::Ins(flags!(), idx! {2}, idx! {0}, idx! {1}))
-            }
-            Operator::del => {
-                todo!("Unimplemented operation `del`")
-            }
-
-            // *** Digest operations
-            Operator::ripemd => {
-                let reg: RegR = reg! {1};
-                if reg != RegR::R160 {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 2,
-                            expected: "r160 register",
-                        },
-                        self.operands[1].as_span(),
-                    );
-                    return Ok(Instr::Nop);
-                }
-                Instr::Digest(DigestOp::Ripemd(idx! {0}, idx! {1}))
-            }
-            Operator::sha2 => match reg! {1} {
-                RegR::R256 => Instr::Digest(DigestOp::Sha256(idx! {0}, idx! {1})),
-                RegR::R512 => Instr::Digest(DigestOp::Sha512(idx! {0}, idx! {1})),
-                _ => {
-                    issues.push_error(
-                        SemanticError::OperandWrongReg {
-                            operator: self.operator.0,
-                            pos: 2,
-                            expected: "r256 or r512 registers",
-                        },
-                        self.operands[1].as_span(),
-                    );
-                    return Ok(Instr::Nop);
-                }
-            },
-
-            // *** Elliptic curve operations
-            Operator::secpgen => {
-                for r in 0..=1 {
-                    let reg: RegR = reg! {r};
-                    if reg != [RegR::R256, RegR::R512][r as usize] {
-                        issues.push_error(
-                            SemanticError::OperandWrongReg {
-                                operator: self.operator.0,
-                                pos: r + 1,
-                                expected: if r == 0 { "r256 register" } else { "r512 register" },
-                            },
-                            self.operands[r as usize].as_span(),
-                        );
-                        return Ok(Instr::Nop);
-                    }
-                }
-                Instr::Secp256k1(Secp256k1Op::Gen(idx! {0}, idx! {1}))
-            }
-            Operator::secpneg => {
-                for r in 0..=1 {
-                    let reg: RegR = reg! {r};
-                    if reg != RegR::R512 {
-                        issues.push_error(
-                            SemanticError::OperandWrongReg {
-                                operator: self.operator.0,
-                                pos: r + 1,
-                                expected: "r512 register",
-                            },
-                            self.operands[r as usize].as_span(),
-                        );
-                        return Ok(Instr::Nop);
-                    }
-                }
-                Instr::Secp256k1(Secp256k1Op::Neg(idx! {0}, idx! {1}))
-            }
-            Operator::secpadd => {
-                for r in 0..=1 {
-                    let reg: RegR = reg! {r};
-                    if reg != RegR::R512 {
-                        issues.push_error(
-                            SemanticError::OperandWrongReg {
-                                operator: self.operator.0,
-                                pos: r + 1,
-                                expected: "r512 register",
-                            },
-                            self.operands[r as usize].as_span(),
-                        );
-                        return Ok(Instr::Nop);
-                    }
-                }
-                Instr::Secp256k1(Secp256k1Op::Add(idx! {0}, idx! {1}))
-            }
-            Operator::secpmul => {
-                for r in 1..=2 {
-                    let reg: RegR = reg! {r};
-                    if reg != RegR::R512 {
-                        issues.push_error(
-                            SemanticError::OperandWrongReg {
-                                operator: self.operator.0,
-                                pos: r + 1,
-                                expected: "r512 register",
-                            },
-                            self.operands[r as usize].as_span(),
-                        );
-                        return Ok(Instr::Nop);
-                    }
-                }
-                Instr::Secp256k1(Secp256k1Op::Mul(reg! {0}, idx! {0}, idx! {1}, idx! {2}))
-            }
-
-            Operator::nop => Instr::Nop,
-        })
+    ) -> Result<Instr<Ext>, CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        table.compile(self, &mut CompileCtx { program, call_table, issues })
     }
 }
 
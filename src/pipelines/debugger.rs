@@ -0,0 +1,203 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Step debugger executing programs one instruction at a time
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use aluvm::data::Number;
+use aluvm::isa::{Bytecode, CmpOp, ExecStep, InstructionSet, MergeFlag};
+use aluvm::library::{LibId, LibSite};
+use aluvm::reg::{CoreRegs, Reg8, RegA, RegA8};
+use aluvm::{Prog, Program as _};
+
+use crate::ast::Program;
+use crate::isa::{Context, Instr};
+use crate::source::SourceMap;
+use crate::DebugError;
+
+/// Reason for the debugger to stop the program execution
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum Halt {
+    /// Single instruction was executed
+    #[display("step")]
+    Step,
+
+    /// Execution reached a breakpoint
+    #[display("breakpoint at {0}")]
+    Breakpoint(LibSite),
+
+    /// Program has completed with the given value of `st0` register
+    #[display("program completed with st0 = {0}")]
+    Completed(bool),
+}
+
+/// Step debugger holding the program, state of its registers and the current instruction
+pub struct Debugger<'p> {
+    prog: &'p Prog<Instr>,
+    context: Context,
+    regs: CoreRegs,
+    /// Disassembled code of the program libraries by the code offset of each instruction
+    code: BTreeMap<LibId, BTreeMap<u16, Instr>>,
+    /// Position of the next instruction; `None` once the program has completed
+    site: Option<LibSite>,
+    breakpoints: BTreeSet<LibSite>,
+}
+
+impl<'p> Debugger<'p> {
+    /// Prepares the program for the execution starting from its entry point
+    pub fn with(prog: &'p Prog<Instr>, context: Context) -> Self {
+        Debugger {
+            prog,
+            context,
+            regs: CoreRegs::new(),
+            code: bmap! {},
+            site: Some(prog.entrypoint()),
+            breakpoints: bset! {},
+        }
+    }
+
+    /// Position of the instruction which will be executed next, or `None` if the program has
+    /// completed
+    #[inline]
+    pub fn site(&self) -> Option<LibSite> { self.site }
+
+    #[inline]
+    pub fn registers(&self) -> &CoreRegs { &self.regs }
+
+    /// Value of `st0` register
+    #[inline]
+    pub fn status(&self) -> bool {
+        status(&self.regs, self.site.unwrap_or_else(|| self.prog.entrypoint()))
+    }
+
+    #[inline]
+    pub fn registers_mut(&mut self) -> &mut CoreRegs { &mut self.regs }
+
+    #[inline]
+    pub fn breakpoints(&self) -> &BTreeSet<LibSite> { &self.breakpoints }
+
+    /// Sets breakpoint, returning `false` if it was already set
+    #[inline]
+    pub fn set_breakpoint(&mut self, site: LibSite) -> bool { self.breakpoints.insert(site) }
+
+    /// Removes breakpoint, returning `false` if it was not set
+    #[inline]
+    pub fn remove_breakpoint(&mut self, site: LibSite) -> bool { self.breakpoints.remove(&site) }
+
+    /// Returns the instruction which will be executed next
+    pub fn instr(&mut self) -> Result<Option<&Instr>, DebugError> {
+        let site = match self.site {
+            Some(site) => site,
+            None => return Ok(None),
+        };
+        Ok(self.code(site.lib)?.get(&site.pos))
+    }
+
+    /// Executes a single instruction
+    pub fn step(&mut self) -> Result<Halt, DebugError> {
+        let site = self.site.ok_or(DebugError::Completed)?;
+        let code = self.code(site.lib)?;
+        let instr = code.get(&site.pos).cloned();
+        let past_end = code.range(site.pos..).next().is_none();
+        let instr = match instr {
+            Some(instr) => instr,
+            // Execution ends once it passes the last instruction of a library
+            None if past_end => return Ok(self.complete(site)),
+            None => return Err(DebugError::InstrMisaligned(site)),
+        };
+
+        self.site = match instr.exec(&mut self.regs, site, &self.context) {
+            ExecStep::Stop => return Ok(self.complete(site)),
+            ExecStep::Fail => {
+                self.site = None;
+                return Ok(Halt::Completed(false));
+            }
+            ExecStep::Next => {
+                Some(LibSite::with(site.pos.wrapping_add(instr.byte_count()), site.lib))
+            }
+            ExecStep::Jump(pos) => Some(LibSite::with(pos, site.lib)),
+            ExecStep::Call(site) => Some(site),
+        };
+        Ok(Halt::Step)
+    }
+
+    /// Executes instructions until a breakpoint is reached or the program completes. The
+    /// instruction at the current position is always executed, even if it has a breakpoint.
+    pub fn resume(&mut self) -> Result<Halt, DebugError> {
+        loop {
+            match self.step()? {
+                Halt::Step => {}
+                halt => return Ok(halt),
+            }
+            match self.site {
+                Some(site) if self.breakpoints.contains(&site) => {
+                    return Ok(Halt::Breakpoint(site))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Compiles a single statement, like `put a8[1], 5`, and executes it over the registers
+    /// without changing the current position. Used to modify register values.
+    pub fn exec(&mut self, statement: &str) -> Result<(), DebugError> {
+        let code =
+            format!(".ISAE\n                ALU\n.MAIN\n                {}\n", statement.trim());
+        let invalid = || DebugError::Statement(statement.trim().to_owned());
+        let sources = SourceMap::with("<debugger>", code).map_err(|_| invalid())?;
+        let (program, issues) = Program::analyze(&sources).map_err(|_| invalid())?;
+        if issues.has_errors() {
+            return Err(invalid());
+        }
+        let (module, issues) = program.compile(&mut None).map_err(|_| invalid())?;
+        if issues.has_errors() {
+            return Err(invalid());
+        }
+        let lib = module.as_static_lib();
+        let instr = match lib.disassemble::<Instr>()?.as_slice() {
+            [instr] => instr.clone(),
+            _ => return Err(invalid()),
+        };
+        match instr.exec(&mut self.regs, LibSite::with(0, lib.id()), &self.context) {
+            ExecStep::Next => Ok(()),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Returns disassembled code of the library with the given id
+    fn code(&mut self, id: LibId) -> Result<&BTreeMap<u16, Instr>, DebugError> {
+        if !self.code.contains_key(&id) {
+            let lib = self.prog.lib(id).ok_or(DebugError::LibAbsent(id))?;
+            let mut pos = 0u16;
+            let mut code = bmap! {};
+            for instr in lib.disassemble::<Instr>()? {
+                let len = instr.byte_count();
+                code.insert(pos, instr);
+                pos = pos.wrapping_add(len);
+            }
+            self.code.insert(id, code);
+        }
+        Ok(&self.code[&id])
+    }
+
+    fn complete(&mut self, site: LibSite) -> Halt {
+        self.site = None;
+        Halt::Completed(status(&self.regs, site))
+    }
+}
+
+/// Reads value of `st0` register, which is not directly accessible, by executing `st` instruction
+/// over a copy of the registers
+fn status(regs: &CoreRegs, site: LibSite) -> bool {
+    let mut regs = regs.clone();
+    let st = CmpOp::St(MergeFlag::Set, RegA8::A8, Reg8::Reg0);
+    st.exec(&mut regs, site, &());
+    Option::<Number>::from(regs.get_n(RegA::A8, Reg8::Reg0))
+        .map(|number| number.as_ref().iter().any(|byte| *byte != 0))
+        .unwrap_or_default()
+}
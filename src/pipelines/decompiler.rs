@@ -0,0 +1,689 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Decompiler converting modules and linked products back into the assembly source code
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use aluvm::data::{FloatLayout, MaybeNumber, Number};
+use aluvm::isa::{
+    ArithmeticOp, BitwiseOp, Bytecode, BytesOp, CmpOp, ControlFlowOp, DigestOp, MoveOp, PutOp,
+    Secp256k1Op,
+};
+use aluvm::library::{Lib, LibId, LibSite};
+
+use crate::compiler::{float_number, float_text};
+use crate::formatter::{INDENT, MNEMONIC_WIDTH};
+use crate::isa::{AluReOp, ExtOp, Instr, RgbOp};
+use crate::linker::LibManager;
+use crate::module::{DataType, Module, Reloc, Variable};
+use crate::product::Product;
+use crate::DecompileError;
+
+/// Formats register operand from the register name (or the register set for `s16`) and its index
+macro_rules! reg {
+    ($reg:expr, $idx:expr) => {
+        format!("{}{}", $reg, $idx)
+    };
+}
+
+impl Module {
+    /// Decompiles module into the source code. Routines are named after the module exports,
+    /// while libraries and jump targets get synthetic names. Assembling the produced source code
+    /// results in the same module.
+    pub fn decompile(&self) -> Result<String, DecompileError> {
+        let mut calls = BTreeMap::<(LibId, u16), String>::new();
+        for (id, routine) in self.imports.routines() {
+            let pos = calls.range((id, 0)..=(id, u16::MAX)).count() as u16;
+            calls.insert((id, pos), routine.to_owned());
+        }
+        let externs = self
+            .relocs
+            .iter()
+            .filter_map(|(offset, reloc)| match reloc {
+                Reloc::Extern(routine) => Some((offset, routine.clone())),
+                Reloc::Import(_) => None,
+            })
+            .collect();
+        Decompiler::with(&self.inner, &self.vars, &self.exports, externs, calls).decompile()
+    }
+}
+
+impl Product {
+    /// Decompiles linked library or binary into the source code. Names of the external routines
+    /// are resolved from the exports of the libraries provided by `lib_man`; routines of a
+    /// binary other than its entry point get synthetic names.
+    ///
+    /// Assembling and linking the produced source code against the same libraries results in the
+    /// same code, as long as routine names follow the order of the routines in the code.
+    pub fn decompile(&self, lib_man: &mut LibManager) -> Result<String, DecompileError> {
+        let (inner, exports) = match self {
+            Product::Lib(lib) => (&lib.inner, lib.exports.clone()),
+            Product::Bin(bin) => (&bin.inner, bmap! { s!(".MAIN") => bin.entry_point }),
+        };
+        let mut calls = bmap! {};
+        for id in inner.inner.libs.iter().copied() {
+            if let Some(lib) = lib_man.get(id) {
+                calls.extend(lib.exports.iter().map(|(name, pos)| ((id, *pos), name.clone())));
+            }
+        }
+        Decompiler::with(&inner.inner, &inner.vars, &exports, bmap! {}, calls).decompile()
+    }
+}
+
+struct Decompiler<'m> {
+    lib: &'m Lib,
+    vars: &'m [Variable],
+    /// Names of the routines by their code offsets
+    routines: BTreeMap<u16, String>,
+    /// Names of the routines called by the instructions at the given offsets, which are not
+    /// defined by the module
+    externs: BTreeMap<u16, String>,
+    /// Names of the library routines by their call sites
+    calls: BTreeMap<(LibId, u16), String>,
+    lib_names: BTreeMap<LibId, String>,
+    labels: BTreeSet<u16>,
+}
+
+impl<'m> Decompiler<'m> {
+    fn with(
+        lib: &'m Lib,
+        vars: &'m [Variable],
+        exports: &BTreeMap<String, u16>,
+        externs: BTreeMap<u16, String>,
+        calls: BTreeMap<(LibId, u16), String>,
+    ) -> Self {
+        let mut routines = bmap! {};
+        for (name, offset) in exports {
+            routines.entry(*offset).or_insert_with(|| name.clone());
+        }
+        let lib_names =
+            lib.libs.iter().enumerate().map(|(no, id)| (*id, format!("lib{}", no))).collect();
+        Decompiler { lib, vars, routines, externs, calls, lib_names, labels: bset! {} }
+    }
+
+    fn decompile(mut self) -> Result<String, DecompileError> {
+        let code = self.lib.disassemble::<Instr>()?;
+        let mut offsets = Vec::with_capacity(code.len());
+        let mut pos = 0u16;
+        for instr in &code {
+            offsets.push(pos);
+            pos = pos.wrapping_add(instr.byte_count());
+        }
+        let starts = offsets.iter().copied().collect::<BTreeSet<_>>();
+
+        if !code.is_empty() {
+            self.routines.entry(0).or_insert_with(|| s!("routine_0000"));
+        }
+        for (offset, instr) in offsets.iter().zip(&code) {
+            match instr {
+                Instr::ControlFlow(ControlFlowOp::Routine(to))
+                    if !self.externs.contains_key(offset) =>
+                {
+                    if !starts.contains(to) {
+                        return Err(DecompileError::TargetMisaligned(*offset, *to));
+                    }
+                    self.routines.entry(*to).or_insert_with(|| format!("routine_{:04x}", to));
+                }
+                _ => {}
+            }
+        }
+        for (offset, name) in &self.routines {
+            if !starts.contains(offset) {
+                return Err(DecompileError::RoutineMisaligned(name.clone(), *offset));
+            }
+        }
+        for (offset, instr) in offsets.iter().zip(&code) {
+            if let Instr::ControlFlow(ControlFlowOp::Jmp(to) | ControlFlowOp::Jif(to)) = instr {
+                if !starts.contains(to) {
+                    return Err(DecompileError::TargetMisaligned(*offset, *to));
+                }
+                if self.routine_of(*to) != self.routine_of(*offset) {
+                    return Err(DecompileError::JumpOutside(*offset, *to));
+                }
+                self.labels.insert(*to);
+            }
+        }
+
+        let mut segments = vec![];
+
+        let isae = self.lib.isae.iter().map(|isa| indented(&isa.to_string())).collect::<Vec<_>>();
+        if !isae.is_empty() {
+            segments.push((s!(".ISAE"), isae));
+        }
+
+        if !self.lib_names.is_empty() {
+            let libs =
+                self.lib_names.iter().map(|(id, name)| indented(&format!("{} {}", name, id)));
+            segments.push((s!(".LIBS"), libs.collect()));
+        }
+
+        if !self.vars.is_empty() {
+            let vars = self
+                .vars
+                .iter()
+                .enumerate()
+                .map(|(index, var)| self.var(index as u16, var).map(|decl| indented(&decl)))
+                .collect::<Result<_, _>>()?;
+            segments.push((s!(".INPUT"), vars));
+        }
+
+        let mut routines = self.routines.iter().collect::<Vec<_>>();
+        routines.sort_by_key(|(offset, name)| (name.as_str() != ".MAIN", **offset));
+        for (start, name) in routines {
+            let end = self.routines.range(start..).nth(1).map(|(end, _)| *end);
+            let mut lines = vec![];
+            for (offset, instr) in offsets.iter().zip(&code) {
+                if *offset < *start || end.map(|end| *offset >= end).unwrap_or_default() {
+                    continue;
+                }
+                let statement = self.statement(*offset, instr)?;
+                lines.push(if self.labels.contains(offset) {
+                    format!("{:<2$}{}", format!("label_{:04x}:", offset), statement, INDENT)
+                } else {
+                    indented(&statement)
+                });
+            }
+            let header = match name.as_str() {
+                ".MAIN" => s!(".MAIN"),
+                name => format!(".ROUTINE {}", name),
+            };
+            segments.push((header, lines));
+        }
+
+        let mut out = String::new();
+        for (no, (header, lines)) in segments.into_iter().enumerate() {
+            if no > 0 {
+                out.push('\n');
+            }
+            out.push_str(&header);
+            out.push('\n');
+            for line in lines {
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns offset of the routine containing code at the given offset
+    fn routine_of(&self, offset: u16) -> Option<u16> {
+        self.routines.range(..=offset).next_back().map(|(start, _)| *start)
+    }
+
+    fn var_name(&self, index: u16) -> String {
+        match self.vars.get(index as usize).and_then(|var| var.name.as_ref()) {
+            Some(name) => format!("${}", name),
+            // Zero padding keeps the order of the variables, which are sorted by name
+            None => format!("$var{:05}", index),
+        }
+    }
+
+    /// Constructs input variable declaration
+    fn var(&self, index: u16, var: &Variable) -> Result<String, DecompileError> {
+        let (ty, default) = match &var.data {
+            DataType::ByteStr(bytes) => (s!("bytes"), bytes.as_deref().map(bytes_lit)),
+            DataType::Int(layout, default) => {
+                let ty = format!("{}{}", if layout.signed { 'i' } else { 'u' }, layout.bytes * 8);
+                let default = Option::<Number>::from(*default)
+                    .map(|number| int_lit(number.as_ref(), layout.signed))
+                    .transpose()?;
+                (ty, default)
+            }
+            DataType::Float(layout, default) => {
+                let default = Option::<Number>::from(*default)
+                    .map(|number| float_lit(*layout, number))
+                    .transpose()?;
+                (s!(float_type(*layout)), default)
+            }
+        };
+        let mut decl = format!("{}: {}", self.var_name(index), ty);
+        if let Some(default) = default {
+            decl.push_str(" = ");
+            decl.push_str(&default);
+        }
+        decl.push(' ');
+        decl.push_str(&str_lit(&var.info));
+        Ok(decl)
+    }
+
+    /// Constructs statement for the instruction at the given code offset, without a label
+    fn statement(&self, offset: u16, instr: &Instr) -> Result<String, DecompileError> {
+        let unsupported = || DecompileError::Unsupported(offset, instr.to_string());
+        let int = |value: &MaybeNumber| -> Result<String, DecompileError> {
+            let number =
+                Option::<Number>::from(*value).ok_or(DecompileError::ValueUndefined(offset))?;
+            int_lit(number.as_ref(), false)
+        };
+        let goto = |to: &u16| format!("label_{:04x}", to);
+        let call = |site: &LibSite| {
+            let lib = self.lib_names.get(&site.lib);
+            let routine = self.calls.get(&(site.lib, site.pos));
+            match (lib, routine) {
+                (Some(lib), Some(routine)) => Ok(format!("{}->{}", lib, routine)),
+                _ => Err(DecompileError::CallUnresolved(offset, site.lib, site.pos)),
+            }
+        };
+
+        Ok(match instr {
+            Instr::ControlFlow(op) => match op {
+                ControlFlowOp::Fail => code("fail", "", &[]),
+                ControlFlowOp::Succ => code("succ", "", &[]),
+                ControlFlowOp::Jmp(to) => code("jmp", "", &[goto(to)]),
+                ControlFlowOp::Jif(to) => code("jif", "", &[goto(to)]),
+                ControlFlowOp::Routine(to) => {
+                    let name = match self.externs.get(&offset) {
+                        Some(name) => name,
+                        None => &self.routines[to],
+                    };
+                    code("routine", "", &[name.clone()])
+                }
+                ControlFlowOp::Call(site) => code("call", "", &[call(site)?]),
+                ControlFlowOp::Exec(site) => code("exec", "", &[call(site)?]),
+                ControlFlowOp::Ret => code("ret", "", &[]),
+            },
+
+            Instr::Put(op) => match op {
+                PutOp::ClrA(reg, idx) => code("clr", "", &[reg!(reg, idx)]),
+                PutOp::ClrF(reg, idx) => code("clr", "", &[reg!(reg, idx)]),
+                PutOp::ClrR(reg, idx) => code("clr", "", &[reg!(reg, idx)]),
+                PutOp::PutA(reg, idx, val) => code("put", "", &[reg!(reg, idx), int(&**val)?]),
+                PutOp::PutF(reg, idx, val) => {
+                    let number = Option::<Number>::from(**val)
+                        .ok_or(DecompileError::ValueUndefined(offset))?;
+                    let layout = match number.layout() {
+                        aluvm::data::Layout::Float(layout) => layout,
+                        aluvm::data::Layout::Integer(_) => return Err(unsupported()),
+                    };
+                    code("put", "", &[reg!(reg, idx), float_lit(layout, number)?])
+                }
+                PutOp::PutR(reg, idx, val) => code("put", "", &[reg!(reg, idx), int(&**val)?]),
+                PutOp::PutIfA(reg, idx, val) => code("putif", "", &[int(&**val)?, reg!(reg, idx)]),
+                PutOp::PutIfR(reg, idx, val) => code("putif", "", &[int(&**val)?, reg!(reg, idx)]),
+            },
+
+            Instr::Move(op) => match op {
+                MoveOp::MovA(reg, idx1, idx2) => {
+                    code("mov", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::MovF(reg, idx1, idx2) => {
+                    code("mov", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::MovR(reg, idx1, idx2) => {
+                    code("mov", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::DupA(reg, idx1, idx2) => {
+                    code("dup", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::DupF(reg, idx1, idx2) => {
+                    code("dup", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::DupR(reg, idx1, idx2) => {
+                    code("dup", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::SwpA(reg, idx1, idx2) => {
+                    code("swp", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::SwpF(reg, idx1, idx2) => {
+                    code("swp", "", &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                MoveOp::CpyA(reg1, idx1, reg2, idx2) => {
+                    code("cpy", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                MoveOp::CpyR(reg1, idx1, reg2, idx2) => {
+                    code("cpy", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                MoveOp::CnvA(reg1, idx1, reg2, idx2) => {
+                    code("cnv", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                MoveOp::CnvF(reg1, idx1, reg2, idx2) => {
+                    code("cnv", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                MoveOp::CnvAF(reg1, idx1, reg2, idx2) => {
+                    code("cnv", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                MoveOp::CnvFA(reg1, idx1, reg2, idx2) => {
+                    code("cnv", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                MoveOp::SpyAR(reg1, idx1, reg2, idx2) => {
+                    code("spy", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                _ => return Err(unsupported()),
+            },
+
+            Instr::Cmp(op) => match op {
+                CmpOp::GtA(flag, reg, idx1, idx2) => {
+                    code("gt", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                CmpOp::LtA(flag, reg, idx1, idx2) => {
+                    code("lt", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                CmpOp::GtF(flag, reg, idx1, idx2) => {
+                    code("gt", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                CmpOp::LtF(flag, reg, idx1, idx2) => {
+                    code("lt", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                CmpOp::GtR(reg, idx1, idx2) => code("gt", "", &[reg!(reg, idx1), reg!(reg, idx2)]),
+                CmpOp::LtR(reg, idx1, idx2) => code("lt", "", &[reg!(reg, idx1), reg!(reg, idx2)]),
+                CmpOp::EqA(flag, reg, idx1, idx2) => {
+                    code("eq", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                CmpOp::EqF(flag, reg, idx1, idx2) => {
+                    code("eq", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                CmpOp::EqR(flag, reg, idx1, idx2) => {
+                    code("eq", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                CmpOp::IfZA(reg, idx) => code("ifz", "", &[reg!(reg, idx)]),
+                CmpOp::IfZR(reg, idx) => code("ifz", "", &[reg!(reg, idx)]),
+                CmpOp::IfNA(reg, idx) => code("ifn", "", &[reg!(reg, idx)]),
+                CmpOp::IfNR(reg, idx) => code("ifn", "", &[reg!(reg, idx)]),
+                CmpOp::St(flag, reg, idx) => code("st", flag, &[reg!(reg, idx)]),
+                CmpOp::StInv => code("stinv", "", &[]),
+            },
+
+            Instr::Arithmetic(op) => match op {
+                ArithmeticOp::Neg(reg, idx) => code("neg", "", &[reg!(reg, idx)]),
+                ArithmeticOp::Stp(reg, idx, step) => match step.as_i8() {
+                    1 => code("inc", "", &[reg!(reg, idx)]),
+                    -1 => code("dec", "", &[reg!(reg, idx)]),
+                    // Step of -128 can't be encoded neither by `add` nor by `sub` literal
+                    i8::MIN => return Err(unsupported()),
+                    step if step < 0 => code("sub", "", &[reg!(reg, idx), (-step).to_string()]),
+                    step => code("add", "", &[reg!(reg, idx), step.to_string()]),
+                },
+                ArithmeticOp::AddA(flags, reg, idx1, idx2) => {
+                    code("add", flags, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::AddF(flag, reg, idx1, idx2) => {
+                    code("add", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::SubA(flags, reg, idx1, idx2) => {
+                    code("sub", flags, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::SubF(flag, reg, idx1, idx2) => {
+                    code("sub", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::MulA(flags, reg, idx1, idx2) => {
+                    code("mul", flags, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::MulF(flag, reg, idx1, idx2) => {
+                    code("mul", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::DivA(flags, reg, idx1, idx2) => {
+                    code("div", flags, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::DivF(flag, reg, idx1, idx2) => {
+                    code("div", flag, &[reg!(reg, idx1), reg!(reg, idx2)])
+                }
+                ArithmeticOp::Rem(reg1, idx1, reg2, idx2) => {
+                    code("rem", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                ArithmeticOp::Abs(reg, idx) => code("abs", "", &[reg!(reg, idx)]),
+            },
+
+            Instr::Bitwise(op) => match op {
+                BitwiseOp::And(reg, idx1, idx2, idx3) => {
+                    code("and", "", &[reg!(reg, idx1), reg!(reg, idx2), reg!(reg, idx3)])
+                }
+                BitwiseOp::Or(reg, idx1, idx2, idx3) => {
+                    code("or", "", &[reg!(reg, idx1), reg!(reg, idx2), reg!(reg, idx3)])
+                }
+                BitwiseOp::Xor(reg, idx1, idx2, idx3) => {
+                    code("xor", "", &[reg!(reg, idx1), reg!(reg, idx2), reg!(reg, idx3)])
+                }
+                BitwiseOp::Not(reg, idx) => code("not", "", &[reg!(reg, idx)]),
+                BitwiseOp::Shl(reg1, idx1, reg2, idx2) => {
+                    code("shl", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                BitwiseOp::ShrA(flag, reg1, idx1, reg2, idx2) => {
+                    code("shr", flag, &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                BitwiseOp::ShrR(reg1, idx1, reg2, idx2) => {
+                    code("shr", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                BitwiseOp::Scl(reg1, idx1, reg2, idx2) => {
+                    code("scl", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                BitwiseOp::Scr(reg1, idx1, reg2, idx2) => {
+                    code("scr", "", &[reg!(reg1, idx1), reg!(reg2, idx2)])
+                }
+                BitwiseOp::RevA(reg, idx) => code("rev", "", &[reg!(reg, idx)]),
+                BitwiseOp::RevR(reg, idx) => code("rev", "", &[reg!(reg, idx)]),
+            },
+
+            Instr::Bytes(op) => match op {
+                BytesOp::Put(idx, val, false) => {
+                    code("put", "", &[reg!("s16", idx), bytes_lit(AsRef::<[u8]>::as_ref(&**val))])
+                }
+                BytesOp::Mov(idx1, idx2) => {
+                    code("mov", "", &[reg!("s16", idx1), reg!("s16", idx2)])
+                }
+                BytesOp::Swp(idx1, idx2) => {
+                    code("swp", "", &[reg!("s16", idx1), reg!("s16", idx2)])
+                }
+                BytesOp::Eq(idx1, idx2) => code("eq", "", &[reg!("s16", idx1), reg!("s16", idx2)]),
+                BytesOp::Rev(idx1, idx2) => {
+                    code("rev", "", &[reg!("s16", idx1), reg!("s16", idx2)])
+                }
+                BytesOp::Fill(idx0, idx1, idx2, idx3, flag) => code("fill", flag, &[
+                    reg!("s16", idx0),
+                    reg!("a16", idx1),
+                    reg!("a16", idx2),
+                    reg!("a8", idx3),
+                ]),
+                BytesOp::Len(idx0, reg, idx1) => {
+                    code("len", "", &[reg!("s16", idx0), reg!(reg, idx1)])
+                }
+                BytesOp::Cnt(idx0, idx1, idx2) => {
+                    code("cnt", "", &[reg!("s16", idx0), reg!("a8", idx1), reg!("a16", idx2)])
+                }
+                BytesOp::Con(idx0, idx1, idx2, idx3, idx4) => code("con", "", &[
+                    reg!("s16", idx0),
+                    reg!("s16", idx1),
+                    reg!("a16", idx2),
+                    reg!("a16", idx3),
+                    reg!("a16", idx4),
+                ]),
+                BytesOp::Find(idx0, idx1) => {
+                    code("find", "", &[reg!("s16", idx0), reg!("s16", idx1), s!("a16[0]")])
+                }
+                BytesOp::Extr(idx0, reg, idx1, idx2) => {
+                    code("extr", "", &[reg!("s16", idx0), reg!(reg, idx1), reg!("a16", idx2)])
+                }
+                BytesOp::Inj(idx0, reg, idx1, idx2) => {
+                    code("inj", "", &[reg!("s16", idx0), reg!(reg, idx1), reg!("a16", idx2)])
+                }
+                BytesOp::Join(idx0, idx1, idx2) => {
+                    code("join", "", &[reg!("s16", idx0), reg!("s16", idx1), reg!("s16", idx2)])
+                }
+                BytesOp::Splt(flag, offset, idx0, idx1, idx2) => code("splt", flag, &[
+                    reg!("s16", idx0),
+                    reg!("a16", offset),
+                    reg!("s16", idx1),
+                    reg!("s16", idx2),
+                ]),
+                BytesOp::Ins(flag, offset, idx0, idx1) => {
+                    code("ins", flag, &[reg!("s16", idx0), reg!("s16", idx1), reg!("a16", offset)])
+                }
+                _ => return Err(unsupported()),
+            },
+
+            Instr::Digest(op) => match op {
+                DigestOp::Ripemd(idx1, idx2) => {
+                    code("ripemd", "", &[reg!("s16", idx1), reg!("r160", idx2)])
+                }
+                DigestOp::Sha256(idx1, idx2) => {
+                    code("sha2", "", &[reg!("s16", idx1), reg!("r256", idx2)])
+                }
+                DigestOp::Sha512(idx1, idx2) => {
+                    code("sha2", "", &[reg!("s16", idx1), reg!("r512", idx2)])
+                }
+                _ => return Err(unsupported()),
+            },
+
+            Instr::Secp256k1(op) => match op {
+                Secp256k1Op::Gen(idx1, idx2) => {
+                    code("secpgen", "", &[reg!("r256", idx1), reg!("r512", idx2)])
+                }
+                Secp256k1Op::Neg(idx1, idx2) => {
+                    code("secpneg", "", &[reg!("r512", idx1), reg!("r512", idx2)])
+                }
+                Secp256k1Op::Add(idx1, idx2) => {
+                    code("secpadd", "", &[reg!("r512", idx1), reg!("r512", idx2)])
+                }
+                Secp256k1Op::Mul(reg, idx0, idx1, idx2) => {
+                    code("secpmul", "", &[reg!(reg, idx0), reg!("r512", idx1), reg!("r512", idx2)])
+                }
+            },
+
+            Instr::ExtensionCodes(ExtOp::AluRe(op)) => {
+                let (reg, var) = match op {
+                    AluReOp::ReadA(reg, idx, var) => (reg!(reg, idx), *var),
+                    AluReOp::ReadF(reg, idx, var) => (reg!(reg, idx), *var),
+                    AluReOp::ReadR(reg, idx, var) => (reg!(reg, idx), *var),
+                    AluReOp::ReadS(idx, var) => (reg!("s16", idx), *var),
+                };
+                if var as usize >= self.vars.len() {
+                    return Err(DecompileError::VarAbsent(offset, var));
+                }
+                code("read", "", &[reg, self.var_name(var)])
+            }
+
+            Instr::ExtensionCodes(ExtOp::Rgb(op)) => match op {
+                RgbOp::Scn(flag, ty, idx) => code("scn", flag, &[ty.to_string(), reg!("a16", idx)]),
+                RgbOp::Pld(flag, ty, src, dst1, dst2) => code("pld", flag, &[
+                    ty.to_string(),
+                    reg!("a16", src),
+                    reg!("r512", dst1),
+                    reg!("r512", dst2),
+                ]),
+            },
+
+            Instr::Nop => code("nop", "", &[]),
+
+            _ => return Err(unsupported()),
+        })
+    }
+}
+
+/// Formats statement from the mnemonic, its flags and operands
+fn code(mnemonic: &str, flags: impl Display, operands: &[String]) -> String {
+    let flags = flags.to_string();
+    let operator =
+        if flags.is_empty() { mnemonic.to_owned() } else { format!("{}.{}", mnemonic, flags) };
+    if operands.is_empty() {
+        return operator;
+    }
+    format!("{:<2$} {}", operator, operands.join(", "), MNEMONIC_WIDTH - 1)
+}
+
+fn indented(code: &str) -> String { format!("{0:1$}{2}", "", INDENT, code) }
+
+/// Constructs hex literal for integer given by its little-endian bytes. Negative values of signed
+/// integers are written with minus sign.
+fn int_lit(le_bytes: &[u8], signed: bool) -> Result<String, DecompileError> {
+    if signed && le_bytes.last().map(|byte| byte & 0x80 != 0).unwrap_or_default() {
+        let mut carry = true;
+        let magnitude = le_bytes
+            .iter()
+            .map(|byte| {
+                let (byte, overflow) = (!byte).overflowing_add(carry as u8);
+                carry = overflow;
+                byte
+            })
+            .collect::<Vec<_>>();
+        return Ok(format!("-{}", int_lit(&magnitude, false)?));
+    }
+    let be_bytes = le_bytes.iter().rev().skip_while(|byte| **byte == 0).collect::<Vec<_>>();
+    // Integer literals are limited to 1024 bits
+    if be_bytes.len() > 128 {
+        return Err(DecompileError::IntTooLarge(be_bytes.len()));
+    }
+    if be_bytes.is_empty() {
+        return Ok(s!("0x00"));
+    }
+    Ok(be_bytes.into_iter().fold(s!("0x"), |lit, byte| lit + &format!("{:02X}", byte)))
+}
+
+/// Constructs float literal with integer significand, like `15.0e-1`, which is parsed back into
+/// exactly the same float number
+fn float_lit(layout: FloatLayout, number: Number) -> Result<String, DecompileError> {
+    let text = number.to_string();
+    let inexact = || DecompileError::FloatInexact(text.clone());
+
+    let (neg, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, text.trim_start_matches('+')),
+    };
+    let (mantissa, exp) = unsigned.split_once(|c| c == 'e' || c == 'E').unwrap_or((unsigned, "0"));
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int.is_empty() && frac.is_empty()
+        || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit())
+    {
+        return Err(inexact());
+    }
+    let mut exp = i32::from_str(exp).map_err(|_| inexact())? - frac.len() as i32;
+    let mut digits = format!("{}{}", int, frac).trim_start_matches('0').to_owned();
+    while digits.ends_with('0') {
+        digits.pop();
+        exp += 1;
+    }
+    if digits.is_empty() {
+        digits = s!("0");
+        exp = 0;
+    }
+    let significand = u128::from_str(&digits).map_err(|_| inexact())?;
+    let exp = i16::try_from(exp).map_err(|_| inexact())?;
+
+    let (parsed, _) =
+        float_number(layout, &float_text(neg, significand, "0", exp)).map_err(|_| inexact())?;
+    match Option::<Number>::from(parsed) {
+        Some(parsed) if parsed.as_ref() == number.as_ref() => {}
+        _ => return Err(inexact()),
+    }
+    Ok(format!("{}{}.0e{}", if neg { "-" } else { "" }, significand, exp))
+}
+
+fn bytes_lit(bytes: &[u8]) -> String {
+    let hex = bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<String>();
+    format!("x\"{}\"", hex)
+}
+
+fn str_lit(s: &str) -> String {
+    let mut lit = s!("\"");
+    for c in s.chars() {
+        match c {
+            '"' => lit.push_str("\\\""),
+            '\\' => lit.push_str("\\\\"),
+            '\n' => lit.push_str("\\n"),
+            '\r' => lit.push_str("\\r"),
+            '\t' => lit.push_str("\\t"),
+            c if c.is_control() => lit.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => lit.push(c),
+        }
+    }
+    lit.push('"');
+    lit
+}
+
+fn float_type(layout: FloatLayout) -> &'static str {
+    match layout {
+        FloatLayout::BFloat16 => "f16b",
+        FloatLayout::IeeeHalf => "f16",
+        FloatLayout::IeeeSingle => "f32",
+        FloatLayout::IeeeDouble => "f64",
+        FloatLayout::X87DoubleExt => "f80",
+        FloatLayout::IeeeQuad => "f128",
+        FloatLayout::IeeeOct => "f256",
+        FloatLayout::FloatTapered => "apfloat",
+    }
+}
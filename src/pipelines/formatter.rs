@@ -0,0 +1,487 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Source code formatter re-emitting assembly in the canonical layout while keeping comments
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use pest::iterators::Pair;
+use pest::Parser as ParserTrait;
+
+use crate::ast::Program;
+use crate::issues::Diagnostic;
+use crate::module::Module;
+use crate::parser::{Parser, Rule};
+use crate::source::SourceMap;
+use crate::{BuildError, LexerError, MainError};
+
+/// Column at which mnemonics and segment items start
+pub(crate) const INDENT: usize = 16;
+/// Width of the mnemonic column, after which operands start
+pub(crate) const MNEMONIC_WIDTH: usize = 8;
+/// Minimal column at which trailing comments are aligned
+const COMMENT_COLUMN: usize = 48;
+
+/// Formats source code of the file `name` into the canonical layout.
+///
+/// Segments are ordered as `.INCLUDE`, `.ISAE`, `.LIBS`, `.CONST`, `.INPUT`, `.MACRO`, `.MAIN`,
+/// `.ROUTINE` and `.TEST`, keeping the original order of the segments of the same kind. Labels
+/// start at the first column, mnemonics and operands are aligned into columns and trailing
+/// comments are aligned within each segment. Formatted code is verified to parse back into the
+/// same sequence of tokens and to compile into the same module with the same issues as the
+/// original code.
+pub fn format(name: &str, text: &str) -> Result<String, MainError> {
+    let program = parse(name, text)?;
+    let formatted = Formatter::with(text, program.clone()).format(program.clone());
+    if signature(parse(name, &formatted)?) != signature(program)
+        || compile(name, &formatted) != compile(name, text)
+    {
+        return Err(BuildError::FormatMismatch(name.to_owned()).into());
+    }
+    Ok(formatted)
+}
+
+/// Compiles source code into a module, returning `None` if the code contains errors, together
+/// with errno and message of each reported issue. Both must not change with formatting; issue
+/// locations are not compared since formatting moves the code.
+fn compile(name: &str, text: &str) -> (Option<Module>, Vec<(u16, String)>) {
+    let sources = match SourceMap::with(name, text) {
+        Ok(sources) => sources,
+        Err(err) => return (None, summary(vec![err.diagnostic()])),
+    };
+    let (program, issues) = match Program::analyze(&sources) {
+        Ok(analyzed) => analyzed,
+        // Lexer error messages quote the source code, which is changed by formatting
+        Err(err) => return (None, vec![(err.errno(), String::new())]),
+    };
+    let mut reported = summary(issues.diagnostics());
+    if issues.has_errors() {
+        return (None, reported);
+    }
+    match program.compile(&mut None) {
+        Ok((module, issues)) => {
+            reported.extend(summary(issues.diagnostics()));
+            (Some(module).filter(|_| !issues.has_errors()), reported)
+        }
+        Err(err) => {
+            reported.push((err.errno(), err.to_string()));
+            (None, reported)
+        }
+    }
+}
+
+fn summary(diagnostics: Vec<Diagnostic>) -> Vec<(u16, String)> {
+    diagnostics.into_iter().map(|diagnostic| (diagnostic.errno, diagnostic.message)).collect()
+}
+
+fn parse<'t>(name: &str, text: &'t str) -> Result<Pair<'t, Rule>, MainError> {
+    Ok(Parser::parse(Rule::program, text)
+        .map_err(|err| MainError::Parser(name.to_owned(), err))?
+        .next()
+        .ok_or(LexerError::ProgramAbsent)?)
+}
+
+/// Line of the formatted code
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+enum Line {
+    Blank,
+    /// Comment occupying the whole line
+    Comment(String),
+    /// Comment occupying the whole line, which continues trailing comment of the previous line
+    Continuation(String),
+    /// Code followed by an optional comment
+    Code(String, Option<String>),
+}
+
+/// Formatted segment together with the comments preceding it
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+struct Segment {
+    rank: u8,
+    leading: Vec<Line>,
+    lines: Vec<Line>,
+}
+
+struct Formatter<'t> {
+    line_starts: Vec<usize>,
+    /// Columns and text of the comments of each line, which were not emitted yet
+    comments: BTreeMap<usize, (usize, &'t str)>,
+    /// First line of the source code which was not processed yet
+    next_line: usize,
+    /// Line and column of the last emitted trailing comment
+    last_trailing: Option<(usize, usize)>,
+}
+
+impl<'t> Formatter<'t> {
+    fn with(text: &'t str, program: Pair<'t, Rule>) -> Self {
+        let tokens = program
+            .into_inner()
+            .flatten()
+            .filter(is_leaf)
+            .map(|pair| token(&pair))
+            .collect::<Vec<_>>();
+
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(pos, _)| pos + 1));
+
+        let mut comments = BTreeMap::new();
+        for (line, start) in line_starts.iter().copied().enumerate() {
+            let end = text[start..].find('\n').map(|pos| start + pos).unwrap_or(text.len());
+            if let Some(pos) = text[start..end]
+                .match_indices(';')
+                .map(|(pos, _)| start + pos)
+                .find(|pos| !tokens.iter().any(|token| token.contains(pos)))
+            {
+                comments.insert(line, (pos - start, text[pos..end].trim_end()));
+            }
+        }
+
+        Formatter { line_starts, comments, next_line: 0, last_trailing: None }
+    }
+
+    fn format(mut self, program: Pair<'t, Rule>) -> String {
+        let mut segments = vec![];
+        for pair in program.into_inner() {
+            let rank = match rank(&pair) {
+                Some(rank) => rank,
+                None => continue,
+            };
+            let leading = self.leading(self.line_of(pair.as_span().start()));
+            let lines = self.segment(pair);
+            segments.push(Segment { rank, leading, lines });
+        }
+        let mut trailing = self.leading(self.line_starts.len());
+        segments.sort_by_key(|segment| segment.rank);
+
+        let mut out = String::new();
+        for (no, mut segment) in segments.into_iter().enumerate() {
+            if no > 0 {
+                out.push('\n');
+            }
+            trim_blanks(&mut segment.leading);
+            render(&mut out, &segment.leading, 0);
+            render(&mut out, &segment.lines, INDENT);
+        }
+        trim_blanks(&mut trailing);
+        if !trailing.is_empty() {
+            out.push('\n');
+            render(&mut out, &trailing, 0);
+        }
+        out
+    }
+
+    fn segment(&mut self, pair: Pair<'t, Rule>) -> Vec<Line> {
+        let span = pair.as_span();
+        let rule = pair.as_rule();
+        let header_line = self.line_of(span.start());
+        let mut inner = pair.into_inner();
+        let header = match rule {
+            Rule::include => {
+                format!(".INCLUDE {}", inner.next().map(|path| path.as_str()).unwrap_or_default())
+            }
+            Rule::isae => s!(".ISAE"),
+            Rule::libs => s!(".LIBS"),
+            Rule::data => s!(".CONST"),
+            Rule::input => s!(".INPUT"),
+            Rule::macro_def => {
+                let name = inner.next().map(|name| name.as_str()).unwrap_or_default();
+                let params = inner
+                    .next()
+                    .map(|params| params.into_inner().map(|var| var.as_str()).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                format!(".MACRO {} {}", name, params.join(", ")).trim_end().to_owned()
+            }
+            _ => match inner.peek() {
+                Some(name) if name.as_rule() == Rule::routine_name => {
+                    inner.next();
+                    format!(".ROUTINE {}", name.as_str())
+                }
+                Some(name) if name.as_rule() == Rule::test_name => {
+                    inner.next();
+                    format!(".TEST {}", name.as_str())
+                }
+                _ => s!(".MAIN"),
+            },
+        };
+        let header_comment = self.trailing(header_line);
+        self.next_line = header_line + 1;
+
+        let mut body = vec![];
+        for item in inner {
+            let code = match item.as_rule() {
+                Rule::instruction => {
+                    self.instruction(item, &mut body);
+                    continue;
+                }
+                Rule::isae_name => item.as_str().to_owned(),
+                Rule::lib_def => {
+                    let parts = item.clone().into_inner().map(|part| part.as_str());
+                    parts.collect::<Vec<_>>().join(" ")
+                }
+                Rule::const_decl => {
+                    let mut parts = item.clone().into_inner();
+                    let name = parts.next().map(|var| var.as_str()).unwrap_or_default();
+                    format!("{} = {}", name, parts.next().map(operand).unwrap_or_default())
+                }
+                Rule::input_decl => {
+                    item.clone().into_inner().fold(s!(""), |code, part| match part.as_rule() {
+                        Rule::var => code + part.as_str(),
+                        Rule::input_default => {
+                            let lit = part.into_inner().next().map(|lit| lit.as_str());
+                            code + " = " + lit.unwrap_or_default()
+                        }
+                        Rule::input_info => code + " " + part.as_str(),
+                        _ => code + ": " + part.as_str(),
+                    })
+                }
+                Rule::routine_main => continue,
+                _ => item.as_str().trim().to_owned(),
+            };
+            let (first, last) = self.lines(tokens(&item));
+            body.extend(self.leading(first));
+            let comments =
+                (first..=last).filter_map(|line| self.trailing(line)).collect::<Vec<_>>();
+            let comment = if comments.is_empty() { None } else { Some(comments.join(" ")) };
+            body.push(Line::Code(indented(&code), comment));
+            self.next_line = self.next_line.max(last + 1);
+        }
+
+        let mut lines = vec![Line::Code(header, header_comment)];
+        if rule == Rule::macro_def {
+            let end_line = self.line_of(span.start() + span.as_str().rfind(".ENDM").unwrap_or(0));
+            body.extend(self.leading(end_line));
+            trim_blanks(&mut body);
+            lines.extend(body);
+            lines.push(Line::Code(s!(".ENDM"), self.trailing(end_line)));
+            self.next_line = end_line + 1;
+        } else {
+            trim_blanks(&mut body);
+            lines.extend(body);
+        }
+        lines
+    }
+
+    fn instruction(&mut self, pair: Pair<'t, Rule>, body: &mut Vec<Line>) {
+        let (first, last) = self.lines(tokens(&pair));
+        body.extend(self.leading(first));
+
+        let mut label = None;
+        let mut operator = "";
+        let mut operands = vec![];
+        for part in pair.into_inner() {
+            match part.as_rule() {
+                Rule::label => label = Some(part.as_str()),
+                Rule::operator => operator = part.as_str(),
+                _ => operands.push(operand(part)),
+            }
+        }
+        let code = if operands.is_empty() {
+            operator.to_owned()
+        } else {
+            format!("{:<2$} {}", operator, operands.join(", "), MNEMONIC_WIDTH - 1)
+        };
+
+        let label_comment = if first < last { self.trailing(first) } else { None };
+        let inner_comments =
+            (first + 1..last).filter_map(|line| self.comment(line)).collect::<Vec<_>>();
+        let comment = self.trailing(last);
+        match label {
+            Some(label)
+                if label_comment.is_some()
+                    || !inner_comments.is_empty()
+                    || label.len() + 2 > INDENT =>
+            {
+                body.push(Line::Code(format!("{}:", label), label_comment));
+                body.extend(inner_comments);
+                body.push(Line::Code(indented(&code), comment));
+            }
+            Some(label) => body.push(Line::Code(
+                format!("{:<2$}{}", format!("{}:", label), code, INDENT),
+                comment,
+            )),
+            None => body.push(Line::Code(indented(&code), comment)),
+        }
+        self.next_line = self.next_line.max(last + 1);
+    }
+
+    /// Collects comments and blank lines which precede the line `to` and were not processed yet
+    fn leading(&mut self, to: usize) -> Vec<Line> {
+        let mut lines = vec![];
+        for line in self.next_line..to {
+            match self.comment(line) {
+                Some(comment) => lines.push(comment),
+                None if lines.last() == Some(&Line::Blank) => {}
+                None => lines.push(Line::Blank),
+            }
+        }
+        self.next_line = self.next_line.max(to);
+        lines
+    }
+
+    /// Takes comment occupying the whole line
+    fn comment(&mut self, line: usize) -> Option<Line> {
+        let (column, comment) = self.comments.remove(&line)?;
+        if line > 0 && self.last_trailing == Some((line - 1, column)) {
+            self.last_trailing = Some((line, column));
+            return Some(Line::Continuation(comment.to_owned()));
+        }
+        Some(Line::Comment(comment.to_owned()))
+    }
+
+    /// Takes comment following the code in the line
+    fn trailing(&mut self, line: usize) -> Option<String> {
+        let (column, comment) = self.comments.remove(&line)?;
+        self.last_trailing = Some((line, column));
+        Some(comment.to_owned())
+    }
+
+    fn line_of(&self, pos: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= pos) - 1
+    }
+
+    /// Returns first and last lines occupied by the byte range
+    fn lines(&self, range: Range<usize>) -> (usize, usize) {
+        (self.line_of(range.start), self.line_of(range.end.saturating_sub(1).max(range.start)))
+    }
+}
+
+/// Returns position of the segment in the canonical segment order
+fn rank(pair: &Pair<Rule>) -> Option<u8> {
+    Some(match pair.as_rule() {
+        Rule::include => 0,
+        Rule::isae => 1,
+        Rule::libs => 2,
+        Rule::data => 3,
+        Rule::input => 4,
+        Rule::macro_def => 5,
+        Rule::routine
+            if pair.clone().into_inner().next().map(|name| name.as_rule())
+                == Some(Rule::routine_main) =>
+        {
+            6
+        }
+        Rule::routine
+            if pair.clone().into_inner().next().map(|name| name.as_rule())
+                == Some(Rule::test_name) =>
+        {
+            8
+        }
+        Rule::routine => 7,
+        _ => return None,
+    })
+}
+
+/// Describes segments of a parsed program by their kind and the sequence of their tokens, which
+/// does not depend on the code layout and comments
+fn signature(program: Pair<'_, Rule>) -> Vec<(u8, Vec<(Rule, &str)>)> {
+    let mut segments = program
+        .into_inner()
+        .filter_map(|segment| {
+            let rank = rank(&segment)?;
+            let tokens = segment
+                .into_inner()
+                .flatten()
+                .filter(is_leaf)
+                .map(|pair| {
+                    let len = token(&pair).len();
+                    (pair.as_rule(), &pair.as_str()[..len])
+                })
+                .collect();
+            Some((rank, tokens))
+        })
+        .collect::<Vec<_>>();
+    segments.sort_by_key(|(rank, _)| *rank);
+    segments
+}
+
+fn operand(pair: Pair<Rule>) -> String {
+    match pair.as_rule() {
+        Rule::call => {
+            let parts = pair.into_inner().map(|part| part.as_str()).collect::<Vec<_>>();
+            parts.join("->")
+        }
+        Rule::expr => expr(pair.as_str()),
+        _ => pair.as_str().to_owned(),
+    }
+}
+
+/// Normalizes constant expression, surrounding each binary operator with single spaces
+fn expr(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().filter(|c| !c.is_whitespace());
+    while let Some(c) = chars.next() {
+        match c {
+            '<' | '>' => {
+                chars.next();
+                out.push_str(&format!(" {}{} ", c, c));
+            }
+            '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^' => out.push_str(&format!(" {} ", c)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn is_leaf(pair: &Pair<Rule>) -> bool { pair.clone().into_inner().next().is_none() }
+
+/// Returns byte range of the token. Segment headers without content are parsed as tokens
+/// including trailing comments and new lines, which are excluded from the range.
+fn token(pair: &Pair<Rule>) -> Range<usize> {
+    let span = pair.as_span();
+    let len = match pair.as_rule() {
+        Rule::routine_main => ".MAIN".len(),
+        Rule::isae => ".ISAE".len(),
+        Rule::libs => ".LIBS".len(),
+        Rule::data => ".CONST".len(),
+        Rule::input => ".INPUT".len(),
+        _ => span.end() - span.start(),
+    };
+    span.start()..span.start() + len
+}
+
+/// Returns byte range from the start of the first to the end of the last token of the pair
+fn tokens(pair: &Pair<Rule>) -> Range<usize> {
+    let mut leaves = pair.clone().into_inner().flatten().filter(is_leaf).map(|leaf| token(&leaf));
+    match leaves.next() {
+        Some(first) => first.start..leaves.fold(first.end, |end, leaf| end.max(leaf.end)),
+        None => token(pair),
+    }
+}
+
+fn indented(code: &str) -> String { format!("{0:1$}{2}", "", INDENT, code) }
+
+/// Removes blank lines at the beginning and the end
+fn trim_blanks(lines: &mut Vec<Line>) {
+    while lines.last() == Some(&Line::Blank) {
+        lines.pop();
+    }
+    let start = lines.iter().position(|line| *line != Line::Blank).unwrap_or(lines.len());
+    lines.drain(..start);
+}
+
+/// Renders lines, aligning trailing comments and indenting standalone comments by `indent`
+fn render(out: &mut String, lines: &[Line], indent: usize) {
+    let column = lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Code(code, Some(_)) => Some(code.len() + 1),
+            _ => None,
+        })
+        .fold(COMMENT_COLUMN, usize::max);
+    for line in lines {
+        match line {
+            Line::Blank => {}
+            Line::Comment(comment) => out.push_str(&format!("{0:1$}{2}", "", indent, comment)),
+            Line::Continuation(comment) => out.push_str(&format!("{0:1$}{2}", "", column, comment)),
+            Line::Code(code, None) => out.push_str(code),
+            Line::Code(code, Some(comment)) => {
+                out.push_str(&format!("{0:1$}{2}", code, column, comment))
+            }
+        }
+        out.push('\n');
+    }
+}
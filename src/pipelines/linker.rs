@@ -5,55 +5,235 @@
 //     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
 // for Pandora Core AG
 
-use std::collections::BTreeMap;
-use std::fs::File;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 
 use aluvm::data::encoding::Decode;
-use aluvm::isa::{ControlFlowOp, Instr};
-use aluvm::library::{Cursor, Lib, LibId, Write};
+use aluvm::data::ByteStr;
+use aluvm::isa::{Bytecode, BytecodeError, ControlFlowOp};
+use aluvm::library::{Cursor, IsaSeg, Lib, LibId, LibSeg, LibSite, Read, Write, WriteError};
 
+use crate::debug::DebugInfo;
+use crate::isa::Instr;
 use crate::issues::{self, Issues, ReferenceError, ReferenceWarning};
-use crate::module::Module;
+use crate::module::{CallTable, Module, Reloc, RelocTable};
 use crate::product::{DyBin, DyInner, DyLib, EntryPoint, Product};
 use crate::{BuildError, InstrError, LinkerError};
 
 impl Module {
     pub fn link_bin(
-        &self,
+        modules: &BTreeMap<String, Module>,
         name: String,
         org: String,
         lib_man: &mut LibManager,
-    ) -> Result<(Product, Issues<issues::Linking>), LinkerError> {
+    ) -> Result<(Product, Issues<'static, issues::Linking>), LinkerError> {
         let mut issues = Issues::default();
 
-        let entry_point = self.exports.get(".MAIN").copied().unwrap_or_else(|| {
+        let module = Module::merge(modules, &mut issues)?;
+
+        let entry_point = module.exports.get(".MAIN").copied().unwrap_or_else(|| {
             issues.push_error_nospan(ReferenceError::BinaryNoMain);
             0
         });
 
         let product =
-            self.link(name, org, EntryPoint::BinMain(entry_point), lib_man, &mut issues)?;
+            module.link(name, org, EntryPoint::BinMain(entry_point), lib_man, &mut issues)?;
         Ok((product, issues))
     }
 
     pub fn link_lib(
-        &self,
+        modules: &BTreeMap<String, Module>,
         name: String,
         org: String,
         lib_man: &mut LibManager,
-    ) -> Result<(Product, Issues<issues::Linking>), LinkerError> {
+    ) -> Result<(Product, Issues<'static, issues::Linking>), LinkerError> {
         let mut issues = Issues::default();
 
-        if self.exports.get(".MAIN").is_some() {
+        let module = Module::merge(modules, &mut issues)?;
+
+        if module.exports.get(".MAIN").is_some() {
             issues.push_warning_nospan(ReferenceWarning::LibraryWithMain);
         }
 
-        let product =
-            self.link(name, org, EntryPoint::LibTable(self.exports.clone()), lib_man, &mut issues)?;
+        let entry_point = EntryPoint::LibTable(module.exports.clone());
+        let product = module.link(name, org, entry_point, lib_man, &mut issues)?;
         Ok((product, issues))
     }
 
+    /// Joins code and data segments of multiple modules into a single module, relocating jumps,
+    /// exports and relocation tables of each module, re-indexing external calls into the joined
+    /// call table and checking that routines called from one module are defined by the other
+    /// modules.
+    pub fn merge(
+        modules: &BTreeMap<String, Module>,
+        issues: &mut Issues<issues::Linking>,
+    ) -> Result<Module, LinkerError> {
+        let isae = IsaSeg::from_iter(
+            modules
+                .values()
+                .flat_map(|module| module.inner.isae.iter().cloned())
+                .collect::<BTreeSet<_>>(),
+        )
+        .map_err(|_| issues.push_error_nospan(ReferenceError::IsaeLengthOverflow))
+        .unwrap_or_default();
+        // Libraries which routines are never called are not included into the joined segment
+        let used_libs = modules
+            .values()
+            .flat_map(|module| module.imports.routines().map(|(libid, _)| libid))
+            .collect::<BTreeSet<_>>();
+        let declared_libs = modules
+            .values()
+            .flat_map(|module| module.inner.libs.iter().copied())
+            .collect::<BTreeSet<_>>();
+        let libs = LibSeg::from_iter(declared_libs.intersection(&used_libs).copied())
+            .map_err(|err| issues.push_error_nospan(err.into()))
+            .unwrap_or_default();
+
+        let mut code = ByteStr::default();
+        let mut cursor = Cursor::new(&mut code.bytes[..], &libs);
+        let mut vars = vec![];
+        let mut imports = CallTable::default();
+        let mut exports = bmap! {};
+        let mut relocs = RelocTable::default();
+        let mut owners: BTreeMap<&str, &str> = bmap! {};
+        let mut externs = bset! {};
+        let mut debug = None;
+        // Position at which the code of the first module not fitting the segments starts
+        let mut overflow = None;
+
+        'modules: for (module_name, module) in modules {
+            let base = cursor.pos();
+            let var_base = vars.len() as u16;
+            let instrs = module
+                .inner
+                .disassemble::<Instr>()
+                .map_err(|_| LinkerError::ModuleCode(module_name.clone()))?;
+
+            // Call table entries of the module are moved into the joined call table, so calls
+            // of external routines must refer to the joined table indexes
+            let mut calls = bmap! {};
+            let mut module_relocs = vec![];
+            let mut module_externs = vec![];
+            for (offset, reloc) in module.relocs.iter() {
+                let reloc = match reloc {
+                    Reloc::Import(site) => {
+                        let pos = module
+                            .imports
+                            .get(*site)
+                            .and_then(|routine| imports.find_or_insert(site.lib, routine));
+                        match pos {
+                            Ok(pos) => {
+                                calls.insert(offset, pos);
+                                Reloc::Import(LibSite::with(pos, site.lib))
+                            }
+                            Err(err) => {
+                                issues.push_error_nospan(err.into());
+                                continue;
+                            }
+                        }
+                    }
+                    Reloc::Extern(routine) => {
+                        module_externs.push((routine, module_name));
+                        Reloc::Extern(routine.clone())
+                    }
+                };
+                module_relocs.push((offset.wrapping_add(base), reloc));
+            }
+
+            for mut instr in instrs {
+                let offset = cursor.pos().wrapping_sub(base);
+                if let Instr::ControlFlow(
+                    ControlFlowOp::Call(ref mut site) | ControlFlowOp::Exec(ref mut site),
+                ) = instr
+                {
+                    if let Some(pos) = calls.get(&offset) {
+                        site.pos = *pos;
+                    }
+                }
+                if let Instr::ControlFlow(
+                    ControlFlowOp::Jmp(ref mut pos)
+                    | ControlFlowOp::Jif(ref mut pos)
+                    | ControlFlowOp::Routine(ref mut pos),
+                ) = instr
+                {
+                    *pos = pos.wrapping_add(base);
+                }
+                // Input variables of all modules are joined, so their indexes are shifted
+                if let Instr::ExtensionCodes(ref mut op) = instr {
+                    if let Some(var) = op.var_mut() {
+                        *var += var_base;
+                    }
+                }
+                match instr.encode(&mut cursor) {
+                    Ok(()) => {}
+                    Err(BytecodeError::Write(WriteError::LibAbsent(id))) => {
+                        return Err(LinkerError::ModuleLibAbsent(module_name.clone(), id))
+                    }
+                    Err(BytecodeError::Write(WriteError::CodeNotFittingSegment)) => {
+                        issues.push_error_nospan(ReferenceError::CodeLengthOverflow(
+                            module_name.clone(),
+                        ));
+                        overflow = Some(base);
+                        break 'modules;
+                    }
+                    Err(_) => {
+                        issues.push_error_nospan(ReferenceError::DataLengthOverflow(
+                            module_name.clone(),
+                        ));
+                        overflow = Some(base);
+                        break 'modules;
+                    }
+                }
+            }
+
+            for (offset, reloc) in module_relocs {
+                relocs.insert(offset, reloc);
+            }
+            externs.extend(module_externs);
+
+            vars.extend(module.vars.iter().cloned());
+
+            if let Some(info) = &module.debug {
+                debug.get_or_insert_with(DebugInfo::default).join(info, base);
+            }
+
+            for (routine, offset) in &module.exports {
+                if let Some(first) = owners.get(routine.as_str()) {
+                    issues.push_error_nospan(ReferenceError::RoutineRedefined {
+                        routine: routine.clone(),
+                        first: first.to_string(),
+                        second: module_name.clone(),
+                    });
+                    continue;
+                }
+                owners.insert(routine, module_name);
+                exports.insert(routine.clone(), offset.wrapping_add(base));
+            }
+        }
+
+        // Once the segments overflow, the remaining modules are not merged, so the routines they
+        // define can't be checked
+        if overflow.is_some() {
+            externs.clear();
+        }
+        for (routine, module_name) in externs {
+            if !exports.contains_key(routine) {
+                issues.push_error_nospan(ReferenceError::ModulesNoRoutine {
+                    routine: routine.clone(),
+                    module: module_name.clone(),
+                });
+            }
+        }
+
+        let pos = cursor.pos();
+        let data = cursor.into_data_segment();
+        code.adjust_len(overflow.unwrap_or(pos));
+
+        let inner = Lib { isae, code, data, libs };
+        Ok(Module { inner, vars, imports, exports, relocs, debug })
+    }
+
     fn link(
         &self,
         name: String,
@@ -62,13 +242,8 @@ impl Module {
         lib_man: &mut LibManager,
         issues: &mut Issues<issues::Linking>,
     ) -> Result<Product, LinkerError> {
-        let isae = self.inner.isae.clone();
-        let code = self.inner.code.clone();
-        let data = self.inner.data.clone();
-        let libs = self.inner.libs.clone();
-        let vars = self.vars.clone();
-
-        for (libid, routine, map) in self.imports.call_refs() {
+        let mut resolved = bmap! {};
+        for (libid, routine) in self.imports.routines() {
             let lib = match lib_man.get(libid) {
                 Some(lib) => lib,
                 None => {
@@ -77,37 +252,76 @@ impl Module {
                 }
             };
 
-            let pos = match lib.exports.get(routine) {
-                Some(pos) => *pos,
+            match lib.exports.get(routine) {
+                Some(pos) => {
+                    resolved.insert((libid, routine), *pos);
+                }
                 None => {
                     issues.push_error_nospan(ReferenceError::LibraryNoRoutine {
                         libid,
                         routine: routine.to_owned(),
                         module: name.clone(),
                     });
-                    continue;
                 }
-            };
+            }
+        }
 
-            let mut code = self.inner.code.clone();
-            let mut cursor = Cursor::with(&mut code, self.inner.data.clone(), &self.inner.libs);
-            for p in map {
-                cursor
-                    .edit(*p, |instr| match instr {
-                        Instr::ControlFlow(
-                            ControlFlowOp::Call(ref mut site) | ControlFlowOp::Exec(ref mut site),
-                        ) => {
-                            site.pos = pos;
-                            Ok(())
+        let mut code = self.inner.code.clone();
+        let mut cursor = Cursor::with(&mut code, self.inner.data.clone(), &self.inner.libs);
+        for (offset, reloc) in self.relocs.iter() {
+            match reloc {
+                Reloc::Import(site) => {
+                    let routine = match self.imports.get(*site) {
+                        Ok(routine) => routine,
+                        Err(err) => {
+                            issues.push_error_nospan(err.into());
+                            continue;
                         }
-                        _ => Err(InstrError::Changed("call", instr.clone())),
-                    })
-                    .map_err(|err| LinkerError::with(err, pos))?;
+                    };
+                    // Unresolved routines were already reported above
+                    let pos = match resolved.get(&(site.lib, routine)) {
+                        Some(pos) => *pos,
+                        None => continue,
+                    };
+                    cursor
+                        .edit(offset, |instr| match instr {
+                            Instr::ControlFlow(
+                                ControlFlowOp::Call(ref mut site)
+                                | ControlFlowOp::Exec(ref mut site),
+                            ) => {
+                                site.pos = pos;
+                                Ok(())
+                            }
+                            other => Err(InstrError::Changed("call", other.to_string())),
+                        })
+                        .map_err(|err| LinkerError::with(err, offset))?;
+                }
+                Reloc::Extern(routine) => {
+                    // Routines missed in all of the modules were reported during module merge
+                    let pos = match self.exports.get(routine) {
+                        Some(pos) => *pos,
+                        None => continue,
+                    };
+                    cursor
+                        .edit(offset, |instr| match instr {
+                            Instr::ControlFlow(ControlFlowOp::Routine(ref mut to)) => {
+                                *to = pos;
+                                Ok(())
+                            }
+                            other => Err(InstrError::Changed("routine", other.to_string())),
+                        })
+                        .map_err(|err| LinkerError::with(err, offset))?;
+                }
             }
         }
+        let data = cursor.into_data_segment();
+
+        let isae = self.inner.isae.clone();
+        let libs = self.inner.libs.clone();
+        let vars = self.vars.clone();
 
         let lib = Lib { isae, code, data, libs };
-        let inner = DyInner { name, org, inner: lib, vars };
+        let inner = DyInner { name, org, inner: lib, vars, debug: self.debug.clone() };
         Ok(match entry_point {
             EntryPoint::LibTable(exports) => Product::Lib(DyLib { inner, exports }),
             EntryPoint::BinMain(entry_point) => Product::Bin(DyBin { inner, entry_point }),
@@ -161,3 +375,29 @@ impl LibManager {
         None
     }
 }
+
+/// Lists library files found in the `lib_dir` directory, which can be passed to
+/// [`LibManager::with`]
+pub fn enumerate_libs(lib_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, BuildError> {
+    let lib_dir = lib_dir.as_ref();
+    let lib_dir_name = lib_dir.to_string_lossy().to_string();
+    if lib_dir.is_file() {
+        return Err(BuildError::LibDirIsFile(lib_dir_name));
+    }
+
+    let mut vec = vec![];
+    for entry in fs::read_dir(lib_dir)
+        .map_err(|err| BuildError::LibDirFail(lib_dir_name.clone(), err.into()))?
+    {
+        let path =
+            entry.map_err(|err| BuildError::LibDirFail(lib_dir_name.clone(), err.into()))?.path();
+        if path.is_dir() {
+            continue;
+        }
+        if path.extension().unwrap_or_default().to_string_lossy() != Product::LIB_EXTENSION {
+            continue;
+        }
+        vec.push(path);
+    }
+    Ok(vec)
+}
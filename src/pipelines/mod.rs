@@ -6,6 +6,15 @@
 // for Pandora Core AG
 
 pub mod analyzer;
+pub mod assembler;
 pub mod compiler;
+pub mod debugger;
+pub mod decompiler;
+pub mod formatter;
 pub mod linker;
+pub mod navigator;
 pub mod parser;
+pub mod plugins;
+pub mod runner;
+pub mod tester;
+pub mod verifier;
@@ -0,0 +1,123 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Lookup of symbols and statements by their position in the source code, used by editor tooling
+
+use pest::Span;
+
+use crate::ast::{Operand, Program, Routine, Statement};
+use crate::source::FileId;
+
+/// Symbol which may be defined and referenced in the program source code
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum Symbol {
+    /// Label defined within a routine
+    #[display("label `{label}` of `{routine}` routine")]
+    Label { label: String, routine: String },
+
+    #[display("routine `{0}`")]
+    Routine(String),
+
+    /// Constant or input variable, named with `$` prefix
+    #[display("constant `{0}`")]
+    Const(String),
+
+    #[display("library `{0}`")]
+    Lib(String),
+}
+
+impl<'i> Program<'i> {
+    /// Finds statement which code covers byte `offset` in the source `file`, returning it together
+    /// with the routine it belongs to
+    pub fn statement_at(
+        &'i self,
+        file: FileId,
+        offset: usize,
+    ) -> Option<(&'i Routine<'i>, &'i Statement<'i>)> {
+        self.routines.values().chain(self.tests.values()).find_map(|routine| {
+            routine
+                .statements
+                .iter()
+                .find(|statement| self.covers(file, &statement.span, offset))
+                .map(|statement| (routine, statement))
+        })
+    }
+
+    /// Finds symbol which is referenced or defined at byte `offset` in the source `file`,
+    /// returning it together with the span of the reference or definition
+    pub fn symbol_at(&'i self, file: FileId, offset: usize) -> Option<(Symbol, Span<'i>)> {
+        if let Some((routine, statement)) = self.statement_at(file, offset) {
+            if let Some((label, span)) = &statement.label {
+                if self.covers(file, span, offset) {
+                    let symbol =
+                        Symbol::Label { label: label.clone(), routine: routine.name.clone() };
+                    return Some((symbol, *span));
+                }
+            }
+            let operand = statement
+                .operands
+                .iter()
+                .find(|operand| self.covers(file, operand.as_span(), offset))?;
+            let symbol = match operand {
+                Operand::Goto(name, _) if statement.calls_routine() => {
+                    Symbol::Routine(name.clone())
+                }
+                Operand::Goto(label, _) => {
+                    Symbol::Label { label: label.clone(), routine: routine.name.clone() }
+                }
+                Operand::Call { lib, .. } => Symbol::Lib(lib.clone()),
+                Operand::Const(name, _) => Symbol::Const(name.clone()),
+                _ => return None,
+            };
+            return Some((symbol, *operand.as_span()));
+        }
+
+        let consts = self.consts.iter().map(|(name, c)| (name, &c.span));
+        let vars = self.input.iter().map(|(name, var)| (name, &var.span));
+        if let Some((name, span)) =
+            consts.chain(vars).find(|(_, span)| self.covers(file, span, offset))
+        {
+            return Some((Symbol::Const(name.clone()), *span));
+        }
+        if let Some((name, span)) =
+            self.libs.spans.iter().find(|(_, span)| self.covers(file, span, offset))
+        {
+            return Some((Symbol::Lib(name.clone()), *span));
+        }
+        self.routines
+            .values()
+            .find(|routine| self.covers(file, &routine.span, offset))
+            .map(|routine| (Symbol::Routine(routine.name.clone()), routine.span))
+    }
+
+    /// Returns span of the symbol definition, if the symbol is defined by the program
+    pub fn definition(&'i self, symbol: &Symbol) -> Option<Span<'i>> {
+        match symbol {
+            Symbol::Label { label, routine } => {
+                let routine = self.routines.get(routine)?;
+                let no = *routine.labels.get(label)?;
+                let (_, span) = routine.statements.get(no as usize)?.label.as_ref()?;
+                Some(*span)
+            }
+            Symbol::Routine(name) => self.routines.get(name).map(|routine| routine.span),
+            Symbol::Const(name) => self
+                .consts
+                .get(name)
+                .map(|c| c.span)
+                .or_else(|| self.input.get(name).map(|var| var.span)),
+            Symbol::Lib(name) => self.libs.spans.get(name).copied(),
+        }
+    }
+
+    /// Checks whether the span lies within the text of the source `file` and contains `offset`
+    fn covers(&self, file: FileId, span: &Span, offset: usize) -> bool {
+        let text = &self.sources.file(file).text;
+        let start = text.as_ptr() as usize;
+        let ptr = span.as_str().as_ptr() as usize;
+        (start..=start + text.len()).contains(&ptr) && (span.start()..=span.end()).contains(&offset)
+    }
+}
@@ -0,0 +1,1250 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Pluggable instruction table used by the compiler to encode statements into the instructions of
+//! AluVM core instruction set and its extensions
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use aluvm::data::Step;
+use aluvm::isa::{
+    ArithmeticOp, BitwiseOp, BytesOp, CmpOp, ControlFlowOp, DigestOp, Instr, InstructionSet,
+    MoveOp, PutOp, Secp256k1Op,
+};
+use aluvm::reg::{Reg32, RegA, RegAF, RegAFR, RegAR, RegAll, RegR, RegS};
+use amplify::num::u1024;
+
+use crate::ast::{FlagSet, Literal, Operand, Operator, Program, Statement};
+use crate::isa::{AluReOp, ExtOp, RgbOp};
+use crate::issues::{self, Issues, SemanticError};
+use crate::module::CallTable;
+use crate::CompilerError;
+
+/// Kind of the operand taken by a mnemonic
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum OperandKind {
+    /// Register
+    Reg,
+    /// Literal, constant or constant expression
+    Value,
+    /// Register or a value, like a step in `add a8[1],1`
+    RegOrValue,
+    /// Input variable
+    Var,
+    /// Label inside the routine
+    Label,
+    /// Name of a routine from the same program
+    Routine,
+    /// Name of a routine from the same program or a routine of an external library
+    Call,
+    /// Routine of an external library (`lib->routine`)
+    LibCall,
+}
+
+impl OperandKind {
+    /// Checks whether the operand is of this kind
+    pub fn matches(self, operand: &Operand) -> bool {
+        matches!(
+            (self, operand),
+            (OperandKind::Reg | OperandKind::RegOrValue, Operand::Reg { .. })
+                | (
+                    OperandKind::Value | OperandKind::RegOrValue,
+                    Operand::Lit(..) | Operand::Const(..) | Operand::Expr(..)
+                )
+                | (OperandKind::Var, Operand::Const(..))
+                | (
+                    OperandKind::Label | OperandKind::Routine | OperandKind::Call,
+                    Operand::Goto(..)
+                )
+                | (OperandKind::Call | OperandKind::LibCall, Operand::Call { .. })
+        )
+    }
+
+    /// Describes the operand kind for the diagnostic messages
+    pub fn description(self) -> &'static str {
+        match self {
+            OperandKind::Reg => "register",
+            OperandKind::Value => "constant or literal",
+            OperandKind::RegOrValue => "register, constant or literal",
+            OperandKind::Var => "input variable",
+            OperandKind::Label => "label",
+            OperandKind::Routine => "routine name",
+            OperandKind::Call => "routine name or library call",
+            OperandKind::LibCall => "library call",
+        }
+    }
+}
+
+/// Description of a mnemonic provided by an ISA plugin
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Mnemonic {
+    /// Operator which is compiled by the plugin
+    pub operator: Operator,
+    /// Characters which may be used as the operator flags
+    pub flags: &'static str,
+    /// Kinds of the operands taken by the operator
+    pub operands: &'static [OperandKind],
+    /// Number of leading operands which must be present; the rest of them are optional
+    pub required: u8,
+}
+
+impl Mnemonic {
+    /// Constructs mnemonic description with all of the operands being required
+    pub const fn new(
+        operator: Operator,
+        flags: &'static str,
+        operands: &'static [OperandKind],
+    ) -> Mnemonic {
+        Mnemonic { operator, flags, operands, required: operands.len() as u8 }
+    }
+
+    /// Makes operands following the first `count` ones optional
+    pub const fn required(self, count: u8) -> Mnemonic { Mnemonic { required: count, ..self } }
+
+    /// Maximal number of operands taken by the operator
+    pub const fn arity(&self) -> u8 { self.operands.len() as u8 }
+
+    /// Checks flags and operands of the statement against the mnemonic description, reporting
+    /// all mismatches. Returns whether the statement matches the description.
+    pub fn validate<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        issues: &mut Issues<'i, issues::Compile>,
+    ) -> bool {
+        let operator = statement.operator.0;
+        let mut valid = true;
+
+        let flags = match statement.flags {
+            FlagSet::None => vec![],
+            FlagSet::One(flag, span) => vec![(flag, span)],
+            FlagSet::Double(flag1, flag2, span) => vec![(flag1, span), (flag2, span)],
+        };
+        for (flag, span) in flags {
+            if !self.flags.contains(flag) {
+                issues.push_error(SemanticError::OperatorWrongFlag(operator, flag), &span);
+                valid = false;
+            }
+        }
+
+        for (pos, kind) in self.operands.iter().enumerate() {
+            let expected = kind.description();
+            match statement.operands.get(pos) {
+                Some(operand) if !kind.matches(operand) => {
+                    issues.push_error(
+                        SemanticError::OperandWrongType { operator, pos: pos as u8 + 1, expected },
+                        operand.as_span(),
+                    );
+                    valid = false;
+                }
+                None if pos < self.required as usize => {
+                    issues.push_error(
+                        SemanticError::OperandMissed { operator, pos: pos as u8 + 1, expected },
+                        &statement.operator.1,
+                    );
+                    valid = false;
+                }
+                _ => {}
+            }
+        }
+        if let Some(operand) = statement.operands.get(self.operands.len()) {
+            issues.push_error(
+                SemanticError::OperandExcess { operator, expected: self.arity() },
+                operand.as_span(),
+            );
+            valid = false;
+        }
+
+        valid
+    }
+}
+
+const NONE: &[OperandKind] = &[];
+const REG: &[OperandKind] = &[OperandKind::Reg];
+const REG2: &[OperandKind] = &[OperandKind::Reg, OperandKind::Reg];
+const REG3: &[OperandKind] = &[OperandKind::Reg, OperandKind::Reg, OperandKind::Reg];
+const REG4: &[OperandKind] =
+    &[OperandKind::Reg, OperandKind::Reg, OperandKind::Reg, OperandKind::Reg];
+const REG5: &[OperandKind] =
+    &[OperandKind::Reg, OperandKind::Reg, OperandKind::Reg, OperandKind::Reg, OperandKind::Reg];
+const REG_VALUE: &[OperandKind] = &[OperandKind::Reg, OperandKind::Value];
+const VALUE_REG: &[OperandKind] = &[OperandKind::Value, OperandKind::Reg];
+const VALUE_REG3: &[OperandKind] =
+    &[OperandKind::Value, OperandKind::Reg, OperandKind::Reg, OperandKind::Reg];
+const REG_STEP: &[OperandKind] = &[OperandKind::Reg, OperandKind::RegOrValue];
+const REG_VAR: &[OperandKind] = &[OperandKind::Reg, OperandKind::Var];
+const LABEL: &[OperandKind] = &[OperandKind::Label];
+const ROUTINE: &[OperandKind] = &[OperandKind::Routine];
+const CALL: &[OperandKind] = &[OperandKind::Call];
+const LIB_CALL: &[OperandKind] = &[OperandKind::LibCall];
+
+/// Mnemonics of the core AluVM instruction set
+pub const ALU_MNEMONICS: &[Mnemonic] = &[
+    Mnemonic::new(Operator::succ, "", NONE),
+    Mnemonic::new(Operator::fail, "", NONE),
+    Mnemonic::new(Operator::ret, "", NONE),
+    Mnemonic::new(Operator::jif, "", LABEL),
+    Mnemonic::new(Operator::jmp, "", LABEL),
+    Mnemonic::new(Operator::routine, "", ROUTINE),
+    Mnemonic::new(Operator::exec, "", LIB_CALL),
+    Mnemonic::new(Operator::call, "", CALL),
+    Mnemonic::new(Operator::clr, "", REG),
+    Mnemonic::new(Operator::put, "", REG_VALUE),
+    Mnemonic::new(Operator::putif, "", VALUE_REG),
+    Mnemonic::new(Operator::dup, "", REG2),
+    Mnemonic::new(Operator::mov, "", REG2),
+    Mnemonic::new(Operator::cnv, "", REG2),
+    Mnemonic::new(Operator::cpy, "", REG2),
+    Mnemonic::new(Operator::spy, "", REG2),
+    Mnemonic::new(Operator::swp, "", REG2),
+    Mnemonic::new(Operator::eq, "enr", REG2),
+    Mnemonic::new(Operator::gt, "usre", REG2),
+    Mnemonic::new(Operator::lt, "usre", REG2),
+    Mnemonic::new(Operator::ifn, "", REG),
+    Mnemonic::new(Operator::ifz, "", REG),
+    Mnemonic::new(Operator::stinv, "", NONE),
+    Mnemonic::new(Operator::st, "sano", REG),
+    Mnemonic::new(Operator::neg, "", REG),
+    Mnemonic::new(Operator::inc, "", REG),
+    Mnemonic::new(Operator::dec, "", REG),
+    Mnemonic::new(Operator::add, "uscwnzf", REG_STEP),
+    Mnemonic::new(Operator::sub, "uscwnzf", REG_STEP),
+    Mnemonic::new(Operator::mul, "uscwnzf", REG2),
+    Mnemonic::new(Operator::div, "uscwnzf", REG2),
+    Mnemonic::new(Operator::rem, "", REG2),
+    Mnemonic::new(Operator::abs, "", REG),
+    Mnemonic::new(Operator::not, "", REG),
+    Mnemonic::new(Operator::and, "", REG3),
+    Mnemonic::new(Operator::or, "", REG3),
+    Mnemonic::new(Operator::xor, "", REG3),
+    Mnemonic::new(Operator::shl, "", REG2),
+    Mnemonic::new(Operator::shr, "us", REG2),
+    Mnemonic::new(Operator::scl, "", REG2),
+    Mnemonic::new(Operator::scr, "", REG2),
+    // The second operand is used only when reversing bytes of `s` registers
+    Mnemonic::new(Operator::rev, "", REG2).required(1),
+    Mnemonic::new(Operator::fill, "ef", REG4),
+    Mnemonic::new(Operator::len, "", REG2),
+    Mnemonic::new(Operator::cnt, "", REG3),
+    Mnemonic::new(Operator::con, "", REG5),
+    Mnemonic::new(Operator::find, "", REG3),
+    Mnemonic::new(Operator::extr, "", REG3),
+    Mnemonic::new(Operator::inj, "", REG3),
+    Mnemonic::new(Operator::join, "", REG3),
+    Mnemonic::new(Operator::splt, "nczf", REG4),
+    Mnemonic::new(Operator::ins, "czf", REG3),
+    Mnemonic::new(Operator::nop, "", NONE),
+];
+
+/// Mnemonics of the bitcoin protocol digest instruction set extension
+pub const BPDIGEST_MNEMONICS: &[Mnemonic] =
+    &[Mnemonic::new(Operator::ripemd, "", REG2), Mnemonic::new(Operator::sha2, "", REG2)];
+
+/// Mnemonics of the Secp256k1 instruction set extension
+pub const SECP256K1_MNEMONICS: &[Mnemonic] = &[
+    Mnemonic::new(Operator::secpgen, "", REG2),
+    Mnemonic::new(Operator::secpneg, "", REG2),
+    Mnemonic::new(Operator::secpadd, "", REG2),
+    Mnemonic::new(Operator::secpmul, "", REG3),
+];
+
+/// Mnemonics of the runtime environment instruction set extension
+pub const ALURE_MNEMONICS: &[Mnemonic] = &[Mnemonic::new(Operator::read, "", REG_VAR)];
+
+/// Mnemonics of the RGB instruction set extension
+pub const RGB_MNEMONICS: &[Mnemonic] = &[
+    Mnemonic::new(Operator::scn, "io", VALUE_REG),
+    Mnemonic::new(Operator::pld, "io", VALUE_REG3),
+];
+
+/// Compiler state available to ISA plugins during statement encoding
+pub struct CompileCtx<'i, 'c> {
+    /// Program which statements are compiled
+    pub program: &'i Program<'i>,
+    /// Table of the external routines called by the program
+    pub call_table: &'c mut CallTable,
+    /// Issues found during the compilation
+    pub issues: &'c mut Issues<'i, issues::Compile>,
+}
+
+/// Plugin providing the compiler with mnemonics of a specific instruction set
+pub trait IsaPlugin<Ext>
+where
+    Ext: InstructionSet,
+{
+    /// Identifier of the instruction set, as used in `.ISAE` segment
+    fn isa_id(&self) -> &'static str;
+
+    /// Mnemonics which are compiled by the plugin
+    fn mnemonics(&self) -> &'static [Mnemonic];
+
+    /// Encodes statement, which operator is one of the plugin [`IsaPlugin::mnemonics`], into an
+    /// instruction
+    fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError>;
+}
+
+/// Instruction table mapping mnemonics to the ISA plugins compiling them
+pub struct IsaTable<Ext>
+where
+    Ext: InstructionSet,
+{
+    plugins: Vec<Box<dyn IsaPlugin<Ext>>>,
+    mnemonics: BTreeMap<Operator, (usize, Mnemonic)>,
+}
+
+impl<Ext> IsaTable<Ext>
+where
+    Ext: InstructionSet,
+{
+    /// Constructs empty instruction table
+    pub fn empty() -> Self { IsaTable { plugins: vec![], mnemonics: bmap! {} } }
+
+    /// Constructs instruction table with AluVM core instructions and instruction set extensions
+    /// which do not require extension codes
+    pub fn core() -> Self {
+        Self::empty().with(AluPlugin).with(BpDigestPlugin).with(Secp256k1Plugin)
+    }
+
+    /// Registers plugin in the table. Mnemonics of the plugin override mnemonics with the same
+    /// name provided by the plugins registered before
+    pub fn register(&mut self, plugin: impl IsaPlugin<Ext> + 'static) {
+        let index = self.plugins.len();
+        for mnemonic in plugin.mnemonics() {
+            self.mnemonics.insert(mnemonic.operator, (index, *mnemonic));
+        }
+        self.plugins.push(Box::new(plugin));
+    }
+
+    /// Registers plugin in the table and returns the table back
+    pub fn with(mut self, plugin: impl IsaPlugin<Ext> + 'static) -> Self {
+        self.register(plugin);
+        self
+    }
+
+    /// Returns description of the mnemonic for the given operator, if it is supported
+    pub fn mnemonic(&self, operator: Operator) -> Option<Mnemonic> {
+        self.mnemonics.get(&operator).map(|(_, mnemonic)| *mnemonic)
+    }
+
+    /// Returns identifier of the instruction set providing the operator, if it is supported
+    pub fn isa_id(&self, operator: Operator) -> Option<&'static str> {
+        self.mnemonics.get(&operator).map(|(index, _)| self.plugins[*index].isa_id())
+    }
+
+    /// Returns mapping of all supported mnemonic names to the operators
+    pub fn operators(&self) -> BTreeMap<String, Operator> {
+        self.mnemonics.keys().map(|operator| (operator.to_string(), *operator)).collect()
+    }
+
+    /// Returns identifiers of instruction sets provided by the registered plugins
+    pub fn isa_ids(&self) -> BTreeSet<&'static str> {
+        self.plugins.iter().map(|plugin| plugin.isa_id()).collect()
+    }
+
+    /// Encodes statement into an instruction using the plugin registered for its operator. All
+    /// instructions produced by a plugin are considered to belong to the plugin instruction set,
+    /// which must be declared in the program `.ISAE` segment. The plugin is not invoked for
+    /// statements which flags or operands do not match the mnemonic description.
+    pub fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError> {
+        let operator = statement.operator.0;
+        let (index, mnemonic) = match self.mnemonics.get(&operator) {
+            Some(entry) => *entry,
+            None => {
+                ctx.issues.push_error(
+                    SemanticError::MnemonicUnsupported(operator),
+                    &statement.operator.1,
+                );
+                return Ok(Instr::Nop);
+            }
+        };
+        let isae = self.plugins[index].isa_id();
+        if !ctx.program.isae.contains_key(isae) {
+            ctx.issues
+                .push_error(SemanticError::IsaeNotDeclared { operator, isae }, &statement.span);
+        }
+        if !mnemonic.validate(statement, ctx.issues) {
+            return Ok(Instr::Nop);
+        }
+        self.plugins[index].compile(statement, ctx)
+    }
+}
+
+impl Default for IsaTable<ExtOp> {
+    fn default() -> Self { Self::core().with(AluRePlugin).with(RgbPlugin) }
+}
+
+/// Plugin for AluVM core instruction set
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AluPlugin;
+
+impl<Ext> IsaPlugin<Ext> for AluPlugin
+where
+    Ext: InstructionSet,
+{
+    fn isa_id(&self) -> &'static str { "ALU" }
+
+    fn mnemonics(&self) -> &'static [Mnemonic] { ALU_MNEMONICS }
+
+    fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError> {
+        statement.compile_alu(ctx)
+    }
+}
+
+/// Plugin for bitcoin protocol digest instruction set extension
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct BpDigestPlugin;
+
+impl<Ext> IsaPlugin<Ext> for BpDigestPlugin
+where
+    Ext: InstructionSet,
+{
+    fn isa_id(&self) -> &'static str { "BPDIGEST" }
+
+    fn mnemonics(&self) -> &'static [Mnemonic] { BPDIGEST_MNEMONICS }
+
+    fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError> {
+        statement.compile_digest(ctx)
+    }
+}
+
+/// Plugin for Secp256k1 elliptic curve instruction set extension
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Secp256k1Plugin;
+
+impl<Ext> IsaPlugin<Ext> for Secp256k1Plugin
+where
+    Ext: InstructionSet,
+{
+    fn isa_id(&self) -> &'static str { "SECP256" }
+
+    fn mnemonics(&self) -> &'static [Mnemonic] { SECP256K1_MNEMONICS }
+
+    fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError> {
+        statement.compile_secp(ctx)
+    }
+}
+
+/// Plugin for runtime environment instruction set extension reading input variables
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AluRePlugin;
+
+impl<Ext> IsaPlugin<Ext> for AluRePlugin
+where
+    Ext: InstructionSet + From<AluReOp>,
+{
+    fn isa_id(&self) -> &'static str { "ALURE" }
+
+    fn mnemonics(&self) -> &'static [Mnemonic] { ALURE_MNEMONICS }
+
+    fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError> {
+        statement.compile_alure(ctx)
+    }
+}
+
+/// Plugin for RGB instruction set extension accessing contract state
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct RgbPlugin;
+
+impl<Ext> IsaPlugin<Ext> for RgbPlugin
+where
+    Ext: InstructionSet + From<RgbOp>,
+{
+    fn isa_id(&self) -> &'static str { "RGB" }
+
+    fn mnemonics(&self) -> &'static [Mnemonic] { RGB_MNEMONICS }
+
+    fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError> {
+        statement.compile_rgb(ctx)
+    }
+}
+
+impl<'i> Statement<'i> {
+    fn compile_alu<Ext>(&'i self, ctx: &mut CompileCtx<'i, '_>) -> Result<Instr<Ext>, CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        let program = ctx.program;
+        let call_table = &mut *ctx.call_table;
+        let issues = &mut *ctx.issues;
+        macro_rules! reg {
+            ($no:expr) => {
+                self.reg($no, issues)
+            };
+        }
+        macro_rules! idx {
+            ($no:expr) => {
+                self.idx($no, issues)
+            };
+        }
+        macro_rules! num {
+            ($no:expr, $reg:ident) => {
+                Box::new(self.num($no, $reg, &program.consts, issues)?)
+            };
+        }
+        macro_rules! str {
+            ($no:expr) => {
+                Box::new(self.str($no, &program.consts, issues)?)
+            };
+        }
+        macro_rules! lib {
+            ($no:expr) => {
+                self.lib($no, program, call_table, issues)
+            };
+        }
+        macro_rules! flags {
+            () => {
+                self.flags(issues)
+            };
+        }
+        Ok(match self.operator.0 {
+            Operator::succ => Instr::ControlFlow(ControlFlowOp::Succ),
+            Operator::fail => Instr::ControlFlow(ControlFlowOp::Fail),
+            Operator::ret => Instr::ControlFlow(ControlFlowOp::Ret),
+            Operator::jif => Instr::ControlFlow(ControlFlowOp::Jif(0)),
+            Operator::jmp => Instr::ControlFlow(ControlFlowOp::Jmp(0)),
+            Operator::routine => Instr::ControlFlow(ControlFlowOp::Routine(0)),
+            Operator::exec => Instr::ControlFlow(ControlFlowOp::Exec(lib! {0})),
+            Operator::call if self.calls_routine() => Instr::ControlFlow(ControlFlowOp::Routine(0)),
+            Operator::call => Instr::ControlFlow(ControlFlowOp::Call(lib! {0})),
+
+            // *** Put operations
+            Operator::clr => match reg! {0} {
+                RegAFR::A(a) => Instr::Put(PutOp::ClrA(a, idx! {0})),
+                RegAFR::F(f) => Instr::Put(PutOp::ClrF(f, idx! {0})),
+                RegAFR::R(r) => Instr::Put(PutOp::ClrR(r, idx! {0})),
+            },
+            Operator::put => match reg! {0} {
+                RegAll::A(a) => Instr::Put(PutOp::PutA(a, idx! {0}, num! {1, a})),
+                RegAll::F(f) => Instr::Put(PutOp::PutF(f, idx! {0}, num! {1, f})),
+                RegAll::R(r) => Instr::Put(PutOp::PutR(r, idx! {0}, num! {1, r})),
+                RegAll::S => Instr::Bytes(BytesOp::Put(idx! {0}, str! {1}, false)),
+            },
+            Operator::putif => match reg! {1} {
+                RegAR::A(a) => Instr::Put(PutOp::PutIfA(a, idx! {1}, num! {0, a})),
+                RegAR::R(r) => Instr::Put(PutOp::PutIfR(r, idx! {1}, num! {0, r})),
+            },
+
+            // *** Move operations
+            Operator::dup => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAFR::A(a) => Instr::Move(MoveOp::DupA(a, idx! {0}, idx! {1})),
+                    RegAFR::F(f) => Instr::Move(MoveOp::DupF(f, idx! {0}, idx! {1})),
+                    RegAFR::R(r) => Instr::Move(MoveOp::DupR(r, idx! {0}, idx! {1})),
+                }
+            }
+            Operator::mov => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAll::A(a) => Instr::Move(MoveOp::MovA(a, idx! {0}, idx! {1})),
+                    RegAll::F(f) => Instr::Move(MoveOp::MovF(f, idx! {0}, idx! {1})),
+                    RegAll::R(r) => Instr::Move(MoveOp::MovR(r, idx! {0}, idx! {1})),
+                    RegAll::S => Instr::Bytes(BytesOp::Mov(idx! {0}, idx! {1})),
+                }
+            }
+            Operator::cnv => match (reg! {0}, reg! {1}) {
+                (RegAF::A(a1), RegAF::A(a2)) => {
+                    Instr::Move(MoveOp::CnvA(a1, idx! {0}, a2, idx! {1}))
+                }
+                (RegAF::F(f1), RegAF::F(f2)) => {
+                    Instr::Move(MoveOp::CnvF(f1, idx! {0}, f2, idx! {1}))
+                }
+                (RegAF::A(a), RegAF::F(f)) => Instr::Move(MoveOp::CnvAF(a, idx! {0}, f, idx! {1})),
+                (RegAF::F(f), RegAF::A(a)) => Instr::Move(MoveOp::CnvFA(f, idx! {0}, a, idx! {1})),
+            },
+            Operator::cpy => match reg! {0} {
+                RegAR::A(a) => Instr::Move(MoveOp::CpyA(a, idx! {0}, reg! {1}, idx! {1})),
+                RegAR::R(r) => Instr::Move(MoveOp::CpyR(r, idx! {0}, reg! {1}, idx! {1})),
+            },
+            Operator::spy => Instr::Move(MoveOp::SpyAR(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
+            Operator::swp => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAll::A(a) => Instr::Move(MoveOp::SwpA(a, idx! {0}, idx! {1})),
+                    RegAll::F(f) => Instr::Move(MoveOp::SwpF(f, idx! {0}, idx! {1})),
+                    RegAll::S => Instr::Bytes(BytesOp::Swp(idx! {0}, idx! {1})),
+                    _ => {
+                        issues.push_error(
+                            SemanticError::OperandWrongReg {
+                                operator: Operator::mov,
+                                pos: 0,
+                                expected: "register S",
+                            },
+                            self.operands[1].as_span(),
+                        );
+                        Instr::Nop
+                    }
+                }
+            }
+
+            // *** Comparison operations
+            Operator::eq => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAll::A(a) => Instr::Cmp(CmpOp::EqA(flags!(), a, idx! {0}, idx! {1})),
+                    RegAll::F(f) => Instr::Cmp(CmpOp::EqF(flags!(), f, idx! {0}, idx! {1})),
+                    RegAll::R(r) => Instr::Cmp(CmpOp::EqR(flags!(), r, idx! {0}, idx! {1})),
+                    RegAll::S => {
+                        let _: RegS = reg! {1};
+                        Instr::Bytes(BytesOp::Eq(idx! {0}, idx! {1}))
+                    }
+                }
+            }
+            Operator::gt => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAFR::A(a) => Instr::Cmp(CmpOp::GtA(flags!(), a, idx! {0}, idx! {1})),
+                    RegAFR::F(f) => Instr::Cmp(CmpOp::GtF(flags!(), f, idx! {0}, idx! {1})),
+                    RegAFR::R(r) => Instr::Cmp(CmpOp::GtR(r, idx! {0}, idx! {1})),
+                }
+            }
+            Operator::lt => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAFR::A(a) => Instr::Cmp(CmpOp::LtA(flags!(), a, idx! {0}, idx! {1})),
+                    RegAFR::F(f) => Instr::Cmp(CmpOp::LtF(flags!(), f, idx! {0}, idx! {1})),
+                    RegAFR::R(r) => Instr::Cmp(CmpOp::LtR(r, idx! {0}, idx! {1})),
+                }
+            }
+            Operator::ifn => match reg! {0} {
+                RegAR::A(a) => Instr::Cmp(CmpOp::IfNA(a, idx! {0})),
+                RegAR::R(r) => Instr::Cmp(CmpOp::IfNR(r, idx! {0})),
+            },
+            Operator::ifz => match reg! {0} {
+                RegAR::A(a) => Instr::Cmp(CmpOp::IfZA(a, idx! {0})),
+                RegAR::R(r) => Instr::Cmp(CmpOp::IfZR(r, idx! {0})),
+            },
+            Operator::stinv => Instr::Cmp(CmpOp::StInv),
+            Operator::st => Instr::Cmp(CmpOp::St(flags!(), reg! {0}, idx! {0})),
+
+            // *** Arithmetic
+            Operator::neg => Instr::Arithmetic(ArithmeticOp::Neg(reg! {0}, idx! {0})),
+            Operator::inc => {
+                Instr::Arithmetic(ArithmeticOp::Stp(reg! {0}, idx! {0}, Step::with(1)))
+            }
+            Operator::dec => {
+                Instr::Arithmetic(ArithmeticOp::Stp(reg! {0}, idx! {0}, Step::with(-1)))
+            }
+            Operator::add => {
+                if let Some(Operand::Lit(Literal::Int { val: mut step, neg, .. }, span)) =
+                    self.operands.get(1)
+                {
+                    if step > u1024::from(i8::MAX as u8) {
+                        step = u1024::from(1u64);
+                        issues.push_error(SemanticError::StepTooLarge(self.operator.0), span);
+                    }
+                    let step = step.low_u32() as i8;
+                    Instr::Arithmetic(ArithmeticOp::Stp(
+                        reg! {0},
+                        idx! {0},
+                        Step::with(if *neg { -step } else { step }),
+                    ))
+                } else {
+                    let reg = reg! {0};
+                    if reg != reg! {1} {
+                        issues.push_error(
+                            SemanticError::OperandRegMutBeEqual(self.operator.0),
+                            self.operands[1].as_span(),
+                        );
+                    }
+                    match reg {
+                        RegAF::A(a) => {
+                            Instr::Arithmetic(ArithmeticOp::AddA(flags!(), a, idx! {0}, idx! {1}))
+                        }
+                        RegAF::F(f) => {
+                            Instr::Arithmetic(ArithmeticOp::AddF(flags!(), f, idx! {0}, idx! {1}))
+                        }
+                    }
+                }
+            }
+            Operator::sub => {
+                if let Some(Operand::Lit(Literal::Int { val: mut step, neg, .. }, span)) =
+                    self.operands.get(1)
+                {
+                    if step > u1024::from(i8::MAX as u8) {
+                        step = u1024::from(1u64);
+                        issues.push_error(SemanticError::StepTooLarge(self.operator.0), span);
+                    }
+                    let step = step.low_u32() as i8;
+                    Instr::Arithmetic(ArithmeticOp::Stp(
+                        reg! {0},
+                        idx! {0},
+                        Step::with(if *neg { step } else { -step }),
+                    ))
+                } else {
+                    let reg = reg! {0};
+                    if reg != reg! {1} {
+                        issues.push_error(
+                            SemanticError::OperandRegMutBeEqual(self.operator.0),
+                            self.operands[1].as_span(),
+                        );
+                    }
+                    match reg {
+                        RegAF::A(a) => {
+                            Instr::Arithmetic(ArithmeticOp::SubA(flags!(), a, idx! {0}, idx! {1}))
+                        }
+                        RegAF::F(f) => {
+                            Instr::Arithmetic(ArithmeticOp::SubF(flags!(), f, idx! {0}, idx! {1}))
+                        }
+                    }
+                }
+            }
+            Operator::mul => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAF::A(a) => {
+                        Instr::Arithmetic(ArithmeticOp::MulA(flags!(), a, idx! {0}, idx! {1}))
+                    }
+                    RegAF::F(f) => {
+                        Instr::Arithmetic(ArithmeticOp::MulF(flags!(), f, idx! {0}, idx! {1}))
+                    }
+                }
+            }
+            Operator::div => {
+                let reg = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                match reg {
+                    RegAF::A(a) => {
+                        Instr::Arithmetic(ArithmeticOp::DivA(flags!(), a, idx! {0}, idx! {1}))
+                    }
+                    RegAF::F(f) => {
+                        Instr::Arithmetic(ArithmeticOp::DivF(flags!(), f, idx! {0}, idx! {1}))
+                    }
+                }
+            }
+            Operator::rem => {
+                Instr::Arithmetic(ArithmeticOp::Rem(reg! {0}, idx! {0}, reg! {1}, idx! {1}))
+            }
+            Operator::abs => Instr::Arithmetic(ArithmeticOp::Abs(reg! {0}, idx! {0})),
+
+            // *** Bitwise
+            Operator::not => Instr::Bitwise(BitwiseOp::Not(reg! {0}, idx! {0})),
+            Operator::and => {
+                let reg: RegAR = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                if reg != reg! {2} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[2].as_span(),
+                    );
+                }
+                Instr::Bitwise(BitwiseOp::And(reg, idx! {0}, idx! {1}, idx! {2}))
+            }
+            Operator::or => {
+                let reg: RegAR = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                if reg != reg! {2} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[2].as_span(),
+                    );
+                }
+                Instr::Bitwise(BitwiseOp::Or(reg, idx! {0}, idx! {1}, idx! {2}))
+            }
+            Operator::xor => {
+                let reg: RegAR = reg! {0};
+                if reg != reg! {1} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[1].as_span(),
+                    );
+                }
+                if reg != reg! {2} {
+                    issues.push_error(
+                        SemanticError::OperandRegMutBeEqual(self.operator.0),
+                        self.operands[2].as_span(),
+                    );
+                }
+                Instr::Bitwise(BitwiseOp::Xor(reg, idx! {0}, idx! {1}, idx! {2}))
+            }
+            Operator::shl => Instr::Bitwise(BitwiseOp::Shl(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
+            Operator::shr => match reg! {1} {
+                RegAR::A(a) => {
+                    Instr::Bitwise(BitwiseOp::ShrA(flags!(), reg! {0}, idx! {0}, a, idx! {1}))
+                }
+                RegAR::R(r) => Instr::Bitwise(BitwiseOp::ShrR(reg! {0}, idx! {0}, r, idx! {1})),
+            },
+            Operator::scl => Instr::Bitwise(BitwiseOp::Scl(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
+            Operator::scr => Instr::Bitwise(BitwiseOp::Scr(reg! {0}, idx! {0}, reg! {1}, idx! {1})),
+            Operator::rev => match reg! {0} {
+                RegAll::A(a) => Instr::Bitwise(BitwiseOp::RevA(a, idx! {0})),
+                RegAll::R(r) => Instr::Bitwise(BitwiseOp::RevR(r, idx! {0})),
+                RegAll::S => {
+                    let _: RegS = reg! {1};
+                    Instr::Bytes(BytesOp::Rev(idx! {0}, idx! {1}))
+                }
+                _ => {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 0,
+                            expected: "register S",
+                        },
+                        self.operands[0].as_span(),
+                    );
+                    Instr::Nop
+                }
+            },
+
+            // *** String operations
+            Operator::fill => {
+                let _: RegS = reg! {0};
+                let reg1: RegA = reg! {1};
+                let reg2: RegA = reg! {2};
+                let reg3: RegA = reg! {3};
+                if reg1 != RegA::A16 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 1,
+                            expected: "a16 register",
+                        },
+                        self.operands[1].as_span(),
+                    );
+                }
+                if reg2 != RegA::A16 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 2,
+                            expected: "a16 register",
+                        },
+                        self.operands[2].as_span(),
+                    );
+                }
+                if reg3 != RegA::A8 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 3,
+                            expected: "a8 register",
+                        },
+                        self.operands[3].as_span(),
+                    );
+                }
+                Instr::Bytes(BytesOp::Fill(idx! {0}, idx! {1}, idx! {2}, idx! {3}, flags!()))
+            }
+            Operator::len => {
+                let _: RegS = reg! {0};
+                Instr::Bytes(BytesOp::Len(idx! {0}, reg! {1}, idx! {1}))
+            }
+            Operator::cnt => {
+                let _: RegS = reg! {0};
+                let byte_reg: RegA = reg! {1};
+                let dst_reg: RegA = reg! {2};
+                if byte_reg != RegA::A8 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 2,
+                            expected: "a8 register",
+                        },
+                        self.operands[2].as_span(),
+                    );
+                }
+                if dst_reg != RegA::A16 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 0,
+                            expected: "a16 register",
+                        },
+                        self.operands[0].as_span(),
+                    );
+                }
+                Instr::Bytes(BytesOp::Cnt(idx! {0}, idx! {1}, idx! {2}))
+            }
+            Operator::con => {
+                let _: RegS = reg! {0};
+                let _: RegS = reg! {1};
+                for n in 2..5 {
+                    let reg: RegA = reg! {n};
+                    if reg != RegA::A16 {
+                        issues.push_error(
+                            SemanticError::OperandWrongReg {
+                                operator: self.operator.0,
+                                pos: n,
+                                expected: "a16 register",
+                            },
+                            self.operands[n as usize].as_span(),
+                        );
+                    }
+                }
+                Instr::Bytes(BytesOp::Con(idx! {0}, idx! {1}, idx! {2}, idx! {3}, idx! {4}))
+            }
+            Operator::find => {
+                let _: RegS = reg! {0};
+                let _: RegS = reg! {1};
+                let reg: RegA = reg! {2};
+                let idx: Reg32 = idx! {2};
+                if reg != RegA::A16 || idx != Reg32::Reg0 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 2,
+                            expected: "a16[0] register",
+                        },
+                        self.operands[2].as_span(),
+                    );
+                }
+                Instr::Bytes(BytesOp::Find(idx! {0}, idx! {1}))
+            }
+            Operator::extr => {
+                let _: RegS = reg! {0};
+                Instr::Bytes(BytesOp::Extr(idx! {0}, reg! {1}, idx! {1}, idx! {2}))
+            }
+            Operator::inj => {
+                let _: RegS = reg! {0};
+                let reg: RegA = reg! {2};
+                if reg != RegA::A16 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 2,
+                            expected: "a16 register",
+                        },
+                        self.operands[2].as_span(),
+                    );
+                }
+                Instr::Bytes(BytesOp::Inj(idx! {0}, reg! {1}, idx! {1}, idx! {2}))
+            }
+            Operator::join => {
+                let _: RegS = reg! {0};
+                let _: RegS = reg! {1};
+                let _: RegS = reg! {2};
+                Instr::Bytes(BytesOp::Join(idx! {0}, idx! {1}, idx! {2}))
+            }
+            Operator::splt => {
+                let _: RegS = reg! {0};
+                let _: RegS = reg! {2};
+                let _: RegS = reg! {3};
+                let reg: RegA = reg! {1};
+                if reg != RegA::A16 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 1,
+                            expected: "a16[0] register",
+                        },
+                        self.operands[1].as_span(),
+                    );
+                }
+                Instr::Bytes(BytesOp::Splt(flags!(), idx! {1}, idx! {0}, idx! {2}, idx! {3}))
+            }
+            Operator::ins => {
+                let _: RegS = reg! {0};
+                let _: RegS = reg! {1};
+                let reg: RegA = reg! {2};
+                if reg != RegA::A16 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 2,
+                            expected: "a16[0] register",
+                        },
+                        self.operands[2].as_span(),
+                    );
+                }
+                Instr::Bytes(BytesOp::Ins(flags!(), idx! {2}, idx! {0}, idx! {1}))
+            }
+            Operator::nop => Instr::Nop,
+
+            op => unreachable!("operator `{}` does not belong to ALU instruction set", op),
+        })
+    }
+
+    fn compile_digest<Ext>(
+        &'i self,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        let issues = &mut *ctx.issues;
+        macro_rules! reg {
+            ($no:expr) => {
+                self.reg($no, issues)
+            };
+        }
+        macro_rules! idx {
+            ($no:expr) => {
+                self.idx($no, issues)
+            };
+        }
+        Ok(match self.operator.0 {
+            Operator::ripemd => {
+                let reg: RegR = reg! {1};
+                if reg != RegR::R160 {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 2,
+                            expected: "r160 register",
+                        },
+                        self.operands[1].as_span(),
+                    );
+                    return Ok(Instr::Nop);
+                }
+                Instr::Digest(DigestOp::Ripemd(idx! {0}, idx! {1}))
+            }
+            Operator::sha2 => match reg! {1} {
+                RegR::R256 => Instr::Digest(DigestOp::Sha256(idx! {0}, idx! {1})),
+                RegR::R512 => Instr::Digest(DigestOp::Sha512(idx! {0}, idx! {1})),
+                _ => {
+                    issues.push_error(
+                        SemanticError::OperandWrongReg {
+                            operator: self.operator.0,
+                            pos: 2,
+                            expected: "r256 or r512 registers",
+                        },
+                        self.operands[1].as_span(),
+                    );
+                    return Ok(Instr::Nop);
+                }
+            },
+
+            op => unreachable!("operator `{}` does not belong to BPDIGEST instruction set", op),
+        })
+    }
+
+    fn compile_secp<Ext>(
+        &'i self,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError>
+    where
+        Ext: InstructionSet,
+    {
+        let issues = &mut *ctx.issues;
+        macro_rules! reg {
+            ($no:expr) => {
+                self.reg($no, issues)
+            };
+        }
+        macro_rules! idx {
+            ($no:expr) => {
+                self.idx($no, issues)
+            };
+        }
+        Ok(match self.operator.0 {
+            Operator::secpgen => {
+                for r in 0..=1 {
+                    let reg: RegR = reg! {r};
+                    if reg != [RegR::R256, RegR::R512][r as usize] {
+                        issues.push_error(
+                            SemanticError::OperandWrongReg {
+                                operator: self.operator.0,
+                                pos: r + 1,
+                                expected: if r == 0 { "r256 register" } else { "r512 register" },
+                            },
+                            self.operands[r as usize].as_span(),
+                        );
+                        return Ok(Instr::Nop);
+                    }
+                }
+                Instr::Secp256k1(Secp256k1Op::Gen(idx! {0}, idx! {1}))
+            }
+            Operator::secpneg => {
+                for r in 0..=1 {
+                    let reg: RegR = reg! {r};
+                    if reg != RegR::R512 {
+                        issues.push_error(
+                            SemanticError::OperandWrongReg {
+                                operator: self.operator.0,
+                                pos: r + 1,
+                                expected: "r512 register",
+                            },
+                            self.operands[r as usize].as_span(),
+                        );
+                        return Ok(Instr::Nop);
+                    }
+                }
+                Instr::Secp256k1(Secp256k1Op::Neg(idx! {0}, idx! {1}))
+            }
+            Operator::secpadd => {
+                for r in 0..=1 {
+                    let reg: RegR = reg! {r};
+                    if reg != RegR::R512 {
+                        issues.push_error(
+                            SemanticError::OperandWrongReg {
+                                operator: self.operator.0,
+                                pos: r + 1,
+                                expected: "r512 register",
+                            },
+                            self.operands[r as usize].as_span(),
+                        );
+                        return Ok(Instr::Nop);
+                    }
+                }
+                Instr::Secp256k1(Secp256k1Op::Add(idx! {0}, idx! {1}))
+            }
+            Operator::secpmul => {
+                for r in 1..=2 {
+                    let reg: RegR = reg! {r};
+                    if reg != RegR::R512 {
+                        issues.push_error(
+                            SemanticError::OperandWrongReg {
+                                operator: self.operator.0,
+                                pos: r + 1,
+                                expected: "r512 register",
+                            },
+                            self.operands[r as usize].as_span(),
+                        );
+                        return Ok(Instr::Nop);
+                    }
+                }
+                Instr::Secp256k1(Secp256k1Op::Mul(reg! {0}, idx! {0}, idx! {1}, idx! {2}))
+            }
+
+            op => unreachable!("operator `{}` does not belong to SECP256K1 instruction set", op),
+        })
+    }
+
+    fn compile_alure<Ext>(
+        &'i self,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<Ext>, CompilerError>
+    where
+        Ext: InstructionSet + From<AluReOp>,
+    {
+        let program = ctx.program;
+        let issues = &mut *ctx.issues;
+        macro_rules! reg {
+            ($no:expr) => {
+                self.reg($no, issues)
+            };
+        }
+        macro_rules! idx {
+            ($no:expr) => {
+                self.idx($no, issues)
+            };
+        }
+        Ok(match self.operator.0 {
+            Operator::read => {
+                let reg = reg! {0};
+                let var = self.var(1, reg, program, issues);
+                Instr::ExtensionCodes(Ext::from(match reg {
+                    RegAll::A(a) => AluReOp::ReadA(a, idx! {0}, var),
+                    RegAll::F(f) => AluReOp::ReadF(f, idx! {0}, var),
+                    RegAll::R(r) => AluReOp::ReadR(r, idx! {0}, var),
+                    RegAll::S => AluReOp::ReadS(idx! {0}, var),
+                }))
+            }
+
+            op => unreachable!("operator `{}` does not belong to ALURE instruction set", op),
+        })
+    }
+
+    fn compile_rgb<Ext>(&'i self, ctx: &mut CompileCtx<'i, '_>) -> Result<Instr<Ext>, CompilerError>
+    where
+        Ext: InstructionSet + From<RgbOp>,
+    {
+        let program = ctx.program;
+        let issues = &mut *ctx.issues;
+        Ok(match self.operator.0 {
+            Operator::scn => {
+                let flag = self.state_flag(issues);
+                let ty = self.state_type(0, &program.consts, issues);
+                let idx = self.reg_idx(1, RegAll::A(RegA::A16), "a16", issues);
+                Instr::ExtensionCodes(Ext::from(RgbOp::Scn(flag, ty, idx)))
+            }
+            Operator::pld => {
+                let flag = self.state_flag(issues);
+                let ty = self.state_type(0, &program.consts, issues);
+                let src = self.reg_idx(1, RegAll::A(RegA::A16), "a16", issues);
+                let dst1 = self.reg_idx(2, RegAll::R(RegR::R512), "r512", issues);
+                let dst2 = self.reg_idx(3, RegAll::R(RegR::R512), "r512", issues);
+                Instr::ExtensionCodes(Ext::from(RgbOp::Pld(flag, ty, src, dst1, dst2)))
+            }
+
+            op => unreachable!("operator `{}` does not belong to RGB instruction set", op),
+        })
+    }
+}
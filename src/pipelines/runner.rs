@@ -0,0 +1,122 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Preparation of linked binaries for the execution: resolution of the libraries they depend on
+//! and construction of the program input values
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use aluvm::library::LibSite;
+use aluvm::Prog;
+use pest::Span;
+
+use crate::ast::{Literal, Var, VarType};
+use crate::isa::{Inputs, Instr};
+use crate::issues::{self, Issues};
+use crate::linker::LibManager;
+use crate::module::DataType;
+use crate::product::{DyBin, DyInner, DyLib};
+use crate::RunError;
+
+impl DyBin {
+    /// Constructs program rooted at the binary entry point, adding all libraries the binary
+    /// depends on (directly or through other libraries) from `lib_man`
+    #[inline]
+    pub fn program(&self, lib_man: &mut LibManager) -> Result<Prog<Instr>, RunError> {
+        self.inner.program(self.entry_point, lib_man)
+    }
+
+    /// Constructs values of the program input variables. `values` are given by variable names
+    /// (without `$` prefix) in the same literal syntax as used by the `.INPUT` segment for the
+    /// default values; variables missing from `values` take their default values.
+    #[inline]
+    pub fn inputs(&self, values: &BTreeMap<String, String>) -> Result<Inputs, RunError> {
+        self.inner.inputs(values)
+    }
+}
+
+impl DyLib {
+    /// Constructs program executing exported `routine` of the library, adding all libraries it
+    /// depends on from `lib_man`
+    pub fn program(
+        &self,
+        routine: &str,
+        lib_man: &mut LibManager,
+    ) -> Result<Prog<Instr>, RunError> {
+        let pos = self
+            .exports
+            .get(routine)
+            .ok_or_else(|| RunError::RoutineUnknown(routine.to_owned()))?;
+        self.inner.program(*pos, lib_man)
+    }
+
+    /// Constructs values of the library input variables, in the same way as
+    /// [`DyBin::inputs`] does
+    #[inline]
+    pub fn inputs(&self, values: &BTreeMap<String, String>) -> Result<Inputs, RunError> {
+        self.inner.inputs(values)
+    }
+}
+
+impl DyInner {
+    fn program(&self, entry_point: u16, lib_man: &mut LibManager) -> Result<Prog<Instr>, RunError> {
+        let lib = self.as_static_lib();
+        let mut prog = Prog::<Instr>::new(lib.clone());
+        prog.set_entrypoint(LibSite::with(entry_point, self.lib_id()));
+
+        let mut added = bset! { self.lib_id() };
+        let mut queue = lib.libs.iter().copied().collect::<VecDeque<_>>();
+        while let Some(id) = queue.pop_front() {
+            if !added.insert(id) {
+                continue;
+            }
+            let dylib = lib_man.get(id).ok_or(RunError::LibNotFound(id))?;
+            queue.extend(dylib.as_static_lib().libs.iter().copied());
+            prog.add_lib(dylib.as_static_lib().clone()).map_err(|_| RunError::TooManyLibs)?;
+        }
+        Ok(prog)
+    }
+
+    fn inputs(&self, values: &BTreeMap<String, String>) -> Result<Inputs, RunError> {
+        let names = self.vars.iter().filter_map(|var| var.name.as_deref()).collect::<BTreeSet<_>>();
+        if let Some(name) = values.keys().find(|name| !names.contains(name.as_str())) {
+            return Err(RunError::InputUnknown(name.clone()));
+        }
+
+        let mut inputs = Vec::with_capacity(self.vars.len());
+        for var in &self.vars {
+            let value = var.name.as_ref().and_then(|name| Some((name, values.get(name)?)));
+            let (name, text) = match value {
+                Some(value) => value,
+                None => {
+                    inputs.push(var.data.clone());
+                    continue;
+                }
+            };
+            let invalid = || RunError::InputInvalid(name.clone(), text.clone());
+            let ty = match var.data {
+                DataType::ByteStr(_) => VarType::Bytes,
+                DataType::Int(layout, _) => VarType::Int(layout),
+                DataType::Float(layout, _) => VarType::Float(layout),
+            };
+            let var = Var {
+                name: format!("${}", name),
+                ty,
+                default: Some(Literal::parse(text).ok_or_else(invalid)?),
+                info: var.info.clone(),
+                span: Span::new(text, 0, text.len()).ok_or_else(invalid)?,
+            };
+            let mut issues = Issues::<issues::Compile>::default();
+            let compiled = var.compile(&mut issues).map_err(|_| invalid())?;
+            if issues.has_errors() {
+                return Err(invalid());
+            }
+            inputs.push(compiled.data);
+        }
+        Ok(inputs)
+    }
+}
@@ -0,0 +1,121 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Execution of the tests defined in the source code with `.TEST` segments.
+//!
+//! Test passes if its execution completes with `st0` register set to true, so the test body may
+//! reset `st0` in the middle, for instance to check that a routine fails and then invert the
+//! result with `stinv`. Tests executing more than [`STEP_LIMIT`] instructions are failed, so
+//! infinite loops do not hang the test run.
+
+use std::collections::BTreeMap;
+
+use aluvm::library::LibSite;
+
+use crate::debug::SrcLoc;
+use crate::debugger::{Debugger, Halt};
+use crate::isa::Context;
+use crate::linker::LibManager;
+use crate::product::DyLib;
+use crate::RunError;
+
+/// Prefix of the names under which tests are exported by the modules compiled with tests
+pub const TEST_PREFIX: &str = ".TEST ";
+
+/// Maximal number of instructions executed by a single test, after which the test is considered
+/// hanging and fails
+pub const STEP_LIMIT: usize = 1_000_000;
+
+/// Result of a single test run
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum TestResult {
+    #[display("ok")]
+    Passed,
+
+    /// `st0` register is false at the end of the test; `statement` is the test statement after
+    /// which `st0` has become false
+    #[display("assertion failed at {statement}")]
+    Assertion { statement: SrcLoc },
+
+    /// Execution has stopped with `st0` set to false at the instruction `site`, located at `loc`,
+    /// inside a routine called by the test statement
+    #[display("execution failed at {site} while running statement at {statement}")]
+    Execution { statement: SrcLoc, site: LibSite, loc: Option<SrcLoc> },
+
+    /// Test has executed [`STEP_LIMIT`] instructions without completion; `statement` is the test
+    /// statement which was running at that moment
+    #[display("execution exceeded step limit while running statement at {statement}")]
+    StepLimit { statement: SrcLoc },
+}
+
+impl TestResult {
+    #[inline]
+    pub fn is_passed(&self) -> bool { *self == TestResult::Passed }
+}
+
+impl DyLib {
+    /// Names of the tests contained in the library linked from modules compiled with tests
+    pub fn tests(&self) -> impl Iterator<Item = &str> {
+        self.exports.keys().filter_map(|name| name.strip_prefix(TEST_PREFIX))
+    }
+
+    /// Runs test `name` from the initial state of the registers, using default values of the
+    /// input variables. The library must carry debug information, which locates statements of the
+    /// test body.
+    pub fn run_test(&self, name: &str, lib_man: &mut LibManager) -> Result<TestResult, RunError> {
+        let routine = format!("{}{}", TEST_PREFIX, name);
+        let start =
+            *self.exports.get(&routine).ok_or_else(|| RunError::TestUnknown(name.to_owned()))?;
+        // Test body spans up to the code of the next routine
+        let end = self.exports.values().filter(|pos| **pos > start).min().copied();
+        let debug = self.debug().ok_or(RunError::DebugInfoAbsent)?;
+        let loc = |pos: u16| debug.lines.get(&pos).cloned().ok_or(RunError::DebugInfoAbsent);
+
+        let prog = self.program(&routine, lib_man)?;
+        let mut debugger = Debugger::with(&prog, Context::from(self.inputs(&BTreeMap::new())?));
+        let mut statement = None;
+        let mut failed = None;
+        let mut steps = 0usize;
+        while let Some(site) = debugger.site() {
+            let in_body = site.lib == self.lib_id()
+                && site.pos >= start
+                && end.map(|end| site.pos < end).unwrap_or(true)
+                && debug.lines.contains_key(&site.pos);
+            if in_body {
+                // Execution has reached the next statement of the test body; keep the first of the
+                // completed statements which have left `st0` false
+                if let Some(pos) = statement {
+                    failed = if debugger.status() { None } else { failed.or(Some(pos)) };
+                }
+                statement = Some(site.pos);
+            }
+            if steps == STEP_LIMIT {
+                let pos = statement.ok_or(RunError::DebugInfoAbsent)?;
+                return Ok(TestResult::StepLimit { statement: loc(pos)? });
+            }
+            steps += 1;
+            match debugger.step()? {
+                Halt::Step | Halt::Breakpoint(_) => {}
+                Halt::Completed(true) => return Ok(TestResult::Passed),
+                Halt::Completed(false) => {
+                    let pos = statement.ok_or(RunError::DebugInfoAbsent)?;
+                    return Ok(if in_body {
+                        TestResult::Assertion { statement: loc(failed.unwrap_or(pos))? }
+                    } else {
+                        let at = if site.lib == self.lib_id() {
+                            debug.loc(site.pos).cloned()
+                        } else {
+                            None
+                        };
+                        TestResult::Execution { statement: loc(pos)?, site, loc: at }
+                    });
+                }
+            }
+        }
+        Ok(TestResult::Passed)
+    }
+}
@@ -0,0 +1,121 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Control-flow verification of routines performed by the compiler before encoding them
+
+use std::collections::BTreeSet;
+
+use pest::Span;
+
+use crate::ast::{Operand, Operator, Routine, Statement};
+use crate::issues::{self, Issues, SemanticError, SemanticWarning};
+
+impl<'i> Routine<'i> {
+    /// Verifies control flow of the routine, reporting jumps to undefined labels, labels which are
+    /// never jumped to, statements which are never executed and execution paths reaching the end
+    /// of the routine without `ret`, `succ` or `fail`
+    pub fn verify(&'i self, issues: &mut Issues<'i, issues::Compile>) {
+        let mut targeted = BTreeSet::new();
+        for statement in &self.statements {
+            let (label, span) = match statement.jump_target() {
+                Some(target) => target,
+                None => continue,
+            };
+            if self.labels.contains_key(label) {
+                targeted.insert(label);
+            } else {
+                issues.set_expansion(statement.expansion);
+                issues.push_error(
+                    SemanticError::LabelUnknown {
+                        label: label_name(label).to_owned(),
+                        routine: self.name.clone(),
+                    },
+                    span,
+                );
+            }
+        }
+
+        for (label, no) in &self.labels {
+            if targeted.contains(label.as_str()) {
+                continue;
+            }
+            let statement = match self.statements.get(*no as usize) {
+                Some(statement) => statement,
+                None => continue,
+            };
+            let span = match &statement.label {
+                Some((name, span)) if name == label => span,
+                _ => &statement.span,
+            };
+            issues.set_expansion(statement.expansion);
+            issues.push_warning(SemanticWarning::LabelNotUsed(label_name(label).to_owned()), span);
+        }
+
+        let len = self.statements.len();
+        let mut reachable = vec![false; len];
+        let mut falls_off = None;
+        let mut queue = vec![0usize];
+        while let Some(no) = queue.pop() {
+            if no >= len || reachable[no] {
+                continue;
+            }
+            reachable[no] = true;
+            let statement = &self.statements[no];
+            if let Some(to) = statement.jump_target().and_then(|(label, _)| self.labels.get(label))
+            {
+                queue.push(*to as usize);
+            }
+            if !statement.falls_through() {
+                continue;
+            }
+            if no + 1 == len {
+                falls_off = Some(statement);
+            } else {
+                queue.push(no + 1);
+            }
+        }
+
+        let mut prev_reachable = true;
+        for (statement, reachable) in self.statements.iter().zip(reachable) {
+            if !reachable && prev_reachable {
+                issues.set_expansion(statement.expansion);
+                issues.push_warning(
+                    SemanticWarning::CodeUnreachable(self.name.clone()),
+                    &statement.span,
+                );
+            }
+            prev_reachable = reachable;
+        }
+
+        if let Some(statement) = falls_off {
+            issues.set_expansion(statement.expansion);
+            issues
+                .push_warning(SemanticWarning::RoutineNoReturn(self.name.clone()), &statement.span);
+        }
+        issues.set_expansion(None);
+    }
+}
+
+impl<'i> Statement<'i> {
+    /// Returns label which is the target of `jmp` or `jif` statement
+    fn jump_target(&self) -> Option<(&str, &Span<'i>)> {
+        match (self.operator.0, self.operands.first()) {
+            (Operator::jmp | Operator::jif, Some(Operand::Goto(label, span))) => {
+                Some((label.as_str(), span))
+            }
+            _ => None,
+        }
+    }
+
+    /// Detects whether execution may proceed to the statement following this one
+    fn falls_through(&self) -> bool {
+        !matches!(self.operator.0, Operator::jmp | Operator::ret | Operator::succ | Operator::fail)
+    }
+}
+
+/// Strips the suffix which makes labels defined by a macro unique to its invocation
+fn label_name(label: &str) -> &str { label.split('@').next().unwrap_or(label) }
@@ -30,7 +30,6 @@ macro_rules! aluasm_macro_fail {
 
 macro_rules! aluasm_compiler {
     ($( $tt:tt )+) => { {
-        use pest::Parser;
         let main = stringify!($( $tt )+);
         let main = main.replace(" [", "[");
         let main = main.replace("\n[", "[");
@@ -43,8 +42,8 @@ macro_rules! aluasm_compiler {
                .MAIN ; Code segment
                     {}
         "#, main);
-        let pairs = aluasm::parser::Parser::parse(aluasm::parser::Rule::program, &code).unwrap();
-        let (program, issues) = aluasm::ast::Program::analyze(pairs.into_iter().next().unwrap()).unwrap();
+        let sources = aluasm::source::SourceMap::with("test", code).unwrap();
+        let (program, issues) = aluasm::ast::Program::analyze(&sources).unwrap();
         assert!(!issues.has_errors(), "error(analyze): {}", issues);
         let (module, issues) = program.compile(&mut None).unwrap();
         assert!(!issues.has_errors(), "error(compile): {}", issues);
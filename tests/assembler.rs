@@ -0,0 +1,159 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use aluasm::isa::ExtOp;
+use aluasm::issues::Severity;
+use aluasm::plugins::IsaTable;
+use aluasm::{Assembler, AssemblerError, BuildError};
+
+const CODE: &str = r#".INCLUDE "common.aluasm"
+.MAIN
+                put     a8[1],$nine
+                put     a8[2],9
+                eq.n    a8[1],a8[2]
+                ret
+"#;
+
+const COMMON: &str = r#".ISAE
+                ALU
+.CONST
+                $nine = 9
+"#;
+
+fn resolver(files: &[(&str, &str)]) -> impl Fn(&Path) -> io::Result<String> {
+    let files = files
+        .iter()
+        .map(|(name, text)| (PathBuf::from(name), text.to_string()))
+        .collect::<BTreeMap<_, _>>();
+    move |path: &Path| {
+        files.get(path).cloned().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+#[test]
+fn assemble() {
+    let (module, diagnostics) = Assembler::with("main.aluasm", CODE)
+        .resolver(resolver(&[("common.aluasm", COMMON)]))
+        .assemble()
+        .unwrap();
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    assert!(module.exports.contains_key(".MAIN"));
+    assert_eq!(module.debug, None);
+
+    let (debug, _) = Assembler::with("main.aluasm", CODE)
+        .resolver(resolver(&[("common.aluasm", COMMON)]))
+        .debug(true)
+        .assemble()
+        .unwrap();
+    let info = debug.debug.clone().unwrap();
+    assert_eq!(info.sources.keys().collect::<Vec<_>>(), vec!["common.aluasm", "main.aluasm"]);
+    let mut stripped = debug;
+    stripped.strip_debug();
+    assert_eq!(stripped, module);
+}
+
+#[test]
+fn load() {
+    let (module, _) = Assembler::load("main.aluasm")
+        .resolver(resolver(&[("main.aluasm", CODE), ("common.aluasm", COMMON)]))
+        .assemble()
+        .unwrap();
+    assert!(module.exports.contains_key(".MAIN"));
+
+    match Assembler::load("main.aluasm").resolver(resolver(&[("main.aluasm", CODE)])).assemble() {
+        Err(AssemblerError::Build(BuildError::FileNotFound { file, .. })) => {
+            assert_eq!(file, "common.aluasm")
+        }
+        result => panic!("missing file is not reported: {:?}", result.map(|(_, d)| d)),
+    }
+}
+
+#[test]
+fn include_normalized() {
+    let code = r#".INCLUDE "lib/common.aluasm"
+.INCLUDE "./lib/../lib/common.aluasm"
+.MAIN
+                put     a8[1],$nine
+                ret
+"#;
+    let (module, diagnostics) = Assembler::with("main.aluasm", code)
+        .resolver(resolver(&[("lib/common.aluasm", COMMON)]))
+        .debug(true)
+        .assemble()
+        .unwrap();
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let info = module.debug.unwrap();
+    assert_eq!(info.sources.keys().collect::<Vec<_>>(), vec!["lib/common.aluasm", "main.aluasm"]);
+}
+
+#[test]
+fn warnings_as_errors() {
+    let code = r#".ISAE
+                ALU
+.CONST
+                $unused = 5
+.MAIN
+                ret
+"#;
+    let (_, diagnostics) = Assembler::with("main", code).assemble().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    let location = diagnostics[0].location.clone().unwrap();
+    assert_eq!((location.file.as_deref(), location.line), (Some("main"), 4));
+
+    match Assembler::with("main", code).warnings_as_errors(true).assemble() {
+        Err(AssemblerError::Failed { errors: 1, warnings: 0, diagnostics }) => {
+            assert_eq!(diagnostics[0].severity, Severity::Error);
+            assert!(!diagnostics[0].message.contains('\x1B'));
+        }
+        result => panic!("warning is not promoted: {:?}", result.map(|(_, d)| d)),
+    }
+}
+
+#[test]
+fn errors() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     a8[1],$absent
+                ret
+"#;
+    match Assembler::with("main", code).assemble() {
+        Err(AssemblerError::Failed { diagnostics, .. }) => {
+            assert_eq!(diagnostics[0].severity, Severity::Error);
+            assert_eq!(diagnostics[0].location.as_ref().map(|loc| loc.line), Some(4));
+        }
+        result => panic!("error is not reported: {:?}", result.map(|(_, d)| d)),
+    }
+
+    match Assembler::with("main", "not an assembly").assemble() {
+        Err(AssemblerError::Parser(file, _)) => assert_eq!(file, "main"),
+        result => panic!("parsing error is not reported: {:?}", result.map(|(_, d)| d)),
+    }
+}
+
+#[test]
+fn isa_profile() {
+    let code = r#".ISAE
+                ALU
+                ALURE
+.INPUT
+                $value: u16 "Value"
+.MAIN
+                read    a16[1], $value
+                ret
+"#;
+    assert!(Assembler::with("main", code).assemble().is_ok());
+    assert!(matches!(
+        Assembler::with("main", code).isa(IsaTable::<ExtOp>::core()).assemble(),
+        Err(AssemblerError::Failed { .. })
+    ));
+}
@@ -6,14 +6,18 @@ LINK_FLAGS=""
 ASM=./target/debug/aluasm
 LINK=./target/debug/alink
 
+$ASM fmt --check examples/*.aluasm || exit 1
+
 $ASM $ASM_FLAGS examples/all.aluasm
 $ASM $ASM_FLAGS examples/miner.aluasm
 $ASM $ASM_FLAGS examples/pow.aluasm
 $ASM $ASM_FLAGS examples/pedersen.aluasm
-# $ASM $ASM_FLAGS examples/rgb20.aluasm
+$ASM $ASM_FLAGS examples/rgb20.aluasm
+
+$ASM disasm build/objects/all.ao > /dev/null || exit 1
 
 $LINK $LINK_FLAGS --org=lnpbp.org --bin all
 $LINK $LINK_FLAGS --org=pandoracore.org --lib miner
 $LINK $LINK_FLAGS --org=pandoracore.org --bin pow
 $LINK $LINK_FLAGS --org=lnpbp.org --lib pedersen
-# $LINK $LINK_FLAGS --org=lnpbp.org --bin rgb20
+$LINK $LINK_FLAGS --org=lnpbp.org --bin -n rgb20 rgb20 pedersen
@@ -0,0 +1,96 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+//! Fixture shared by the integration tests, which compiles and links source code failing the test
+//! on any reported error
+
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+
+use aluasm::ast::Program;
+use aluasm::linker::LibManager;
+use aluasm::module::Module;
+use aluasm::product::{DyBin, DyLib, Product};
+use aluasm::source::SourceMap;
+
+/// Analyzes source code, checking that it contains no errors
+pub fn analyze(sources: &SourceMap) -> Program<'_> {
+    let (program, issues) = Program::analyze(sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    program
+}
+
+/// Compiles source code of the `test` file into a module
+pub fn compile(code: &str) -> Module { compile_named("test", code) }
+
+/// Compiles source code of the file `name` into a module
+pub fn compile_named(name: &str, code: &str) -> Module {
+    let sources = SourceMap::with(name, code).unwrap();
+    let program = analyze(&sources);
+    let (module, issues) = program.compile(&mut None).unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+    module
+}
+
+/// Compiles source code of the file `name` into a module with debug information
+pub fn compile_debug(name: &str, code: &str) -> Module {
+    let sources = SourceMap::with(name, code).unwrap();
+    let program = analyze(&sources);
+    let (module, issues) = program.compile_debug(&mut None).unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+    module
+}
+
+/// Compiles source code of the `test` file into a module exporting its tests
+pub fn compile_tests(code: &str) -> Module {
+    let sources = SourceMap::with("test", code).unwrap();
+    let program = analyze(&sources);
+    let (module, issues) = program.compile_tests().unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+    module
+}
+
+/// Collects modules under their names
+pub fn modules<'n>(
+    modules: impl IntoIterator<Item = (&'n str, Module)>,
+) -> BTreeMap<String, Module> {
+    modules.into_iter().map(|(name, module)| (name.to_owned(), module)).collect()
+}
+
+/// Links modules into a binary named `test`
+pub fn link(modules: &BTreeMap<String, Module>, lib_man: &mut LibManager) -> Product {
+    let (product, issues) =
+        Module::link_bin(modules, "test".to_owned(), "test".to_owned(), lib_man).unwrap();
+    assert!(!issues.has_errors(), "error(link): {}", issues);
+    product
+}
+
+/// Links modules into a binary named `test`, resolving libraries from `lib_man`
+pub fn link_bin(modules: &BTreeMap<String, Module>, lib_man: &mut LibManager) -> DyBin {
+    match link(modules, lib_man) {
+        Product::Bin(bin) => bin,
+        Product::Lib(_) => panic!("binary is expected"),
+    }
+}
+
+/// Links modules into a library `name`
+pub fn link_lib(modules: &BTreeMap<String, Module>, name: &str) -> DyLib {
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    let (product, issues) =
+        Module::link_lib(modules, name.to_owned(), "test".to_owned(), &mut lib_man).unwrap();
+    assert!(!issues.has_errors(), "error(link): {}", issues);
+    match product {
+        Product::Lib(lib) => lib,
+        Product::Bin(_) => panic!("library is expected"),
+    }
+}
+
+/// Links a single module `main` into a binary which does not use libraries
+pub fn bin(module: Module) -> DyBin {
+    link_bin(&modules(vec![("main", module)]), &mut LibManager::with(vec![]).unwrap())
+}
@@ -0,0 +1,179 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use std::collections::BTreeMap;
+
+use aluasm::debug::DebugInfo;
+use aluasm::debugger::{Debugger, Halt};
+use aluasm::isa::Context;
+use aluasm::linker::LibManager;
+use aluasm::module::Module;
+use aluasm::product::Product;
+use aluasm::DebugError;
+use aluvm::data::encoding::{Decode, Encode};
+use aluvm::data::Number;
+use aluvm::library::LibSite;
+use aluvm::reg::{Reg32, RegA};
+use common::{bin, compile, compile_debug, link_bin, modules};
+
+mod common;
+
+const CODE: &str = r#".ISAE
+                ALU
+.MAIN
+                put     a8[1], 1
+                routine double
+done:           nop
+                ret
+.ROUTINE double
+                add     a8[1], a8[1]
+                ret
+"#;
+
+fn line(debug: &DebugInfo, line: u32) -> u16 { debug.offsets(None, line).next().unwrap() }
+
+#[test]
+fn debug_info() {
+    let module = compile_debug("test", CODE);
+    let debug = module.debug.clone().unwrap();
+
+    assert_eq!(line(&debug, 4), 0);
+    assert!(line(&debug, 5) > line(&debug, 4));
+    assert_eq!(debug.offsets(Some("test"), 6).collect::<Vec<_>>(), vec![line(&debug, 6)]);
+    assert_eq!(debug.offsets(Some("other"), 6).count(), 0);
+    assert_eq!(debug.offsets(None, 8).count(), 0);
+
+    assert_eq!(debug.label(".MAIN", "done"), Some(line(&debug, 6)));
+    assert_eq!(debug.label("double", "done"), None);
+
+    let loc = debug.loc(module.exports["double"]).unwrap();
+    assert_eq!(loc.to_string(), "test:9:17");
+}
+
+#[test]
+fn debug_section() {
+    let plain = compile(CODE);
+    assert_eq!(plain.debug, None);
+
+    let mut module = compile_debug("test", CODE);
+    let debug = module.debug.clone().unwrap();
+    assert_eq!(debug.sources.len(), 1);
+    assert_eq!(debug.sources["test"], DebugInfo::source_hash(CODE));
+    assert_ne!(debug.sources["test"], DebugInfo::source_hash(""));
+
+    let mut data = vec![];
+    module.encode(&mut data).unwrap();
+    assert_eq!(Module::decode(&data[..]).unwrap(), module);
+
+    // Debug section is appended after all other sections, so stripping it does not change the
+    // rest of the file
+    module.strip_debug();
+    assert_eq!(module, plain);
+    let mut stripped = vec![];
+    module.encode(&mut stripped).unwrap();
+    assert!(data.starts_with(&stripped) && data.len() > stripped.len());
+    assert_eq!(Module::decode(&stripped[..]).unwrap().debug, None);
+}
+
+#[test]
+fn linked_debug() {
+    let main = compile_debug("main", CODE);
+    let main_debug = main.debug.clone().unwrap();
+    let other = compile_debug(
+        "other",
+        r#".ISAE
+                ALU
+.ROUTINE triple
+                nop
+skip:           ret
+"#,
+    );
+    let other_debug = other.debug.clone().unwrap();
+    let modules = modules(vec![("main", main), ("other", other)]);
+    let bin = link_bin(&modules, &mut LibManager::with(vec![]).unwrap());
+
+    let debug = bin.debug().unwrap();
+    assert_eq!(debug.sources.keys().collect::<Vec<_>>(), vec!["main", "other"]);
+    assert_eq!(debug.label(".MAIN", "done"), main_debug.label(".MAIN", "done"));
+    for (pos, loc) in &main_debug.lines {
+        assert_eq!(&debug.lines[pos], loc);
+    }
+    // Code of the second module follows the first one, so its offsets are shifted
+    let base =
+        debug.label("triple", "skip").unwrap() - other_debug.label("triple", "skip").unwrap();
+    assert!(base > 0);
+    for (pos, loc) in &other_debug.lines {
+        assert_eq!(&debug.lines[&(pos + base)], loc);
+    }
+    assert_eq!(debug.lines.len(), main_debug.lines.len() + other_debug.lines.len());
+
+    let mut product = Product::Bin(bin);
+    let mut data = vec![];
+    product.encode(&mut data).unwrap();
+    assert_eq!(Product::decode(&data[..]).unwrap(), product);
+
+    product.strip_debug();
+    assert_eq!(product.debug(), None);
+    let mut data = vec![];
+    product.encode(&mut data).unwrap();
+    assert_eq!(Product::decode(&data[..]).unwrap().debug(), None);
+}
+
+#[test]
+fn step_and_breakpoints() {
+    let module = compile_debug("test", CODE);
+    let debug = module.debug.clone().unwrap();
+    let bin = bin(module);
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    let prog = bin.program(&mut lib_man).unwrap();
+    let mut debugger = Debugger::with(&prog, Context::from(bin.inputs(&no_inputs()).unwrap()));
+    let site = |pos| Some(LibSite::with(pos, bin.lib_id()));
+
+    assert_eq!(debugger.site(), site(0));
+    assert_eq!(debugger.step(), Ok(Halt::Step));
+    assert_eq!(debugger.site(), site(line(&debug, 5)));
+
+    let target = LibSite::with(line(&debug, 9), bin.lib_id());
+    assert!(debugger.set_breakpoint(target));
+    assert!(!debugger.set_breakpoint(target));
+    assert_eq!(debugger.resume(), Ok(Halt::Breakpoint(target)));
+    assert_eq!(
+        Option::<Number>::from(debugger.registers().get_n(RegA::A8, Reg32::Reg1)),
+        Some(Number::from(1u8))
+    );
+
+    assert_eq!(debugger.step(), Ok(Halt::Step));
+    assert_eq!(
+        Option::<Number>::from(debugger.registers().get_n(RegA::A8, Reg32::Reg1)),
+        Some(Number::from(2u8))
+    );
+
+    assert!(debugger.remove_breakpoint(target));
+    assert_eq!(debugger.resume(), Ok(Halt::Completed(true)));
+    assert_eq!(debugger.site(), None);
+    assert_eq!(debugger.step(), Err(DebugError::Completed));
+}
+
+#[test]
+fn exec_statement() {
+    let module = compile_debug("test", CODE);
+    let bin = bin(module);
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    let prog = bin.program(&mut lib_man).unwrap();
+    let mut debugger = Debugger::with(&prog, Context::from(bin.inputs(&no_inputs()).unwrap()));
+
+    debugger.exec("put a8[2], 7").unwrap();
+    assert_eq!(
+        Option::<Number>::from(debugger.registers().get_n(RegA::A8, Reg32::Reg2)),
+        Some(Number::from(7u8))
+    );
+    assert_eq!(debugger.site(), Some(LibSite::with(0, bin.lib_id())));
+
+    assert_eq!(debugger.exec("put a8[2]"), Err(DebugError::Statement("put a8[2]".to_owned())));
+}
+
+fn no_inputs() -> BTreeMap<String, String> { BTreeMap::new() }
@@ -0,0 +1,126 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use aluasm::formatter::format;
+use aluasm::linker::LibManager;
+use aluasm::product::Product;
+use common::{bin, compile};
+
+mod common;
+
+#[test]
+fn module_roundtrip() {
+    let module = compile(
+        r#".ISAE
+                ALU
+                ALURE
+                BPDIGEST
+                SECP256
+           .LIBS
+                ext alu1wnhusevxmdphv3dh8ada44k0xw66ahq9nzhkv39z07hmudhp380sq0dtml
+           .INPUT
+                $limit: u16 = 1000 "Limit of \"iterations\""
+                $delta: i8 = -5 "Delta"
+                $data: bytes "Data"
+                $rate: f32 = 1.5 "Rate"
+           .MAIN
+                clr     r1024[5]
+                put     a16[8], 5
+                put     a8[2], 0
+                put     s16[1], x"DEADBEEF"
+                put     f32[13], 2.25
+                putif   0xAF67937B5498DC, r256[1]
+                read    a16[1], $limit
+                read    s16[2], $data
+                routine count
+                call    ext->check
+                exec    ext->run
+                call    other
+                ret
+           .ROUTINE count
+           loop:
+                inc     a16[3]
+                add     a16[4], 5
+                sub     a16[4], 100
+                dec     a16[8]
+                gt.u    a16[3], a16[1]
+                jif     loop
+                swp     a8[1], a8[2]
+                dup     a256[1], a256[7]
+                mov     a16[1], a16[2]
+                cnv     f128[4], a128[3]
+                spy     a1024[15], r1024[24]
+                lt.s    a8[5], a8[9]
+                eq.n    r160[5], r160[9]
+                ifn     a32[32]
+                ifz     r2048[17]
+                stinv
+                st.s    a8[1]
+                add.uc  a32[12], a32[13]
+                mul.c   f32[12], f32[13]
+                rem     a64[8], a8[2]
+                neg     a64[16]
+                and     a32[5], a32[6], a32[5]
+                shr.s   a16[2], a256[12]
+                rev     a512[28]
+                ripemd  s16[9], r160[7]
+                sha2    s16[19], r256[2]
+                secpmul r256[1], r512[1], r512[2]
+                ret
+        "#,
+    );
+
+    let source = module.decompile().unwrap();
+    assert_eq!(format("decompiled", &source).unwrap(), source);
+    assert_eq!(compile(&source), module);
+}
+
+#[test]
+fn synthetic_labels() {
+    let module = compile(
+        r#".ISAE
+                ALU
+           .MAIN
+           start:
+                inc     a8[1]
+                jif     start
+                ret
+        "#,
+    );
+    assert_eq!(
+        module.decompile().unwrap(),
+        ".ISAE
+                ALU
+
+.MAIN
+label_0000:     inc     a8[1]
+                jif     label_0000
+                ret
+"
+    );
+}
+
+#[test]
+fn product_roundtrip() {
+    let product = Product::Bin(bin(compile(
+        r#".ISAE
+                ALU
+           .MAIN
+                put     a8[1], 1
+                routine double
+                ret
+           .ROUTINE double
+                add     a8[1], a8[1]
+                ret
+        "#,
+    )));
+
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    let source = product.decompile(&mut lib_man).unwrap();
+    assert!(source.contains(".ROUTINE routine_"));
+    assert_eq!(Product::Bin(bin(compile(&source))), product);
+}
@@ -0,0 +1,94 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use std::fs;
+
+use aluasm::formatter::format;
+use common::{compile, compile_named};
+
+mod common;
+
+#[test]
+fn canonical_layout() {
+    let code = ".MAIN ; entry\n  put 5,a8[1]   ; five\nloop:\n\tinc a8[1]\n                jmp \
+                loop\n.ISAE\n    ALU\n.CONST\n$xy=1+ 2\n";
+    let formatted = format("test", code).unwrap();
+    assert_eq!(
+        formatted,
+        ".ISAE
+                ALU
+
+.CONST
+                $xy = 1 + 2
+
+.MAIN                                           ; entry
+                put     5, a8[1]                ; five
+loop:           inc     a8[1]
+                jmp     loop
+"
+    );
+    assert_eq!(format("test", &formatted).unwrap(), formatted);
+}
+
+#[test]
+fn comments_kept() {
+    let code = r#".ISAE ; extensions
+    ALU
+.MAIN
+    read s16[1], $input ; first line
+                        ; second line
+    ; standalone
+long_label_name:   ret  ; done
+; end
+"#;
+    let formatted = format("test", code).unwrap();
+    assert_eq!(
+        formatted,
+        ".ISAE                                           ; extensions
+                ALU
+
+.MAIN
+                read    s16[1], $input          ; first line
+                                                ; second line
+                ; standalone
+long_label_name:
+                ret                             ; done
+
+; end
+"
+    );
+}
+
+#[test]
+fn examples_formatted() {
+    for name in ["all", "miner", "pedersen", "pow", "rgb20"] {
+        let file = format!("examples/{}.aluasm", name);
+        let code = fs::read_to_string(&file).unwrap();
+        let formatted = format(&file, &code).unwrap();
+        assert_eq!(formatted, code, "`{}` is not formatted", file);
+        compile_named(&file, &code);
+    }
+}
+
+#[test]
+fn program_preserved() {
+    let code = r#".ROUTINE helper
+    ret
+.MACRO twice $reg
+    inc $reg
+                    inc $reg
+.ENDM
+.MAIN
+	twice a8[1]
+	routine helper
+	ret
+.ISAE
+ ALU
+"#;
+    let formatted = format("test", code).unwrap();
+    assert_eq!(compile(&formatted), compile(code));
+}
@@ -0,0 +1,337 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use std::fs;
+
+use aluasm::isa::{Context, Instr};
+use aluasm::issues::Issues;
+use aluasm::linker::LibManager;
+use aluasm::module::{Module, ModuleError, Reloc, MODULE_VERSION};
+use aluasm::product::Product;
+use aluvm::data::encoding::{Decode, Encode, MaxLenWord};
+use aluvm::isa::ControlFlowOp;
+use common::{compile, link, link_bin, link_lib, modules};
+
+mod common;
+
+#[test]
+fn routine_from_other_module() {
+    let main = compile(
+        r#".ISAE
+                ALU
+           .MAIN
+                put     a8[1],1
+                routine inc_a8
+                put     a8[2],2
+                eq.n    a8[1],a8[2]
+                ret
+        "#,
+    );
+    // `aaa` routine goes first, so the offset of `inc_a8` differs from its index
+    let util = compile(
+        r#".ISAE
+                ALU
+           .ROUTINE aaa
+                ret
+           .ROUTINE inc_a8
+                inc     a8[1]
+                ret
+        "#,
+    );
+    assert_eq!(main.relocs.len(), 1);
+
+    let modules = modules(vec![("main", main), ("util", util)]);
+
+    let bin = link_bin(&modules, &mut LibManager::with(vec![]).unwrap());
+    assert_eq!(bin.entry_point, 0);
+
+    let mut runtime = aluvm::Vm::<aluvm::isa::Instr>::new();
+    let program = aluvm::Prog::<aluvm::isa::Instr>::new(bin.as_static_lib().clone());
+    assert!(runtime.run(&program, &()), "link: expected success:\n{:#?}", runtime.registers);
+}
+
+#[test]
+fn call_without_library() {
+    // `call inc_local` resolves to the routine of the same module, while `call inc_a8` is left for
+    // the linker
+    let main = compile(
+        r#".ISAE
+                ALU
+           .MAIN
+                put     a8[1],1
+                call    inc_local
+                call    inc_a8
+                put     a8[2],3
+                eq.n    a8[1],a8[2]
+                ret
+           .ROUTINE inc_local
+                inc     a8[1]
+                ret
+        "#,
+    );
+    let util = compile(
+        r#".ISAE
+                ALU
+           .ROUTINE inc_a8
+                inc     a8[1]
+                ret
+        "#,
+    );
+    assert_eq!(main.imports.count(), 0);
+    assert_eq!(main.relocs.len(), 1);
+
+    let modules = modules(vec![("main", main), ("util", util)]);
+
+    let bin = link_bin(&modules, &mut LibManager::with(vec![]).unwrap());
+    let mut runtime = aluvm::Vm::<aluvm::isa::Instr>::new();
+    let program = aluvm::Prog::<aluvm::isa::Instr>::new(bin.as_static_lib().clone());
+    assert!(runtime.run(&program, &()), "link: expected success:\n{:#?}", runtime.registers);
+}
+
+#[test]
+fn external_library_call() {
+    let util = compile(
+        r#".ISAE
+                ALU
+           .ROUTINE inc_a8
+                inc     a8[1]
+                ret
+           .ROUTINE check
+                put     a8[3],3
+                eq.n    a8[1],a8[3]
+                ret
+        "#,
+    );
+    let lib = link_lib(&modules(vec![("util", util)]), "util");
+    let path = std::env::temp_dir().join(format!("aluasm-link-{}.ald", lib.lib_id()));
+    lib.encode(fs::File::create(&path).unwrap()).unwrap();
+
+    // `check` is reached with `exec`, so its `ret` completes the program
+    let main = compile(&format!(
+        r#".ISAE
+                ALU
+           .LIBS
+                util {}
+           .MAIN
+                put     a8[1],1
+                call    util->inc_a8
+                call    util->inc_a8
+                exec    util->check
+        "#,
+        lib.lib_id()
+    ));
+    assert_eq!(main.relocs.len(), 3);
+    let mut lib_man = LibManager::with(vec![path]).unwrap();
+    let bin = link_bin(&modules(vec![("main", main)]), &mut lib_man);
+
+    // Call sites are patched with the offsets of the library routines
+    let sites = bin
+        .as_static_lib()
+        .disassemble::<Instr>()
+        .unwrap()
+        .into_iter()
+        .filter_map(|instr| match instr {
+            Instr::ControlFlow(ControlFlowOp::Call(site) | ControlFlowOp::Exec(site)) => Some(site),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let exports = &lib.exports;
+    assert_eq!(sites.iter().map(|site| site.lib).collect::<Vec<_>>(), vec![lib.lib_id(); 3]);
+    assert_eq!(sites.iter().map(|site| site.pos).collect::<Vec<_>>(), vec![
+        exports["inc_a8"],
+        exports["inc_a8"],
+        exports["check"]
+    ]);
+
+    let program = bin.program(&mut lib_man).unwrap();
+    let mut runtime = aluvm::Vm::<Instr>::new();
+    let success = runtime.run(&program, &Context::default());
+    assert!(success, "link: expected success:\n{:#?}", runtime.registers);
+}
+
+#[test]
+fn merged_call_table() {
+    let first = compile(
+        r#".ISAE
+                ALU
+           .LIBS
+                ext alu145mc48u7f6n9lzesm5wpvrq5y8rck9qgyyjpd2vshpv3ww7cp89qv27dl3
+           .ROUTINE first
+                call    ext->aaa
+                ret
+        "#,
+    );
+    let second = compile(
+        r#".ISAE
+                ALU
+           .LIBS
+                ext alu145mc48u7f6n9lzesm5wpvrq5y8rck9qgyyjpd2vshpv3ww7cp89qv27dl3
+           .ROUTINE second
+                call    ext->bbb
+                ret
+        "#,
+    );
+    // Both modules call their routine through the first entry of their own call table
+    assert_eq!(calls(&first), vec![0]);
+    assert_eq!(calls(&second), vec![0]);
+
+    let modules = modules(vec![("first", first), ("second", second)]);
+    let mut issues = Issues::default();
+    let merged = Module::merge(&modules, &mut issues).unwrap();
+    assert!(!issues.has_errors(), "error(merge): {}", issues);
+
+    // Calls of the merged module refer to the joined call table
+    assert_eq!(merged.imports.count(), 1);
+    assert_eq!(calls(&merged), vec![0, 1]);
+    let mut sites = merged
+        .relocs
+        .iter()
+        .filter_map(|(_, reloc)| match reloc {
+            Reloc::Import(site) => Some(site.pos),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    sites.sort_unstable();
+    assert_eq!(sites, vec![0, 1]);
+}
+
+/// Call table indexes of all `call` instructions of the module
+fn calls(module: &Module) -> Vec<u16> {
+    let mut calls = module
+        .as_static_lib()
+        .disassemble::<Instr>()
+        .unwrap()
+        .into_iter()
+        .filter_map(|instr| match instr {
+            Instr::ControlFlow(ControlFlowOp::Call(site)) => Some(site.pos),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    calls.sort_unstable();
+    calls
+}
+
+#[test]
+fn object_format_version() {
+    let module = compile(
+        r#".ISAE
+                ALU
+           .MAIN
+                ret
+        "#,
+    );
+    let mut data = vec![];
+    module.encode(&mut data).unwrap();
+    assert_eq!(Module::decode(&data[..]).unwrap(), module);
+
+    // Files produced before the format was versioned start directly with the library data
+    assert!(matches!(Module::decode(&data[12..]), Err(ModuleError::WrongMagic { .. })));
+
+    data[10..12].copy_from_slice(&(MODULE_VERSION + 1).to_le_bytes());
+    assert_eq!(
+        Module::decode(&data[..]),
+        Err(ModuleError::UnsupportedVersion(MODULE_VERSION + 1, MODULE_VERSION))
+    );
+}
+
+#[test]
+fn unused_library_dropped() {
+    let main = compile(
+        r#".ISAE
+                ALU
+           .LIBS
+                pedersen alu145mc48u7f6n9lzesm5wpvrq5y8rck9qgyyjpd2vshpv3ww7cp89qv27dl3
+           .MAIN
+                ret
+        "#,
+    );
+    assert_eq!(main.as_static_lib().libs.iter().count(), 1);
+
+    let modules = modules(vec![("main", main)]);
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    let (product, issues) =
+        Module::link_bin(&modules, "test".to_owned(), "test".to_owned(), &mut lib_man).unwrap();
+    assert!(!issues.has_errors(), "error(link): {}", issues);
+    assert_eq!(issues.count_warnings(), 0, "{}", issues);
+
+    let bin = match product {
+        Product::Bin(bin) => bin,
+        Product::Lib(_) => panic!("binary is expected"),
+    };
+    assert_eq!(bin.as_static_lib().libs.iter().count(), 0);
+}
+
+#[test]
+fn var_names_roundtrip() {
+    let module = compile(
+        r#".ISAE
+                ALU
+           .MAIN
+                ret
+           .INPUT
+                $limit: u16 = 1000 "Limit"
+                $data: bytes "Data"
+        "#,
+    );
+    assert_eq!(module.var_index("data"), Some(0));
+    assert_eq!(module.var_index("$limit"), Some(1));
+
+    let mut data = vec![];
+    module.encode(&mut data).unwrap();
+    let decoded = Module::decode(&data[..]).unwrap();
+    assert_eq!(decoded, module);
+
+    // Variable names are kept in a tagged optional section at the end of the file; files without
+    // it leave variables unnamed
+    let names = vec!["data".to_owned(), "limit".to_owned()];
+    let mut names_data = vec![];
+    let len = MaxLenWord::new(&names).encode(&mut names_data).unwrap();
+    let mut section = vec![1u8];
+    section.extend_from_slice(&(len as u32).to_le_bytes());
+    section.extend(names_data);
+    assert!(data.ends_with(&section));
+    let unnamed = Module::decode(&data[..data.len() - section.len()]).unwrap();
+    assert_eq!(unnamed.vars.len(), 2);
+    assert!(unnamed.vars.iter().all(|var| var.name.is_none()));
+    assert_eq!(unnamed.var_index("limit"), None);
+
+    // Sections unknown to this version are skipped
+    let mut extended = data.clone();
+    extended.extend_from_slice(&[0x7F, 3, 0, 0, 0, 1, 2, 3]);
+    assert_eq!(Module::decode(&extended[..]).unwrap(), module);
+    extended.truncate(extended.len() - 1);
+    assert!(Module::decode(&extended[..]).is_err());
+
+    let product = link(&modules(vec![("main", module)]), &mut LibManager::with(vec![]).unwrap());
+    let mut data = vec![];
+    product.encode(&mut data).unwrap();
+    let bin = match Product::decode(&data[..]).unwrap() {
+        Product::Bin(bin) => bin,
+        Product::Lib(_) => panic!("binary is expected"),
+    };
+    assert_eq!(bin.var_index("limit"), Some(1));
+    assert!(bin.to_string().contains("$limit"));
+}
+
+#[test]
+fn code_overflow_stops_merge() {
+    // Each of the modules occupies more than a half of the code segment
+    let module = |name: &str| {
+        compile(&format!(
+            ".ISAE\n                ALU\n.ROUTINE {}\n{}                ret\n",
+            name,
+            "                nop\n".repeat(40_000)
+        ))
+    };
+    let modules = modules(vec![("a", module("a")), ("b", module("b")), ("c", module("c"))]);
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    let (_, issues) =
+        Module::link_lib(&modules, "test".to_owned(), "test".to_owned(), &mut lib_man).unwrap();
+    let errnos = issues.diagnostics().iter().map(|issue| issue.errno).collect::<Vec<_>>();
+    // Only the first module not fitting the segment is reported
+    assert_eq!(errnos, vec![8008], "{}", issues);
+}
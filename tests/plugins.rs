@@ -0,0 +1,163 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use aluasm::ast::{Operator, Program, Statement};
+use aluasm::isa::{Context, ExtOp};
+use aluasm::issues::SemanticError;
+use aluasm::plugins::{CompileCtx, IsaPlugin, IsaTable, Mnemonic, OperandKind};
+use aluasm::source::SourceMap;
+use aluasm::CompilerError;
+use aluvm::data::Step;
+use aluvm::isa::{ArithmeticOp, Instr};
+
+/// Test ISA adding `twice` instruction incrementing register by two
+struct TwicePlugin;
+
+const TWICE_MNEMONICS: &[Mnemonic] =
+    &[Mnemonic::new(Operator::Ext("twice"), "", &[OperandKind::Reg])];
+
+impl IsaPlugin<ExtOp> for TwicePlugin {
+    fn isa_id(&self) -> &'static str { "TWICE" }
+
+    fn mnemonics(&self) -> &'static [Mnemonic] { TWICE_MNEMONICS }
+
+    fn compile<'i>(
+        &self,
+        statement: &'i Statement<'i>,
+        ctx: &mut CompileCtx<'i, '_>,
+    ) -> Result<Instr<ExtOp>, CompilerError> {
+        let reg = statement.reg(0, ctx.issues);
+        let idx = statement.idx(0, ctx.issues);
+        Ok(Instr::Arithmetic(ArithmeticOp::Stp(reg, idx, Step::with(2))))
+    }
+}
+
+const CODE: &str = r#".ISAE
+                ALU
+                TWICE
+.MAIN
+                put     a8[1],5
+                twice   a8[1]
+                put     a8[2],7
+                eq.n    a8[1],a8[2]
+                ret
+"#;
+
+#[test]
+fn custom_plugin() {
+    let sources = SourceMap::with("test", CODE).unwrap();
+    let table = IsaTable::<ExtOp>::default().with(TwicePlugin);
+    assert!(table.isa_ids().contains("TWICE"));
+    assert_eq!(table.isa_id(Operator::Ext("twice")), Some("TWICE"));
+    assert_eq!(table.mnemonic(Operator::Ext("twice")).map(|m| m.arity()), Some(1));
+
+    let (program, issues) = Program::analyze_with(&sources, &table).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (module, issues) = program.compile_with(&table, &mut None).unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+
+    let mut runtime = aluvm::Vm::<Instr<ExtOp>>::new();
+    let program = aluvm::Prog::<Instr<ExtOp>>::new(module.as_static_lib().clone());
+    assert!(runtime.run(&program, &Context::default()));
+}
+
+#[test]
+fn plugin_isa_declaration() {
+    let table = IsaTable::<ExtOp>::default().with(TwicePlugin);
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     a8[1],5
+                twice   a8[1]
+                ret
+"#;
+    let sources = SourceMap::with("test", code).unwrap();
+    let (program, issues) = Program::analyze_with(&sources, &table).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile_with(&table, &mut None).unwrap();
+    assert_eq!(issues.count_errors(), 1, "{}", issues);
+    assert!(issues.to_string().contains("`TWICE`"), "{}", issues);
+}
+
+#[test]
+fn unregistered_plugin() {
+    let sources = SourceMap::with("test", CODE).unwrap();
+    let (_, issues) = Program::analyze(&sources).unwrap();
+    // Both the ISA identifier and the mnemonic are unknown without the plugin
+    assert_eq!(issues.count_errors(), 2);
+
+    let code = r#".ISAE
+                ALU
+                ALURE
+.INPUT
+                $a: u8 = 5 "Value"
+.MAIN
+                read    a8[1],$a
+                ret
+"#;
+    let sources = SourceMap::with("test", code).unwrap();
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile_with(&IsaTable::<ExtOp>::core(), &mut None).unwrap();
+    assert_eq!(issues.count_errors(), 1);
+}
+
+#[test]
+fn operand_excess() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                inc     a8[1],a8[2]
+                ret
+"#;
+    let sources = SourceMap::with("test", code).unwrap();
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    assert_eq!(issues.count_errors(), 1);
+}
+
+#[test]
+fn unsupported_mnemonic() {
+    // `del` is known to the parser but is not implemented by any of the ISA plugins
+    let code = r#".ISAE
+                ALU
+.MAIN
+                del
+                ret
+"#;
+    let sources = SourceMap::with("test", code).unwrap();
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    assert_eq!(issues.count_errors(), 1);
+    assert!(issues
+        .to_string()
+        .contains(&SemanticError::MnemonicUnsupported(Operator::del).to_string()));
+}
+
+#[test]
+fn signature_mismatch() {
+    let code = r#".ISAE
+                ALU
+                TWICE
+.MAIN
+                twice   5
+                twice.s a8[1]
+                twice
+                inc.u   a8[1]
+                rev     a8[1]
+                ret
+"#;
+    let sources = SourceMap::with("test", code).unwrap();
+    let table = IsaTable::<ExtOp>::default().with(TwicePlugin);
+    let (program, issues) = Program::analyze_with(&sources, &table).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    // Each mismatching statement is reported once, without invoking the plugin
+    let (_, issues) = program.compile_with(&table, &mut None).unwrap();
+    assert_eq!(issues.count_errors(), 4, "{}", issues);
+}
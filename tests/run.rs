@@ -0,0 +1,84 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use aluasm::isa::{Context, Instr};
+use aluasm::linker::LibManager;
+use aluasm::product::DyBin;
+use aluasm::RunError;
+use aluvm::Vm;
+use common::{bin, compile};
+
+mod common;
+
+const CODE: &str = r#".ISAE
+                ALU
+                ALURE
+.INPUT
+                $limit: u16 = 1000 "Limit"
+                $value: u16 "Value"
+.MAIN
+                read    a16[1], $limit
+                read    a16[2], $value
+                gt.u    a16[1], a16[2]
+                ret
+"#;
+
+fn run(bin: &DyBin, values: &[(&str, &str)]) -> Result<bool, RunError> {
+    let values = values.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    let inputs = bin.inputs(&values)?;
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    let program = bin.program(&mut lib_man)?;
+    Ok(Vm::<Instr>::new().run(&program, &Context::from(inputs)))
+}
+
+#[test]
+fn inputs_and_defaults() {
+    let bin = bin(compile(CODE));
+    assert!(run(&bin, &[("value", "999")]).unwrap());
+    assert!(run(&bin, &[("value", "0x10")]).unwrap());
+    assert!(!run(&bin, &[("value", "1001")]).unwrap());
+    assert!(run(&bin, &[("value", "1001"), ("limit", "2000")]).unwrap());
+}
+
+#[test]
+fn inputs_invalid() {
+    let bin = bin(compile(CODE));
+    assert_eq!(run(&bin, &[("other", "1")]), Err(RunError::InputUnknown("other".to_owned())));
+    assert_eq!(
+        run(&bin, &[("value", "70000")]),
+        Err(RunError::InputInvalid("value".to_owned(), "70000".to_owned()))
+    );
+    assert_eq!(
+        run(&bin, &[("value", "1.5")]),
+        Err(RunError::InputInvalid("value".to_owned(), "1.5".to_owned()))
+    );
+    assert_eq!(
+        run(&bin, &[("value", "5 5")]),
+        Err(RunError::InputInvalid("value".to_owned(), "5 5".to_owned()))
+    );
+}
+
+#[test]
+fn float_inputs() {
+    let bin = bin(compile(
+        r#".ISAE
+                ALU
+.INPUT
+                $ratio: f32 "Ratio"
+.MAIN
+                ret
+"#,
+    ));
+    let inputs = |value: &str| {
+        bin.inputs(&vec![("ratio".to_owned(), value.to_owned())].into_iter().collect()).unwrap()
+    };
+    // Numbers from JSON and TOML input files are passed as float literals, which must keep
+    // leading zeros of the fraction
+    assert_eq!(inputs("1.05"), inputs("105.0e-2"));
+    assert_eq!(inputs("0.001"), inputs("1.0e-3"));
+    assert_ne!(inputs("1.05"), inputs("1.5"));
+}
@@ -0,0 +1,743 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use std::fs;
+use std::path::PathBuf;
+
+use aluasm::ast::{Operator, Program};
+use aluasm::isa::{Context, ContractState, Inputs, Instr, StateItem};
+use aluasm::issues::{self, Diagnostic, Issues, Severity};
+use aluasm::module::DataType;
+use aluasm::navigator::Symbol;
+use aluasm::source::SourceMap;
+use aluvm::data::{IntLayout, MaybeNumber};
+use amplify::num::u1024;
+
+fn analyze(sources: &SourceMap) -> Issues<issues::Analyze> {
+    let (_, issues) = Program::analyze(sources).unwrap();
+    issues
+}
+
+fn run(sources: &SourceMap) -> bool { run_with_state(sources, ContractState::default()) }
+
+fn run_with_state(sources: &SourceMap, state: ContractState) -> bool {
+    let (program, issues) = Program::analyze(sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (module, issues) = program.compile(&mut None).unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+    let inputs = module.vars.iter().map(|var| var.data.clone()).collect::<Inputs>();
+    let mut runtime = aluvm::Vm::<Instr>::new();
+    let program = aluvm::Prog::<Instr>::new(module.as_static_lib().clone());
+    runtime.run(&program, &Context { inputs, state })
+}
+
+fn source(code: &str) -> SourceMap { SourceMap::with("test", code).unwrap() }
+
+/// Lists errno of each reported issue together with the line and column it points to
+fn reported(diagnostics: Vec<Diagnostic>) -> Vec<(u16, usize, usize)> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let location = diagnostic.location.as_ref().expect("issue without location");
+            (diagnostic.errno, location.line, location.column)
+        })
+        .collect()
+}
+
+fn write_files(dir: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (name, code) in files {
+        fs::write(dir.join(name), code).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn macro_expansion() {
+    let code = r#".ISAE
+                ALU
+.MACRO assert_eq $a, $b
+                eq.n    $a, $b
+                jif     ok
+                fail
+ok:             nop
+.ENDM
+.MAIN
+                put     a8[1],9
+                put     a8[2],9
+                assert_eq a8[1],a8[2]
+                inc     a8[1]
+                assert_eq a8[1],a8[1]
+                ret
+"#;
+    assert!(run(&source(code)));
+
+    let code = r#".ISAE
+                ALU
+.MACRO assert_eq $a, $b
+                eq.n    $a, $b
+                jif     ok
+                fail
+ok:             nop
+.ENDM
+.MAIN
+                put     a8[1],9
+                put     a8[2],8
+                assert_eq a8[1],a8[2]
+                ret
+"#;
+    assert!(!run(&source(code)));
+}
+
+#[test]
+fn macro_errors() {
+    let code = r#".ISAE
+                ALU
+.MACRO inc $a
+                inc     $a
+.ENDM
+.MACRO double $a
+                inc     $a
+                inc     $a
+.ENDM
+.MACRO double $a
+                inc     $a
+.ENDM
+.MAIN
+                double  a8[1],a8[2]
+                ret
+"#;
+    let sources = source(code);
+    let issues = analyze(&sources);
+    assert_eq!(issues.count_errors(), 3, "{}", issues);
+}
+
+#[test]
+fn include() {
+    let dir = write_files("aluasm-include", &[
+        (
+            "common.aluasm",
+            r#".ISAE
+                ALU
+.CONST
+                $nine = 9
+"#,
+        ),
+        (
+            "main.aluasm",
+            r#".INCLUDE "common.aluasm"
+.INCLUDE "common.aluasm"
+.MAIN
+                put     a8[1],$nine
+                put     a8[2],9
+                eq.n    a8[1],a8[2]
+                ret
+"#,
+        ),
+    ]);
+    let sources = SourceMap::load(dir.join("main.aluasm")).unwrap();
+    assert_eq!(sources.files().count(), 2);
+    assert!(run(&sources));
+}
+
+#[test]
+fn include_cycle() {
+    let dir = write_files("aluasm-include-cycle", &[
+        (
+            "a.aluasm",
+            r#".INCLUDE "b.aluasm"
+.MAIN
+                ret
+"#,
+        ),
+        (
+            "b.aluasm",
+            r#".INCLUDE "a.aluasm"
+.ISAE
+                ALU
+"#,
+        ),
+    ]);
+    let sources = SourceMap::load(dir.join("a.aluasm")).unwrap();
+    let issues = analyze(&sources);
+    assert_eq!(issues.count_errors(), 1, "{}", issues);
+    assert!(issues.to_string().contains("b.aluasm:1:1"), "{}", issues);
+}
+
+#[test]
+fn const_expressions() {
+    let code = r#".ISAE
+                ALU
+.CONST
+                $base = 0x10
+                $mask = (1 << 16) - 1
+                $rem = $mask % 7 | 0b100
+.MAIN
+                put     a16[1],$mask
+                put     a16[2],65535
+                eq.n    a16[1],a16[2]
+                jif     next
+                fail
+next:           put     a16[3],$base + $rem * 2
+                put     a16[4],26
+                eq.n    a16[3],a16[4]
+                ret
+"#;
+    assert!(run(&source(code)));
+}
+
+#[test]
+fn const_expression_errors() {
+    let code = r#".ISAE
+                ALU
+.CONST
+                $a = $b + 1
+                $b = 1 / 0
+                $c = 1 << 1024
+.MAIN
+                put     a8[1],$zzz * 2
+                ret
+"#;
+    let sources = source(code);
+    let issues = analyze(&sources);
+    assert_eq!(issues.count_errors(), 4, "{}", issues);
+}
+
+#[test]
+fn string_literals() {
+    let code = r##".ISAE
+                ALU
+.CONST
+                $text = "a\tb\x41\u{e9}"
+                $raw = r#"say "hi""#
+.MAIN
+                put     s16[1],$text
+                put     s16[2],x"61096241c3a9"
+                eq      s16[1],s16[2]
+                jif     raw
+                fail
+raw:            put     s16[3],$raw
+                put     s16[4],"say \"hi\""
+                eq      s16[3],s16[4]
+                jif     chr
+                fail
+chr:            put     s16[5],'\''
+                put     s16[6],x"27"
+                eq      s16[5],s16[6]
+                ret
+"##;
+    assert!(run(&source(code)));
+}
+
+#[test]
+fn string_literal_input() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                ret
+.INPUT
+                $name: str = "\u{48}ello" "Greeting"
+                $key: bytes = x"DEADbeef" "Key"
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (module, issues) = program.compile(&mut None).unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+    let defaults = module.vars.iter().map(|var| var.data.clone()).collect::<Vec<_>>();
+    assert!(defaults.contains(&DataType::ByteStr(Some(b"Hello".to_vec()))));
+    assert!(defaults.contains(&DataType::ByteStr(Some(vec![0xde, 0xad, 0xbe, 0xef]))));
+}
+
+#[test]
+fn string_literal_errors() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     s16[1],"\q"
+                put     s16[2],"\xff"
+                put     s16[3],x"abc"
+                put     s16[4],'\u{e9}'
+                ret
+"#;
+    let sources = source(code);
+    let issues = analyze(&sources);
+    assert_eq!(issues.count_errors(), 4, "{}", issues);
+}
+
+#[test]
+fn float_input_defaults() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                ret
+.INPUT
+                $a: f16b = 1.5 "BFloat16"
+                $b: f16 = 0.5 "Half"
+                $c: f32 = 1.25 "Single"
+                $d: f64 = 2.5 "Double"
+                $e: f80 = 3.5 "X87 extended"
+                $f: f128 = 4.5 "Quad"
+                $g: f256 = 5.5 "Octuple"
+                $h: apfloat "Tapered"
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (module, issues) = program.compile(&mut None).unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+    assert_eq!(issues.count_warnings(), 0, "{}", issues);
+    assert_eq!(module.vars.len(), 8);
+}
+
+#[test]
+fn float_input_default_errors() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                ret
+.INPUT
+                $overflow: f16 = 1.0e5 "Overflows half float"
+                $inexact: f32 = 16777217.0 "Rounded"
+                $tapered: apfloat = 1.0 "No defaults"
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    assert_eq!(issues.count_errors(), 2, "{}", issues);
+    assert_eq!(issues.count_warnings(), 1, "{}", issues);
+}
+
+#[test]
+fn signed_typed_literals() {
+    let code = r#".ISAE
+                ALU
+.CONST
+                $minus_three = -3i8
+.MAIN
+                put     a8[1],$minus_three
+                put     a8[2],253
+                eq.n    a8[1],a8[2]
+                jif     step
+                fail
+step:           put     a16[1],5u16
+                add     a16[1],-2
+                put     a16[2],0x3u16
+                eq.n    a16[1],a16[2]
+                jif     float
+                fail
+float:          put     f32[1],-1.5e-1f32
+                put     f32[2],-0.15
+                eq.e    f32[1],f32[2]
+                ret
+"#;
+    assert!(run(&source(code)));
+}
+
+#[test]
+fn integer_float_literals() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     f32[1],5
+                put     f32[2],5.0
+                eq.e    f32[1],f32[2]
+                ret
+"#;
+    assert!(run(&source(code)));
+
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     f16[1],100000
+                put     f32[2],16777217
+                put     f32[3],5u8
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    // LiteralOutOfRange, LiteralTypeMismatch, LiteralInexact
+    assert_eq!(
+        reported(issues.diagnostics()),
+        vec![(4033, 4, 32), (4034, 6, 32), (4047, 5, 32)],
+        "{}",
+        issues
+    );
+}
+
+#[test]
+fn float_fraction_leading_zeros() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     f32[1],1.05
+                put     f32[2],105.0e-2
+                eq.e    f32[1],f32[2]
+                jif     small
+                fail
+small:          put     f64[1],0.001
+                put     f64[2],1.0e-3
+                eq.e    f64[1],f64[2]
+                jif     differs
+                fail
+differs:        put     f32[3],1.5
+                eq.e    f32[1],f32[3]
+                jif     wrong
+                succ
+                ret
+wrong:          fail
+"#;
+    assert!(run(&source(code)));
+}
+
+#[test]
+fn literal_range_errors() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     a8[1],300u8
+                put     a8[2],-1u8
+                ret
+"#;
+    let sources = source(code);
+    let issues = analyze(&sources);
+    assert_eq!(issues.count_errors(), 2, "{}", issues);
+
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     a16[1],70000
+                put     a8[1],-129
+                put     a8[2],5u16
+                put     f16[1],1.0e5
+                put     f32[1],1.0f64
+                put     a32[1],1.5
+                ret
+.INPUT
+                $small: u8 = 256 "Out of range"
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    assert_eq!(issues.count_errors(), 7, "{}", issues);
+}
+
+#[test]
+fn read_input() {
+    let code = r#".ISAE
+                ALU
+                ALURE
+.MAIN
+                read    a16[1],$limit
+                put     a16[2],1000
+                eq.n    a16[1],a16[2]
+                jif     bytes
+                fail
+bytes:          read    s16[1],$data
+                put     s16[2],x"cafe"
+                eq      s16[1],s16[2]
+                ret
+.INPUT
+                $limit: u16 = 1000 "Limit"
+                $data: bytes = x"cafe" "Data"
+"#;
+    assert!(run(&source(code)));
+}
+
+#[test]
+fn read_input_errors() {
+    let code = r#".ISAE
+                ALU
+                ALURE
+.MAIN
+                read    a8[1],$limit
+                read    f32[1],$data
+                read    s16[1],$unknown
+                ret
+.INPUT
+                $limit: u16 = 1000 "Limit"
+                $data: bytes "Data"
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    assert_eq!(issues.count_errors(), 3, "{}", issues);
+}
+
+#[test]
+fn rgb_state_access() {
+    let code = r#".ISAE
+                ALU
+                RGB
+.MAIN
+                scn.i   1,a16[1]
+                put     a16[2],2
+                eq.n    a16[1],a16[2]
+                jif     load
+                fail
+load:           put     a16[3],1
+                pld.o   7,a16[3],r512[1],r512[2]
+                put     r512[3],0xc0ffee
+                eq.n    r512[1],r512[3]
+                jif     missing
+                fail
+missing:        scn.o   9,a16[4]
+                ifz     a16[4]
+                ret
+"#;
+    let num = |val: u64| {
+        let mut num = MaybeNumber::from(u1024::from(val));
+        num.reshape(IntLayout::unsigned(64).into());
+        num
+    };
+    let item = |ty: u16, val: u64| StateItem { ty, data: [num(val), num(val + 1)] };
+    let state = ContractState {
+        inputs: vec![item(1, 0), item(2, 0), item(1, 0)],
+        outputs: vec![item(7, 0), item(7, 0xc0ffee)],
+    };
+    let sources = source(code);
+    assert!(run_with_state(&sources, state.clone()));
+    assert!(!run_with_state(&sources, ContractState { outputs: vec![], ..state }));
+}
+
+#[test]
+fn rgb_state_access_errors() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                scn.i   1,a16[1]
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    assert_eq!(issues.count_errors(), 1, "{}", issues);
+
+    let code = r#".ISAE
+                ALU
+                RGB
+.MAIN
+                scn     1,a16[1]
+                scn.x   1,a16[1]
+                pld.i   70000,a16[1],r512[1],r512[2]
+                pld.o   1,a8[1],r256[1],r512[2]
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    assert_eq!(issues.count_errors(), 5, "{}", issues);
+}
+
+#[test]
+fn isae_validation() {
+    let code = r#".ISAE
+                ALU
+                SECP256
+.MAIN
+                sha2    s16[1],r256[2]
+                ripemd  s16[1],r160[2]
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    // IsaeNotDeclared(BPDIGEST) for both hashing statements, IsaeNotUsed(SECP256)
+    assert_eq!(
+        reported(issues.diagnostics()),
+        vec![(4038, 5, 17), (4038, 6, 17), (4041, 3, 17)],
+        "{}",
+        issues
+    );
+}
+
+#[test]
+fn call_errors() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                call    5
+                exec    routine_name
+                call
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    // OperandWrongType at `5` and `routine_name`, OperandMissed at the last `call`
+    assert_eq!(
+        reported(issues.diagnostics()),
+        vec![(4001, 4, 25), (4001, 5, 25), (4003, 6, 17)],
+        "{}",
+        issues
+    );
+}
+
+#[test]
+fn extern_routine_warning() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                routine elsewhere
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (module, issues) = program.compile(&mut None).unwrap();
+    assert!(!issues.has_errors(), "error(compile): {}", issues);
+    assert_eq!(module.relocs.len(), 1);
+
+    let diagnostics = issues.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{}", issues);
+    assert_eq!(diagnostics[0].errno, 4046);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    let location = diagnostics[0].location.as_ref().unwrap();
+    assert_eq!(&code[location.range.clone()], "elsewhere");
+}
+
+#[test]
+fn control_flow_verification() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                put     a8[1],1
+                jif     missing
+unused:         inc     a8[1]
+                jmp     skip
+                dec     a8[1]
+skip:           nop
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+    // LabelUnknown at `missing`, LabelNotUsed at `unused`, CodeUnreachable at `dec` and
+    // RoutineNoReturn at the last statement
+    assert_eq!(
+        reported(issues.diagnostics()),
+        vec![(4042, 5, 25), (4043, 6, 1), (4045, 8, 17), (4044, 9, 1)],
+        "{}",
+        issues
+    );
+}
+
+#[test]
+fn unused_symbols() {
+    let code = r#".ISAE
+                ALU
+.LIBS
+                pedersen alu145mc48u7f6n9lzesm5wpvrq5y8rck9qgyyjpd2vshpv3ww7cp89qv27dl3
+.CONST
+                $used = 5
+                $derived = $used + 1
+                $not_used = "some string"
+.MAIN
+                put     a8[1],$derived
+                routine helper
+                ret
+.ROUTINE helper
+                ret
+.ROUTINE orphan
+                ret
+.INPUT
+                $unread: u8 "Never read"
+"#;
+    let sources = source(code);
+    let issues = analyze(&sources);
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    // ConstNotUsed($not_used), VarNotUsed($unread), LibNotUsed(pedersen), RoutineNotUsed(orphan)
+    assert_eq!(
+        reported(issues.diagnostics()),
+        vec![(2028, 8, 17), (2029, 18, 17), (2030, 4, 17), (2031, 15, 1)],
+        "{}",
+        issues
+    );
+}
+
+#[test]
+fn structured_diagnostics() {
+    let code = r#".ISAE
+                ALU
+.MAIN
+                jmp     nowhere
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let (_, issues) = program.compile(&mut None).unwrap();
+
+    let diagnostics = issues.diagnostics();
+    assert_eq!(diagnostics.len(), issues.count_errors() + issues.count_warnings());
+    let error = &diagnostics[0];
+    assert_eq!(error.errno, 4042);
+    assert_eq!(error.severity, Severity::Error);
+    assert_eq!(error.stage, "Compile");
+    assert_eq!(error.message, "jump to label `nowhere` which is not defined in `.MAIN` routine");
+    let location = error.location.as_ref().unwrap();
+    assert_eq!(location.file.as_deref(), Some("test"));
+    assert_eq!(location.range, 56..63);
+    assert_eq!((location.line, location.column), (4, 25));
+    assert_eq!(location.expansion, None);
+
+    #[cfg(feature = "json")]
+    assert_eq!(
+        error.to_json(),
+        "{\"column\":25,\"errno\":4042,\"expansion\":null,\"file\":\"test\",\"line\":4,\
+         \"message\":\"jump to label `nowhere` which is not defined in `.MAIN` routine\",\
+         \"range\":[56,63],\"severity\":\"error\",\"stage\":\"Compile\"}"
+    );
+    assert!(diagnostics[1..].iter().all(|warning| warning.severity == Severity::Warning));
+}
+
+#[test]
+fn symbol_navigation() {
+    let code = r#".ISAE
+                ALU
+.CONST
+                $five = 5
+.MAIN
+                put     a8[1],$five
+                routine helper
+again:          jmp     again
+.ROUTINE helper
+                ret
+"#;
+    let sources = source(code);
+    let (program, issues) = Program::analyze(&sources).unwrap();
+    assert!(!issues.has_errors(), "error(analyze): {}", issues);
+    let at = |pat: &str| code.find(pat).unwrap();
+
+    let (symbol, span) = program.symbol_at(SourceMap::ROOT, at("$five\n") + 1).unwrap();
+    assert_eq!(symbol, Symbol::Const("$five".to_owned()));
+    assert_eq!(span.as_str(), "$five");
+    let definition = program.definition(&symbol).unwrap();
+    assert_eq!(definition.start(), at("$five = 5"));
+
+    let (symbol, _) = program.symbol_at(SourceMap::ROOT, at("helper\n")).unwrap();
+    assert_eq!(symbol, Symbol::Routine("helper".to_owned()));
+    assert_eq!(program.definition(&symbol).unwrap().start(), at(".ROUTINE helper"));
+
+    let (symbol, _) = program.symbol_at(SourceMap::ROOT, at("again\n")).unwrap();
+    assert_eq!(symbol, Symbol::Label { label: "again".to_owned(), routine: ".MAIN".to_owned() });
+    assert_eq!(program.definition(&symbol).unwrap().start(), at("again:"));
+
+    let (_, statement) = program.statement_at(SourceMap::ROOT, at("put")).unwrap();
+    assert_eq!(statement.operator.0, Operator::put);
+    assert!(program.symbol_at(SourceMap::ROOT, at("a8[1]")).is_none());
+}
@@ -0,0 +1,141 @@
+// AluVM Assembler
+// To find more on AluVM please check <https://www.aluvm.org>
+//
+// Designed & written in 2021 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+// for Pandora Core AG
+
+use aluasm::issues::SyntaxError;
+use aluasm::linker::LibManager;
+use aluasm::module::Module;
+use aluasm::product::DyLib;
+use aluasm::source::SourceMap;
+use aluasm::tester::TestResult;
+use aluasm::RunError;
+use common::{analyze, compile_tests, link_lib, modules};
+
+mod common;
+
+const CODE: &str = r#".ISAE
+                ALU
+.ROUTINE double
+                add     a8[1], a8[1]
+                ret
+.ROUTINE check
+                put     a8[9], 4
+                eq.n    a8[1], a8[9]
+                ret
+.TEST doubles
+                put     a8[1], 2
+                routine double
+                put     a8[2], 4
+                eq.n    a8[1], a8[2]
+                ret
+.TEST wrong
+                put     a8[1], 3
+                routine double
+                put     a8[2], 4
+                eq.n    a8[1], a8[2]
+                put     a8[3], 1
+                ret
+.TEST nested
+                put     a8[1], 3
+                routine check
+                ret
+.TEST inverted
+                put     a8[1], 3
+                routine check
+                stinv
+                ret
+"#;
+
+fn lib(code: &str) -> (Module, DyLib) {
+    let module = compile_tests(code);
+    let lib = link_lib(&modules(vec![("test", module.clone())]), "test");
+    (module, lib)
+}
+
+#[test]
+fn tests_excluded_from_build() {
+    let sources = SourceMap::with("test", CODE).unwrap();
+    let program = analyze(&sources);
+    assert_eq!(program.tests.keys().collect::<Vec<_>>(), vec![
+        "doubles", "inverted", "nested", "wrong"
+    ]);
+
+    let (module, _) = program.compile(&mut None).unwrap();
+    assert_eq!(module.exports.keys().collect::<Vec<_>>(), vec!["check", "double"]);
+
+    let (module, _) = lib(CODE);
+    assert!(module.exports.contains_key(".TEST doubles"));
+    assert!(module.debug.is_some());
+}
+
+#[test]
+fn run_tests() {
+    let (_, lib) = lib(CODE);
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    assert_eq!(lib.tests().collect::<Vec<_>>(), vec!["doubles", "inverted", "nested", "wrong"]);
+
+    assert_eq!(lib.run_test("doubles", &mut lib_man), Ok(TestResult::Passed));
+
+    // Test fails if `st0` is false at its end, reporting the statement which has reset it
+    match lib.run_test("wrong", &mut lib_man).unwrap() {
+        TestResult::Assertion { statement } => assert_eq!(statement.to_string(), "test:20:17"),
+        result => panic!("assertion failure is expected, got {:?}", result),
+    }
+
+    // Failure inside a called routine is attributed to the calling test statement
+    match lib.run_test("nested", &mut lib_man).unwrap() {
+        TestResult::Assertion { statement } => assert_eq!(statement.to_string(), "test:25:17"),
+        result => panic!("assertion failure is expected, got {:?}", result),
+    }
+
+    // `st0` may be reset in the middle of the test, checking that a routine fails
+    assert_eq!(lib.run_test("inverted", &mut lib_man), Ok(TestResult::Passed));
+
+    assert_eq!(
+        lib.run_test("absent", &mut lib_man),
+        Err(RunError::TestUnknown("absent".to_owned()))
+    );
+}
+
+#[test]
+fn endless_test() {
+    let (_, lib) = lib(r#".ISAE
+                ALU
+.TEST endless
+                put     a8[1], 1
+loop:           inc     a8[1]
+                jmp     loop
+"#);
+    let mut lib_man = LibManager::with(vec![]).unwrap();
+    match lib.run_test("endless", &mut lib_man).unwrap() {
+        TestResult::StepLimit { statement } => assert_eq!(statement.to_string(), "test:6:17"),
+        result => panic!("step limit failure is expected, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_name_reuse() {
+    let sources = SourceMap::with(
+        "test",
+        r#".ISAE
+                ALU
+.TEST same
+                ret
+.TEST same
+                ret
+.ROUTINE same
+                ret
+"#,
+    )
+    .unwrap();
+    let (program, issues) = aluasm::ast::Program::analyze(&sources).unwrap();
+    assert_eq!(issues.count_errors(), 1);
+    assert!(issues
+        .to_string()
+        .contains(&SyntaxError::TestNameReuse("same".to_owned()).to_string()));
+    assert!(program.routines.contains_key("same"));
+    assert!(program.tests.contains_key("same"));
+}